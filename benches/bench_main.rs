@@ -2,6 +2,9 @@ use criterion::BenchmarkId;
 use criterion::{criterion_group, criterion_main, Criterion};
 
 use std::fs::File;
+use std::io::Cursor;
+
+use mp4::{AacConfig, Bytes, Mp4Config, Mp4Reader, Mp4Sample, Mp4Writer, TrackConfig};
 
 fn read_mp4(filename: &str) -> u64 {
     let f = File::open(filename).unwrap();
@@ -10,6 +13,41 @@ fn read_mp4(filename: &str) -> u64 {
     m.size()
 }
 
+fn write_mp4(sample_count: u32) -> Vec<u8> {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    writer
+        .add_track(&TrackConfig::from(AacConfig::default()))
+        .unwrap();
+    for i in 0..sample_count {
+        let sample = Mp4Sample {
+            start_time: i as u64 * 1024,
+            duration: 1024,
+            rendering_offset: 0,
+            is_sync: true,
+            flags: None,
+            sample_description_index: 1,
+            bytes: Bytes::from(vec![0; 1 + i as usize % 16]),
+        };
+        writer.write_sample(1, &sample).unwrap();
+    }
+    writer.write_end().unwrap();
+    writer.into_writer().into_inner()
+}
+
+fn read_samples(mp4: &mut Mp4Reader<Cursor<Vec<u8>>>) -> usize {
+    let mut size = 0;
+    for sample_id in 1..=mp4.sample_count(1).unwrap() {
+        size += mp4.read_sample(1, sample_id).unwrap().unwrap().bytes.len();
+    }
+    size
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let filename = "tests/samples/minimal.mp4";
 
@@ -20,6 +58,16 @@ fn criterion_benchmark(c: &mut Criterion) {
             b.iter(|| read_mp4(s));
         },
     );
+
+    let data = write_mp4(10_000);
+    let size = data.len() as u64;
+    let mut mp4 = Mp4Reader::read_header(Cursor::new(data), size).unwrap();
+    c.bench_function("read_samples", |b| b.iter(|| read_samples(&mut mp4)));
+
+    mp4.build_sample_index().unwrap();
+    c.bench_function("read_samples_indexed", |b| {
+        b.iter(|| read_samples(&mut mp4))
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
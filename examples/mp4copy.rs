@@ -5,8 +5,8 @@ use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
 
 use mp4::{
-    AacConfig, AvcConfig, HevcConfig, MediaConfig, MediaType, Mp4Config, Result, TrackConfig,
-    TtxtConfig, Vp9Config,
+    AacConfig, AvcConfig, HevcConfig, HevcParameterSets, MediaConfig, MediaType, Mp4Config, Result,
+    TrackConfig, TtxtConfig, Vp9Config,
 };
 
 fn main() {
@@ -50,20 +50,32 @@ fn copy<P: AsRef<Path>>(src_filename: &P, dst_filename: &P) -> Result<()> {
                 seq_param_set: track.sequence_parameter_set()?.to_vec(),
                 pic_param_set: track.picture_parameter_set()?.to_vec(),
             }),
+            // The parameter sets may be in the samples instead.
             MediaType::H265 => MediaConfig::HevcConfig(HevcConfig {
                 width: track.width(),
                 height: track.height(),
+                video_param_set: track.video_parameter_set().unwrap_or_default().to_vec(),
+                seq_param_set: track.sequence_parameter_set().unwrap_or_default().to_vec(),
+                pic_param_set: track.picture_parameter_set().unwrap_or_default().to_vec(),
+                parameter_sets: if track.trak.mdia.minf.stbl.stsd.hvc1().is_some() {
+                    HevcParameterSets::OutOfBand
+                } else {
+                    HevcParameterSets::InBand
+                },
             }),
             MediaType::VP9 => MediaConfig::Vp9Config(Vp9Config {
                 width: track.width(),
                 height: track.height(),
             }),
+            MediaType::AV1 => MediaConfig::Av1Config(track.av1_config()?),
             MediaType::AAC => MediaConfig::AacConfig(AacConfig {
                 bitrate: track.bitrate(),
                 profile: track.audio_profile()?,
                 freq_index: track.sample_freq_index()?,
                 chan_conf: track.channel_config()?,
             }),
+            MediaType::OPUS => MediaConfig::OpusConfig(track.opus_config()?),
+            MediaType::FLAC => MediaConfig::FlacConfig(track.flac_config()?),
             MediaType::TTXT => MediaConfig::TtxtConfig(TtxtConfig {}),
         };
 
@@ -66,7 +66,7 @@ fn info<P: AsRef<Path>>(filename: &P) -> Result<()> {
 }
 
 fn video_info(track: &Mp4Track) -> Result<String> {
-    if track.trak.mdia.minf.stbl.stsd.avc1.is_some() {
+    if track.trak.mdia.minf.stbl.stsd.avc1().is_some() {
         Ok(format!(
             "{} ({}) ({:?}), {}x{}, {} kb/s, {:.2} fps",
             track.media_type()?,
@@ -91,7 +91,7 @@ fn video_info(track: &Mp4Track) -> Result<String> {
 }
 
 fn audio_info(track: &Mp4Track) -> Result<String> {
-    if let Some(ref mp4a) = track.trak.mdia.minf.stbl.stsd.mp4a {
+    if let Some(mp4a) = track.trak.mdia.minf.stbl.stsd.mp4a() {
         if mp4a.esds.is_some() {
             let profile = match track.audio_profile() {
                 Ok(val) => val.to_string(),
@@ -126,7 +126,7 @@ fn audio_info(track: &Mp4Track) -> Result<String> {
 }
 
 fn subtitle_info(track: &Mp4Track) -> Result<String> {
-    if track.trak.mdia.minf.stbl.stsd.tx3g.is_some() {
+    if track.trak.mdia.minf.stbl.stsd.tx3g().is_some() {
         Ok(format!("{} ({:?})", track.media_type()?, track.box_type()?,))
     } else {
         Err(Error::InvalidData("tx3g box not found"))
@@ -0,0 +1,51 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use mp4::{Mp4Limits, Mp4Reader, Mp4ReaderConfig, SampleOrder};
+
+// Low enough that inputs can't make the reader allocate past the memory
+// limit of the fuzzer.
+const LIMITS: Mp4Limits = Mp4Limits {
+    max_box_size: 1 << 24,
+    max_table_entries: 1 << 16,
+    max_sample_size: 1 << 20,
+    max_depth: 16,
+};
+
+fuzz_target!(|data: &[u8]| {
+    for &lenient in [false, true].iter() {
+        let config = Mp4ReaderConfig {
+            lenient,
+            limits: LIMITS,
+            ..Default::default()
+        };
+        let reader = Cursor::new(data);
+        let mut mp4 = match Mp4Reader::read_header_with_config(reader, data.len() as u64, &config) {
+            Ok(mp4) => mp4,
+            Err(_) => continue,
+        };
+
+        let _ = mp4.duration();
+        let mut track_ids: Vec<u32> = mp4.tracks().keys().copied().collect();
+        track_ids.sort_unstable();
+        for track_id in track_ids {
+            let track = &mp4.tracks()[&track_id];
+            let _ = track.duration();
+            let _ = track.frame_rate();
+            let _ = track.bitrate();
+            let _ = track.trimmed_start();
+            let _ = track.trimmed_end();
+            for sample_id in 1..=track.sample_count().min(64) {
+                let _ = track.sample_presentation_time(sample_id);
+            }
+            if let Ok(samples) = mp4.samples(track_id) {
+                samples.take(64).for_each(drop);
+            }
+        }
+        if let Ok(samples) = mp4.interleaved_samples(SampleOrder::DecodeTime) {
+            samples.take(64).for_each(drop);
+        }
+    }
+});
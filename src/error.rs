@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 use crate::mp4box::BoxType;
+use crate::BoxPath;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -26,4 +27,53 @@ pub enum Error {
     EntryInTrunNotFound(u32, BoxType, u32),
     #[error("{0} version {1} is not supported")]
     UnsupportedBoxVersion(BoxType, u8),
+    #[error("trak[{0}].sample[{1}] extends past the end of the file")]
+    TruncatedSample(u32, u32),
+    #[error("{0}")]
+    Malformed(String),
+    #[error("{0} {1} over the limit of {2}, see Mp4Limits")]
+    LimitExceeded(&'static str, u64, u64),
+
+    /// An error while parsing a box, with the path of the box and its offset
+    /// in the file. [`Error::root`] gives the error itself.
+    #[error("{path} at offset {offset}: {source}")]
+    InBox {
+        path: BoxPath,
+        offset: u64,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// The error without the box context of `Error::InBox`.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::InBox { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// Add the box at `offset` to the path of an error propagating to the
+    /// parent of the box.
+    pub(crate) fn in_box(self, name: BoxType, index: u32, offset: u64) -> Error {
+        match self {
+            Error::InBox {
+                mut path,
+                offset,
+                source,
+            } => {
+                path.0.insert(0, (name, index));
+                Error::InBox {
+                    path,
+                    offset,
+                    source,
+                }
+            }
+            err => Error::InBox {
+                path: BoxPath(vec![(name, index)]),
+                offset,
+                source: Box::new(err),
+            },
+        }
+    }
 }
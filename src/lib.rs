@@ -80,10 +80,19 @@ mod mp4box;
 pub use mp4box::*;
 
 mod track;
-pub use track::{Mp4Track, TrackConfig};
+pub use track::{Mp4Track, TrackConfig, TrackEdit};
 
 mod reader;
-pub use reader::Mp4Reader;
+pub use reader::{Mp4Limits, Mp4Reader, Mp4ReaderConfig};
+
+mod samples;
+pub use samples::{InterleavedSamples, SampleOrder, Samples};
+
+mod parser;
+pub use parser::{Mp4Event, Mp4Parser};
+
+mod stream;
+pub use stream::{Mp4Fragment, Mp4StreamReader};
 
 mod writer;
 pub use writer::{Mp4Config, Mp4Writer};
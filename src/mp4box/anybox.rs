@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+
+use crate::mp4box::av01::{Av01Box, Av1CBox};
+use crate::mp4box::avc1::{Avc1Box, AvcCBox};
+use crate::mp4box::co64::Co64Box;
+use crate::mp4box::ctts::CttsBox;
+use crate::mp4box::data::DataBox;
+use crate::mp4box::dinf::{DinfBox, DrefBox, UrlBox};
+use crate::mp4box::edts::EdtsBox;
+use crate::mp4box::elst::ElstBox;
+use crate::mp4box::flac::{DflaBox, FlacBox};
+use crate::mp4box::hdlr::HdlrBox;
+use crate::mp4box::hev1::{Hev1Box, Hvc1Box, HvcCBox};
+use crate::mp4box::ilst::{item_box_type, IlstBox, IlstItemBox};
+use crate::mp4box::mdhd::MdhdBox;
+use crate::mp4box::mdia::MdiaBox;
+use crate::mp4box::mehd::MehdBox;
+use crate::mp4box::meta::MetaBox;
+use crate::mp4box::mfhd::MfhdBox;
+use crate::mp4box::minf::MinfBox;
+use crate::mp4box::mp4a::{EsdsBox, Mp4aBox};
+use crate::mp4box::mvex::MvexBox;
+use crate::mp4box::mvhd::MvhdBox;
+use crate::mp4box::opus::{DopsBox, OpusBox};
+use crate::mp4box::smhd::SmhdBox;
+use crate::mp4box::stbl::StblBox;
+use crate::mp4box::stco::StcoBox;
+use crate::mp4box::stsc::StscBox;
+use crate::mp4box::stsd::StsdBox;
+use crate::mp4box::stss::StssBox;
+use crate::mp4box::stsz::StszBox;
+use crate::mp4box::stts::SttsBox;
+use crate::mp4box::tfdt::TfdtBox;
+use crate::mp4box::tfhd::TfhdBox;
+use crate::mp4box::tkhd::TkhdBox;
+use crate::mp4box::traf::TrafBox;
+use crate::mp4box::trak::TrakBox;
+use crate::mp4box::trex::TrexBox;
+use crate::mp4box::trun::TrunBox;
+use crate::mp4box::tx3g::Tx3gBox;
+use crate::mp4box::udta::UdtaBox;
+use crate::mp4box::vmhd::VmhdBox;
+use crate::mp4box::vp09::Vp09Box;
+use crate::mp4box::vpcc::VpccBox;
+use crate::mp4box::*;
+
+macro_rules! any_box {
+    ($( $variant:ident($ty:ty) ),* $(,)?) => {
+        /// A box of any type, borrowed from the parsed boxes, for going over a
+        /// tree of boxes without knowing their types, see [`AnyBox::walk`].
+        #[derive(Debug, Clone, Copy)]
+        pub enum AnyBox<'a> {
+            $( $variant(&'a $ty), )*
+
+            /// An item of an ilst box, whose type is given by its key.
+            IlstItem(&'a MetadataKey, &'a IlstItemBox),
+
+            /// A box of a type which isn't parsed, with its payload.
+            Unknown(BoxType, &'a [u8]),
+
+            /// A top-level box whose payload isn't read, such as mdat, with
+            /// its size.
+            Skipped(BoxType, u64),
+        }
+
+        impl Mp4Box for AnyBox<'_> {
+            fn box_type(&self) -> BoxType {
+                match *self {
+                    $( AnyBox::$variant(b) => b.box_type(), )*
+                    AnyBox::IlstItem(key, _) => item_box_type(key),
+                    AnyBox::Unknown(name, _) | AnyBox::Skipped(name, _) => name,
+                }
+            }
+
+            fn box_size(&self) -> u64 {
+                match *self {
+                    $( AnyBox::$variant(b) => b.box_size(), )*
+                    AnyBox::IlstItem(_, item) => item.get_size(),
+                    AnyBox::Unknown(_, data) => HEADER_SIZE + data.len() as u64,
+                    AnyBox::Skipped(_, size) => size,
+                }
+            }
+
+            fn to_json(&self) -> Result<String> {
+                match *self {
+                    $( AnyBox::$variant(b) => b.to_json(), )*
+                    AnyBox::IlstItem(_, item) => Ok(serde_json::to_string(item).unwrap()),
+                    AnyBox::Unknown(..) | AnyBox::Skipped(..) => Ok("{}".to_string()),
+                }
+            }
+
+            fn summary(&self) -> Result<String> {
+                match *self {
+                    $( AnyBox::$variant(b) => Mp4Box::summary(b), )*
+                    AnyBox::IlstItem(_, item) => item.data.summary(),
+                    AnyBox::Unknown(_, data) => Ok(format!("data_len={}", data.len())),
+                    AnyBox::Skipped(..) => Ok(String::new()),
+                }
+            }
+        }
+    };
+}
+
+any_box! {
+    Ftyp(FtypBox),
+    Moov(MoovBox),
+    Mvhd(MvhdBox),
+    Mvex(MvexBox),
+    Mehd(MehdBox),
+    Trex(TrexBox),
+    Trak(TrakBox),
+    Tkhd(TkhdBox),
+    Edts(EdtsBox),
+    Elst(ElstBox),
+    Mdia(MdiaBox),
+    Mdhd(MdhdBox),
+    Hdlr(HdlrBox),
+    Minf(MinfBox),
+    Vmhd(VmhdBox),
+    Smhd(SmhdBox),
+    Dinf(DinfBox),
+    Dref(DrefBox),
+    Url(UrlBox),
+    Stbl(StblBox),
+    Stsd(StsdBox),
+    Avc1(Avc1Box),
+    AvcC(AvcCBox),
+    Hev1(Hev1Box),
+    Hvc1(Hvc1Box),
+    HvcC(HvcCBox),
+    Vp09(Vp09Box),
+    Vpcc(VpccBox),
+    Av01(Av01Box),
+    Av1C(Av1CBox),
+    Mp4a(Mp4aBox),
+    Esds(EsdsBox),
+    Opus(OpusBox),
+    Dops(DopsBox),
+    Flac(FlacBox),
+    Dfla(DflaBox),
+    Tx3g(Tx3gBox),
+    Stts(SttsBox),
+    Ctts(CttsBox),
+    Stss(StssBox),
+    Stsc(StscBox),
+    Stsz(StszBox),
+    Stco(StcoBox),
+    Co64(Co64Box),
+    Udta(UdtaBox),
+    Meta(MetaBox),
+    Ilst(IlstBox),
+    Data(DataBox),
+    Moof(MoofBox),
+    Mfhd(MfhdBox),
+    Traf(TrafBox),
+    Tfhd(TfhdBox),
+    Tfdt(TfdtBox),
+    Trun(TrunBox),
+    Emsg(EmsgBox),
+}
+
+impl<'a> AnyBox<'a> {
+    /// Child boxes, in the order they are written.
+    pub fn children(&self) -> Vec<AnyBox<'a>> {
+        let mut children = Vec::new();
+        let unknown_boxes: &[UnknownBox] = match *self {
+            AnyBox::Moov(moov) => {
+                children.push(AnyBox::Mvhd(&moov.mvhd));
+                children.extend(moov.traks.iter().map(AnyBox::Trak));
+                children.extend(moov.mvex.as_ref().map(AnyBox::Mvex));
+                children.extend(moov.meta.as_ref().map(AnyBox::Meta));
+                children.extend(moov.udta.as_ref().map(AnyBox::Udta));
+                &moov.unknown_boxes
+            }
+            AnyBox::Mvex(mvex) => {
+                children.extend(mvex.mehd.as_ref().map(AnyBox::Mehd));
+                children.extend(mvex.trexs.iter().map(AnyBox::Trex));
+                &mvex.unknown_boxes
+            }
+            AnyBox::Trak(trak) => {
+                children.push(AnyBox::Tkhd(&trak.tkhd));
+                children.extend(trak.edts.as_ref().map(AnyBox::Edts));
+                children.extend(trak.meta.as_ref().map(AnyBox::Meta));
+                children.push(AnyBox::Mdia(&trak.mdia));
+                &trak.unknown_boxes
+            }
+            AnyBox::Edts(edts) => {
+                children.extend(edts.elst.as_ref().map(AnyBox::Elst));
+                &edts.unknown_boxes
+            }
+            AnyBox::Mdia(mdia) => {
+                children.push(AnyBox::Mdhd(&mdia.mdhd));
+                children.push(AnyBox::Hdlr(&mdia.hdlr));
+                children.push(AnyBox::Minf(&mdia.minf));
+                &mdia.unknown_boxes
+            }
+            AnyBox::Minf(minf) => {
+                children.extend(minf.vmhd.as_ref().map(AnyBox::Vmhd));
+                children.extend(minf.smhd.as_ref().map(AnyBox::Smhd));
+                children.push(AnyBox::Dinf(&minf.dinf));
+                children.push(AnyBox::Stbl(&minf.stbl));
+                &minf.unknown_boxes
+            }
+            AnyBox::Dinf(dinf) => {
+                children.push(AnyBox::Dref(&dinf.dref));
+                &dinf.unknown_boxes
+            }
+            AnyBox::Dref(dref) => {
+                children.extend(dref.url.as_ref().map(AnyBox::Url));
+                &dref.unknown_boxes
+            }
+            AnyBox::Stbl(stbl) => {
+                children.push(AnyBox::Stsd(&stbl.stsd));
+                children.push(AnyBox::Stts(&stbl.stts));
+                children.extend(stbl.ctts.as_ref().map(AnyBox::Ctts));
+                children.extend(stbl.stss.as_ref().map(AnyBox::Stss));
+                children.push(AnyBox::Stsc(&stbl.stsc));
+                children.push(AnyBox::Stsz(&stbl.stsz));
+                children.extend(stbl.stco.as_ref().map(AnyBox::Stco));
+                children.extend(stbl.co64.as_ref().map(AnyBox::Co64));
+                &stbl.unknown_boxes
+            }
+            AnyBox::Stsd(stsd) => {
+                children.extend(stsd.entries.iter().map(|entry| match entry {
+                    SampleEntry::Avc1(avc1) => AnyBox::Avc1(avc1),
+                    SampleEntry::Hev1(hev1) => AnyBox::Hev1(hev1),
+                    SampleEntry::Hvc1(hvc1) => AnyBox::Hvc1(hvc1),
+                    SampleEntry::Vp09(vp09) => AnyBox::Vp09(vp09),
+                    SampleEntry::Av01(av01) => AnyBox::Av01(av01),
+                    SampleEntry::Mp4a(mp4a) => AnyBox::Mp4a(mp4a),
+                    SampleEntry::Opus(opus) => AnyBox::Opus(opus),
+                    SampleEntry::Flac(flac) => AnyBox::Flac(flac),
+                    SampleEntry::Tx3g(tx3g) => AnyBox::Tx3g(tx3g),
+                    SampleEntry::Unknown(name, data) => AnyBox::Unknown(*name, data),
+                }));
+                &[]
+            }
+            AnyBox::Avc1(avc1) => {
+                children.push(AnyBox::AvcC(&avc1.avcc));
+                &avc1.unknown_boxes
+            }
+            AnyBox::Hev1(hev1) => {
+                children.push(AnyBox::HvcC(&hev1.hvcc));
+                &hev1.unknown_boxes
+            }
+            AnyBox::Hvc1(hvc1) => {
+                children.push(AnyBox::HvcC(&hvc1.hvcc));
+                &hvc1.unknown_boxes
+            }
+            AnyBox::Vp09(vp09) => {
+                children.push(AnyBox::Vpcc(&vp09.vpcc));
+                &vp09.unknown_boxes
+            }
+            AnyBox::Av01(av01) => {
+                children.push(AnyBox::Av1C(&av01.av1c));
+                &av01.unknown_boxes
+            }
+            AnyBox::Mp4a(mp4a) => {
+                children.extend(mp4a.esds.as_ref().map(AnyBox::Esds));
+                &mp4a.unknown_boxes
+            }
+            AnyBox::Opus(opus) => {
+                children.push(AnyBox::Dops(&opus.dops));
+                &opus.unknown_boxes
+            }
+            AnyBox::Flac(flac) => {
+                children.push(AnyBox::Dfla(&flac.dfla));
+                &flac.unknown_boxes
+            }
+            AnyBox::Udta(udta) => {
+                children.extend(udta.meta.as_ref().map(AnyBox::Meta));
+                &udta.unknown_boxes
+            }
+            AnyBox::Meta(meta) => {
+                children.push(AnyBox::Hdlr(meta.hdlr()));
+                match meta {
+                    MetaBox::Mdir {
+                        ilst,
+                        unknown_boxes,
+                    } => {
+                        children.extend(ilst.as_ref().map(AnyBox::Ilst));
+                        unknown_boxes
+                    }
+                    MetaBox::Unknown { .. } => &[],
+                }
+            }
+            AnyBox::Ilst(ilst) => {
+                children.extend(
+                    ilst.sorted_items()
+                        .into_iter()
+                        .map(|(key, item)| AnyBox::IlstItem(key, item)),
+                );
+                &ilst.unknown_boxes
+            }
+            AnyBox::IlstItem(_, item) => {
+                children.push(AnyBox::Data(&item.data));
+                &item.unknown_boxes
+            }
+            AnyBox::Moof(moof) => {
+                children.push(AnyBox::Mfhd(&moof.mfhd));
+                children.extend(moof.trafs.iter().map(AnyBox::Traf));
+                &moof.unknown_boxes
+            }
+            AnyBox::Traf(traf) => {
+                children.push(AnyBox::Tfhd(&traf.tfhd));
+                children.extend(traf.tfdt.as_ref().map(AnyBox::Tfdt));
+                children.extend(traf.truns.iter().map(AnyBox::Trun));
+                &traf.unknown_boxes
+            }
+            _ => &[],
+        };
+        // Unknown boxes in their place, as written by `ChildWriter`.
+        for unknown_box in unknown_boxes {
+            let position = unknown_box.position.min(children.len());
+            let child = AnyBox::Unknown(unknown_box.name, &unknown_box.data);
+            children.insert(position, child);
+        }
+        children
+    }
+
+    /// Walk over this box and all the boxes it contains.
+    pub fn walk(self) -> BoxWalk<'a> {
+        BoxWalk::new(vec![self], &[])
+    }
+}
+
+/// A box found by [`BoxWalk`], with its place in the tree.
+#[derive(Debug, Clone)]
+pub struct BoxNode<'a> {
+    pub mp4box: AnyBox<'a>,
+    pub path: BoxPath,
+
+    /// Location of the box in the file it was read from, if any.
+    pub location: Option<&'a BoxLocation>,
+}
+
+impl BoxNode<'_> {
+    /// Depth of the box, 0 for the boxes the walk starts from.
+    pub fn depth(&self) -> usize {
+        self.path.0.len() - 1
+    }
+
+    /// Offset of the box in the file it was read from.
+    pub fn offset(&self) -> Option<u64> {
+        self.location.map(|location| location.offset)
+    }
+
+    /// Size of the box in the file it was read from, or else its size once
+    /// written.
+    pub fn size(&self) -> u64 {
+        match self.location {
+            Some(location) => location.size,
+            None => self.mp4box.box_size(),
+        }
+    }
+}
+
+/// Depth-first walk over a tree of boxes, with each box before its children,
+/// see [`AnyBox::walk`] and [`Mp4Reader::walk_boxes`].
+///
+/// The children of a box read from a file are walked in file order, and in
+/// the order they are written otherwise.
+#[derive(Debug)]
+pub struct BoxWalk<'a> {
+    // Boxes left to walk, the next one last.
+    stack: Vec<BoxNode<'a>>,
+    locations: HashMap<&'a BoxPath, &'a BoxLocation>,
+}
+
+impl<'a> BoxWalk<'a> {
+    pub(crate) fn new(roots: Vec<AnyBox<'a>>, locations: &'a [BoxLocation]) -> Self {
+        let mut walk = BoxWalk {
+            stack: Vec::new(),
+            locations: locations
+                .iter()
+                .map(|location| (&location.path, location))
+                .collect(),
+        };
+        walk.push_children(&BoxPath::default(), roots);
+        walk
+    }
+
+    fn push_children(&mut self, parent: &BoxPath, children: Vec<AnyBox<'a>>) {
+        // Number of boxes of each type so far, for their index in the path.
+        let mut counts: Vec<(BoxType, u32)> = Vec::new();
+        let mut nodes: Vec<BoxNode<'a>> = children
+            .into_iter()
+            .map(|mp4box| {
+                let name = mp4box.box_type();
+                let index = match counts.iter_mut().find(|(child, _)| *child == name) {
+                    Some((_, count)) => {
+                        *count += 1;
+                        *count
+                    }
+                    None => {
+                        counts.push((name, 1));
+                        1
+                    }
+                };
+                let mut path = parent.clone();
+                path.0.push((name, index));
+                let location = self.locations.get(&path).copied();
+                BoxNode {
+                    mp4box,
+                    path,
+                    location,
+                }
+            })
+            .collect();
+        nodes.sort_by_key(|node| node.offset().unwrap_or(u64::MAX));
+        self.stack.extend(nodes.into_iter().rev());
+    }
+}
+
+impl<'a> Iterator for BoxWalk<'a> {
+    type Item = BoxNode<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.push_children(&node.path, node.mp4box.children());
+        Some(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_moov() {
+        let moov = MoovBox {
+            traks: vec![TrakBox::default(), TrakBox::default()],
+            unknown_boxes: vec![UnknownBox {
+                name: BoxType::from(u32::from_be_bytes(*b"abcd")),
+                data: vec![1, 2],
+                position: 2,
+            }],
+            ..MoovBox::default()
+        };
+        let nodes: Vec<_> = AnyBox::Moov(&moov).walk().collect();
+        let paths: Vec<_> = nodes
+            .iter()
+            .filter(|node| node.depth() <= 2)
+            .map(|node| node.path.to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "moov",
+                "moov/mvhd",
+                "moov/trak",
+                "moov/trak/tkhd",
+                "moov/trak/mdia",
+                "moov/abcd",
+                "moov/trak[2]",
+                "moov/trak[2]/tkhd",
+                "moov/trak[2]/mdia",
+            ]
+        );
+
+        assert!(nodes.iter().all(|node| node.location.is_none()));
+        assert_eq!(nodes[0].size(), moov.box_size());
+        let unknown = nodes
+            .iter()
+            .find(|node| node.path.to_string() == "moov/abcd")
+            .unwrap();
+        assert!(matches!(unknown.mp4box, AnyBox::Unknown(_, [1, 2])));
+        assert_eq!(unknown.size(), 10);
+
+        // Every box is walked, down to the sample table.
+        let stsz = nodes
+            .iter()
+            .find(|node| node.path.to_string() == "moov/trak[2]/mdia/minf/stbl/stsz")
+            .unwrap();
+        assert_eq!(stsz.depth(), 5);
+    }
+}
@@ -0,0 +1,398 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use serde::Serialize;
+use std::io::{Read, Seek, Write};
+
+use crate::mp4box::*;
+
+/// AV1 sample entry, see the AV1 ISOBMFF binding 2.2.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Av01Box {
+    pub data_reference_index: u16,
+    pub width: u16,
+    pub height: u16,
+
+    #[serde(with = "value_u32")]
+    pub horizresolution: FixedPointU16,
+
+    #[serde(with = "value_u32")]
+    pub vertresolution: FixedPointU16,
+    pub frame_count: u16,
+    pub depth: u16,
+    pub av1c: Av1CBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
+}
+
+impl Default for Av01Box {
+    fn default() -> Self {
+        Av01Box {
+            data_reference_index: 0,
+            width: 0,
+            height: 0,
+            horizresolution: FixedPointU16::new(0x48),
+            vertresolution: FixedPointU16::new(0x48),
+            frame_count: 1,
+            depth: 0x0018,
+            av1c: Av1CBox::default(),
+            unknown_boxes: Vec::new(),
+        }
+    }
+}
+
+impl Av01Box {
+    pub fn new(config: &Av1Config) -> Self {
+        Av01Box {
+            data_reference_index: 1,
+            width: config.width,
+            height: config.height,
+            horizresolution: FixedPointU16::new(0x48),
+            vertresolution: FixedPointU16::new(0x48),
+            frame_count: 1,
+            depth: 0x0018,
+            av1c: Av1CBox::new(config),
+            unknown_boxes: Vec::new(),
+        }
+    }
+
+    pub fn get_type(&self) -> BoxType {
+        BoxType::Av01Box
+    }
+
+    pub fn get_size(&self) -> u64 {
+        HEADER_SIZE + 8 + 70 + self.av1c.box_size() + unknown_boxes_size(&self.unknown_boxes)
+    }
+}
+
+impl Mp4Box for Av01Box {
+    fn box_type(&self) -> BoxType {
+        self.get_type()
+    }
+
+    fn box_size(&self) -> u64 {
+        self.get_size()
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self).unwrap())
+    }
+
+    fn summary(&self) -> Result<String> {
+        let s = format!(
+            "data_reference_index={} width={} height={} frame_count={}",
+            self.data_reference_index, self.width, self.height, self.frame_count
+        );
+        Ok(s)
+    }
+}
+
+impl ParseBox for Av01Box {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
+        let start = box_start(reader)?;
+
+        reader.read_u32::<BigEndian>()?; // reserved
+        reader.read_u16::<BigEndian>()?; // reserved
+        let data_reference_index = reader.read_u16::<BigEndian>()?;
+
+        reader.read_u32::<BigEndian>()?; // pre-defined, reserved
+        reader.read_u64::<BigEndian>()?; // pre-defined
+        reader.read_u32::<BigEndian>()?; // pre-defined
+        let width = reader.read_u16::<BigEndian>()?;
+        let height = reader.read_u16::<BigEndian>()?;
+        let horizresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
+        let vertresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
+        reader.read_u32::<BigEndian>()?; // reserved
+        let frame_count = reader.read_u16::<BigEndian>()?;
+        skip_bytes(reader, 32)?; // compressorname
+        let depth = reader.read_u16::<BigEndian>()?;
+        reader.read_i16::<BigEndian>()?; // pre-defined
+
+        let mut av1c = None;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        // Some writers end sample entries with padding shorter than a box.
+        while end.saturating_sub(current) >= HEADER_SIZE {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::Av1CBox => {
+                        av1c = Some(Av1CBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
+        }
+        let av1c = av1c.ok_or(Error::InvalidData("av1c not found"))?;
+
+        skip_bytes_to(reader, start + size)?;
+
+        Ok(Av01Box {
+            data_reference_index,
+            width,
+            height,
+            horizresolution,
+            vertresolution,
+            frame_count,
+            depth,
+            av1c,
+            unknown_boxes,
+        })
+    }
+}
+
+impl<W: Write> WriteBox<&mut W> for Av01Box {
+    fn write_box(&self, writer: &mut W) -> Result<u64> {
+        let size = self.box_size();
+        BoxHeader::new(self.box_type(), size).write(writer)?;
+
+        writer.write_u32::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(self.data_reference_index)?;
+
+        writer.write_u32::<BigEndian>(0)?; // pre-defined, reserved
+        writer.write_u64::<BigEndian>(0)?; // pre-defined
+        writer.write_u32::<BigEndian>(0)?; // pre-defined
+        writer.write_u16::<BigEndian>(self.width)?;
+        writer.write_u16::<BigEndian>(self.height)?;
+        writer.write_u32::<BigEndian>(self.horizresolution.raw_value())?;
+        writer.write_u32::<BigEndian>(self.vertresolution.raw_value())?;
+        writer.write_u32::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(self.frame_count)?;
+        // skip compressorname
+        write_zeros(writer, 32)?;
+        writer.write_u16::<BigEndian>(self.depth)?;
+        writer.write_i16::<BigEndian>(-1)?; // pre-defined
+
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.av1c)?;
+        children.finish()?;
+
+        Ok(size)
+    }
+}
+
+/// AV1CodecConfigurationRecord, see the AV1 ISOBMFF binding 2.3.3.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct Av1CBox {
+    pub version: u8,
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8,
+    pub initial_presentation_delay_minus_one: Option<u8>,
+
+    /// Sequence header OBU followed by metadata OBUs, each with its size.
+    pub config_obus: Vec<u8>,
+}
+
+impl Av1CBox {
+    pub fn new(config: &Av1Config) -> Self {
+        Av1CBox {
+            version: 1,
+            seq_profile: config.seq_profile,
+            seq_level_idx_0: config.seq_level_idx_0,
+            seq_tier_0: config.seq_tier_0,
+            high_bitdepth: config.bit_depth > 8,
+            twelve_bit: config.bit_depth == 12,
+            monochrome: config.monochrome,
+            chroma_subsampling_x: config.chroma_subsampling_x,
+            chroma_subsampling_y: config.chroma_subsampling_y,
+            chroma_sample_position: config.chroma_sample_position,
+            initial_presentation_delay_minus_one: None,
+            config_obus: config.config_obus.clone(),
+        }
+    }
+
+    /// Bit depth of the samples, 8, 10 or 12.
+    pub fn bit_depth(&self) -> u8 {
+        match (self.high_bitdepth, self.twelve_bit) {
+            (false, _) => 8,
+            (true, false) => 10,
+            (true, true) => 12,
+        }
+    }
+}
+
+impl Mp4Box for Av1CBox {
+    fn box_type(&self) -> BoxType {
+        BoxType::Av1CBox
+    }
+
+    fn box_size(&self) -> u64 {
+        HEADER_SIZE + 4 + self.config_obus.len() as u64
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self).unwrap())
+    }
+
+    fn summary(&self) -> Result<String> {
+        let s = format!(
+            "seq_profile={} seq_level_idx_0={} bit_depth={} monochrome={}",
+            self.seq_profile,
+            self.seq_level_idx_0,
+            self.bit_depth(),
+            self.monochrome
+        );
+        Ok(s)
+    }
+}
+
+impl ParseBox for Av1CBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
+        let start = box_start(reader)?;
+        let end = start + size;
+
+        let byte = reader.read_u8()?;
+        if byte >> 7 != 1 {
+            return Err(Error::InvalidData("av1c marker not set"));
+        }
+        let version = byte & 0x7F;
+
+        let byte = reader.read_u8()?;
+        let seq_profile = byte >> 5;
+        let seq_level_idx_0 = byte & 0x1F;
+
+        let byte = reader.read_u8()?;
+        let seq_tier_0 = byte & 0x80 != 0;
+        let high_bitdepth = byte & 0x40 != 0;
+        let twelve_bit = byte & 0x20 != 0;
+        let monochrome = byte & 0x10 != 0;
+        let chroma_subsampling_x = byte & 0x08 != 0;
+        let chroma_subsampling_y = byte & 0x04 != 0;
+        let chroma_sample_position = byte & 0x03;
+
+        let byte = reader.read_u8()?;
+        let initial_presentation_delay_minus_one = if byte & 0x10 != 0 {
+            Some(byte & 0x0F)
+        } else {
+            None
+        };
+
+        let obus_size = end.saturating_sub(reader.stream_position()?);
+        let config_obus = read_bytes(reader, obus_size)?;
+
+        Ok(Av1CBox {
+            version,
+            seq_profile,
+            seq_level_idx_0,
+            seq_tier_0,
+            high_bitdepth,
+            twelve_bit,
+            monochrome,
+            chroma_subsampling_x,
+            chroma_subsampling_y,
+            chroma_sample_position,
+            initial_presentation_delay_minus_one,
+            config_obus,
+        })
+    }
+}
+
+impl<W: Write> WriteBox<&mut W> for Av1CBox {
+    fn write_box(&self, writer: &mut W) -> Result<u64> {
+        let size = self.box_size();
+        BoxHeader::new(self.box_type(), size).write(writer)?;
+
+        writer.write_u8(0x80 | (self.version & 0x7F))?;
+        writer.write_u8((self.seq_profile << 5) | (self.seq_level_idx_0 & 0x1F))?;
+        writer.write_u8(
+            ((self.seq_tier_0 as u8) << 7)
+                | ((self.high_bitdepth as u8) << 6)
+                | ((self.twelve_bit as u8) << 5)
+                | ((self.monochrome as u8) << 4)
+                | ((self.chroma_subsampling_x as u8) << 3)
+                | ((self.chroma_subsampling_y as u8) << 2)
+                | (self.chroma_sample_position & 0x03),
+        )?;
+        match self.initial_presentation_delay_minus_one {
+            Some(delay) => writer.write_u8(0x10 | (delay & 0x0F))?,
+            None => writer.write_u8(0)?,
+        }
+        writer.write_all(&self.config_obus)?;
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4box::BoxHeader;
+    use std::io::Cursor;
+
+    // Sequence header OBU, which is kept as is.
+    const SEQUENCE_HEADER: &[u8] = &[
+        0x0A, 0x0B, 0x00, 0x00, 0x00, 0x24, 0xC4, 0xFF, 0xDF, 0x00, 0x68, 0x02,
+    ];
+
+    #[test]
+    fn test_av1c() {
+        let src_box = Av1CBox {
+            version: 1,
+            seq_profile: 1,
+            seq_level_idx_0: 8,
+            seq_tier_0: true,
+            high_bitdepth: true,
+            twelve_bit: false,
+            monochrome: false,
+            chroma_subsampling_x: false,
+            chroma_subsampling_y: false,
+            chroma_sample_position: 0,
+            initial_presentation_delay_minus_one: Some(3),
+            config_obus: SEQUENCE_HEADER.to_vec(),
+        };
+        assert_eq!(src_box.bit_depth(), 10);
+
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+        assert_eq!(buf[8..12], [0x81, 0x28, 0xC0, 0x13]);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::Av1CBox);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = Av1CBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
+
+    #[test]
+    fn test_av01() {
+        let src_box = Av01Box::new(&Av1Config {
+            width: 320,
+            height: 240,
+            config_obus: SEQUENCE_HEADER.to_vec(),
+            ..Default::default()
+        });
+        assert_eq!(src_box.av1c.bit_depth(), 8);
+
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::Av01Box);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = Av01Box::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
+}
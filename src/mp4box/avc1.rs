@@ -18,6 +18,9 @@ pub struct Avc1Box {
     pub frame_count: u16,
     pub depth: u16,
     pub avcc: AvcCBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl Default for Avc1Box {
@@ -31,6 +34,7 @@ impl Default for Avc1Box {
             frame_count: 1,
             depth: 0x0018,
             avcc: AvcCBox::default(),
+            unknown_boxes: Vec::new(),
         }
     }
 }
@@ -46,6 +50,7 @@ impl Avc1Box {
             frame_count: 1,
             depth: 0x0018,
             avcc: AvcCBox::new(&config.seq_param_set, &config.pic_param_set),
+            unknown_boxes: Vec::new(),
         }
     }
 
@@ -54,7 +59,7 @@ impl Avc1Box {
     }
 
     pub fn get_size(&self) -> u64 {
-        HEADER_SIZE + 8 + 70 + self.avcc.box_size()
+        HEADER_SIZE + 8 + 70 + self.avcc.box_size() + unknown_boxes_size(&self.unknown_boxes)
     }
 }
 
@@ -80,8 +85,8 @@ impl Mp4Box for Avc1Box {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for Avc1Box {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for Avc1Box {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         reader.read_u32::<BigEndian>()?; // reserved
@@ -101,26 +106,46 @@ impl<R: Read + Seek> ReadBox<&mut R> for Avc1Box {
         let depth = reader.read_u16::<BigEndian>()?;
         reader.read_i16::<BigEndian>()?; // pre-defined
 
-        let header = BoxHeader::read(reader)?;
-        let BoxHeader { name, size: s } = header;
-        if name == BoxType::AvcCBox {
-            let avcc = AvcCBox::read_box(reader, s)?;
-
-            skip_bytes_to(reader, start + size)?;
-
-            Ok(Avc1Box {
-                data_reference_index,
-                width,
-                height,
-                horizresolution,
-                vertresolution,
-                frame_count,
-                depth,
-                avcc,
-            })
-        } else {
-            Err(Error::InvalidData("avcc not found"))
+        let mut avcc = None;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        // Some writers end sample entries with padding shorter than a box.
+        while end.saturating_sub(current) >= HEADER_SIZE {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::AvcCBox => {
+                        avcc = Some(AvcCBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
         }
+        let avcc = avcc.ok_or(Error::InvalidData("avcc not found"))?;
+
+        skip_bytes_to(reader, start + size)?;
+
+        Ok(Avc1Box {
+            data_reference_index,
+            width,
+            height,
+            horizresolution,
+            vertresolution,
+            frame_count,
+            depth,
+            avcc,
+            unknown_boxes,
+        })
     }
 }
 
@@ -147,7 +172,9 @@ impl<W: Write> WriteBox<&mut W> for Avc1Box {
         writer.write_u16::<BigEndian>(self.depth)?;
         writer.write_i16::<BigEndian>(-1)?; // pre-defined
 
-        self.avcc.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.avcc)?;
+        children.finish()?;
 
         Ok(size)
     }
@@ -204,8 +231,8 @@ impl Mp4Box for AvcCBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for AvcCBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for AvcCBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let configuration_version = reader.read_u8()?;
@@ -276,18 +303,18 @@ impl From<&[u8]> for NalUnit {
 }
 
 impl NalUnit {
-    fn size(&self) -> usize {
+    pub(crate) fn size(&self) -> usize {
         2 + self.bytes.len()
     }
 
-    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
+    pub(crate) fn read<R: Read + Seek>(reader: &mut R) -> Result<Self> {
         let length = reader.read_u16::<BigEndian>()? as usize;
         let mut bytes = vec![0u8; length];
         reader.read_exact(&mut bytes)?;
         Ok(NalUnit { bytes })
     }
 
-    fn write<W: Write>(&self, writer: &mut W) -> Result<u64> {
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> Result<u64> {
         writer.write_u16::<BigEndian>(self.bytes.len() as u16)?;
         writer.write_all(&self.bytes)?;
         Ok(self.size() as u64)
@@ -326,6 +353,7 @@ mod tests {
                     bytes: vec![0x68, 0xEB, 0xE3, 0xCB, 0x22, 0xC0],
                 }],
             },
+            unknown_boxes: Vec::new(),
         };
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
@@ -339,4 +367,45 @@ mod tests {
         let dst_box = Avc1Box::read_box(&mut reader, header.size).unwrap();
         assert_eq!(src_box, dst_box);
     }
+
+    #[test]
+    fn test_avc1_unknown_boxes() {
+        let avc1 = Avc1Box::new(&AvcConfig {
+            width: 320,
+            height: 240,
+            seq_param_set: vec![0x67, 0x64, 0x00, 0x0D],
+            pic_param_set: vec![0x68, 0xEB, 0xE3, 0xCB],
+        });
+        let mut avcc = Vec::new();
+        avc1.avcc.write_box(&mut avcc).unwrap();
+
+        // A pasp before avcC and a btrt after it.
+        let pasp = [0, 0, 0, 16, b'p', b'a', b's', b'p', 0, 0, 0, 1, 0, 0, 0, 1];
+        let btrt = [
+            0, 0, 0, 20, b'b', b't', b'r', b't', 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0x80, 0,
+        ];
+        let mut buf = Vec::new();
+        avc1.write_box(&mut buf).unwrap();
+        buf.truncate(buf.len() - avcc.len());
+        buf.extend_from_slice(&pasp);
+        buf.extend_from_slice(&avcc);
+        buf.extend_from_slice(&btrt);
+        let size = buf.len() as u32;
+        buf[..4].copy_from_slice(&size.to_be_bytes());
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let dst_box = Avc1Box::read_box(&mut reader, header.size).unwrap();
+        let unknown_boxes: Vec<_> = dst_box
+            .unknown_boxes
+            .iter()
+            .map(|unknown| (unknown.name.to_string(), unknown.position))
+            .collect();
+        assert_eq!(unknown_boxes, [("pasp".into(), 0), ("btrt".into(), 2)]);
+
+        // Written back as it was read.
+        let mut dst_buf = Vec::new();
+        dst_box.write_box(&mut dst_buf).unwrap();
+        assert_eq!(dst_buf, buf);
+    }
 }
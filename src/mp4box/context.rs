@@ -0,0 +1,371 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::mp4box::*;
+
+/// Parse a box with the context of its reader, see `BoxReader`.
+///
+/// `ReadBox` is implemented from it for any reader, parsing the box in a
+/// default context, strictly and with the default limits.
+pub trait ParseBox: Sized {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self>;
+}
+
+impl<'a, R: Read + Seek, T: ParseBox> ReadBox<&'a mut R> for T {
+    fn read_box(reader: &'a mut R, size: u64) -> Result<Self> {
+        T::parse_box(&mut BoxReader::new(reader, ParseContext::default()), size)
+    }
+}
+
+/// A reader of boxes together with the context of their parsing, which the
+/// box parsers pass down to the parsers of their children.
+#[derive(Debug)]
+pub struct BoxReader<R> {
+    reader: R,
+    context: ParseContext,
+}
+
+impl<R> BoxReader<R> {
+    pub(crate) fn new(reader: R, context: ParseContext) -> Self {
+        BoxReader { reader, context }
+    }
+
+    pub(crate) fn context_mut(&mut self) -> &mut ParseContext {
+        &mut self.context
+    }
+
+    pub(crate) fn into_parts(self) -> (R, ParseContext) {
+        (self.reader, self.context)
+    }
+}
+
+impl<R: Read> Read for BoxReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.read_exact(buf)
+    }
+}
+
+impl<R: Seek> Seek for BoxReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader.seek(pos)
+    }
+
+    // Forwarded, as seeking a `BufReader` discards its buffer.
+    fn stream_position(&mut self) -> io::Result<u64> {
+        self.reader.stream_position()
+    }
+}
+
+/// Options of the parsing of boxes, with the warnings found so far.
+#[derive(Debug)]
+pub(crate) struct ParseContext {
+    lenient: bool,
+    limits: Mp4Limits,
+    // Whether the boxes are read from the top level of a file, rather than
+    // from a box read on its own.
+    in_file: bool,
+    // Added to the reader positions to get file offsets.
+    base_offset: u64,
+    // Boxes being parsed, from the top level down.
+    path: BoxPath,
+    // Number of boxes of each type seen so far at the top level and in each
+    // box being parsed.
+    child_counts: Vec<Vec<(BoxType, u32)>>,
+    // Header size of the box whose header has just been read.
+    header_size: u64,
+    locations: Vec<BoxLocation>,
+    warnings: Vec<Mp4Warning>,
+}
+
+impl Default for ParseContext {
+    /// Context of a box read on its own, strictly and with the default limits.
+    fn default() -> Self {
+        ParseContext {
+            in_file: false,
+            ..ParseContext::new(&Mp4ReaderConfig::default())
+        }
+    }
+}
+
+impl ParseContext {
+    /// Context of the boxes of a file read with the options of `config`.
+    pub(crate) fn new(config: &Mp4ReaderConfig) -> Self {
+        ParseContext {
+            lenient: config.lenient,
+            limits: config.limits,
+            in_file: true,
+            base_offset: 0,
+            path: BoxPath::default(),
+            child_counts: vec![Vec::new()],
+            header_size: HEADER_SIZE,
+            locations: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Offset of the reader position 0 in the file.
+    pub(crate) fn set_base_offset(&mut self, base_offset: u64) {
+        self.base_offset = base_offset;
+    }
+
+    /// Set the header size of the next box given to `read_child`, whose header
+    /// has just been read.
+    pub(crate) fn set_header_size(&mut self, header_size: u64) {
+        self.header_size = header_size;
+    }
+
+    pub(crate) fn warnings(&self) -> &[Mp4Warning] {
+        &self.warnings
+    }
+
+    pub(crate) fn into_warnings(self) -> Vec<Mp4Warning> {
+        self.warnings
+    }
+
+    /// Take the locations of the boxes parsed so far, in file order.
+    pub(crate) fn take_locations(&mut self) -> Vec<BoxLocation> {
+        std::mem::take(&mut self.locations)
+    }
+
+    /// Record a top-level box which is skipped, with a warning if its type
+    /// isn't known.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn skip_box(&mut self, name: BoxType, location: BoxLocation, is_known: bool) {
+        let warning = if is_known {
+            None
+        } else {
+            Some(UNKNOWN_BOX_SKIPPED)
+        };
+        let path = self.count_top_level_box(name, location.offset, warning);
+        self.locations.push(BoxLocation { path, ..location });
+    }
+
+    /// Count a top-level box at `offset` which isn't parsed, with `warning` if
+    /// any, and return its path.
+    pub(crate) fn count_top_level_box(
+        &mut self,
+        name: BoxType,
+        offset: u64,
+        warning: Option<&str>,
+    ) -> BoxPath {
+        let path = BoxPath(vec![(name, self.enter(name))]);
+        self.exit();
+        if let Some(message) = warning {
+            self.warnings.push(Mp4Warning {
+                path: path.clone(),
+                offset,
+                message: message.to_string(),
+            });
+        }
+        path
+    }
+
+    /// Index of the next child of type `name` of the box being parsed.
+    fn next_index(&self, name: BoxType) -> u32 {
+        let counts = self.child_counts.last().unwrap();
+        match counts.iter().find(|(child, _)| *child == name) {
+            Some((_, count)) => count + 1,
+            None => 1,
+        }
+    }
+
+    fn enter(&mut self, name: BoxType) -> u32 {
+        let index = self.next_index(name);
+        let counts = self.child_counts.last_mut().unwrap();
+        match counts.iter_mut().find(|(child, _)| *child == name) {
+            Some((_, count)) => *count = index,
+            None => counts.push((name, index)),
+        }
+        self.path.0.push((name, index));
+        self.child_counts.push(Vec::new());
+        index
+    }
+
+    /// Undo the counting of the last child of type `name` by `enter`, once
+    /// it has been exited.
+    fn forget(&mut self, name: BoxType) {
+        let counts = self.child_counts.last_mut().unwrap();
+        if let Some((_, count)) = counts.iter_mut().find(|(child, _)| *child == name) {
+            *count -= 1;
+        }
+    }
+
+    fn exit(&mut self) {
+        self.path.0.pop();
+        self.child_counts.pop();
+    }
+
+    fn warn(&mut self, path: BoxPath, position: u64, message: String) {
+        self.warnings.push(Mp4Warning {
+            path,
+            offset: self.base_offset + position,
+            message,
+        });
+    }
+
+    fn check_box_size(&self, size: u64) -> Result<()> {
+        let max_box_size = self.limits.max_box_size;
+        if size > max_box_size {
+            return Err(Error::LimitExceeded("box size", size, max_box_size));
+        }
+        Ok(())
+    }
+}
+
+/// Whether malformed boxes are skipped with a warning rather than failing.
+pub(crate) fn is_lenient<R>(reader: &BoxReader<R>) -> bool {
+    reader.context.lenient
+}
+
+/// Whether the boxes being read are at the top level of a file, rather than
+/// in a box.
+pub(crate) fn is_top_level<R>(reader: &BoxReader<R>) -> bool {
+    reader.context.in_file && reader.context.path.0.is_empty()
+}
+
+/// Check the size of a top-level box to be parsed against the limits.
+pub(crate) fn check_top_level_box_size<R>(reader: &BoxReader<R>, size: u64) -> Result<()> {
+    reader.context.check_box_size(size)
+}
+
+/// Check a table of `entry_count` entries of `entry_size` bytes against the
+/// limits, and that it fits in the rest of the box ending at `end`, before
+/// anything is allocated for it.
+pub(crate) fn check_entry_count<R: Seek>(
+    reader: &mut BoxReader<R>,
+    end: u64,
+    entry_count: u32,
+    entry_size: u64,
+) -> Result<()> {
+    let max_table_entries = reader.context.limits.max_table_entries;
+    if entry_count > max_table_entries {
+        return Err(Error::LimitExceeded(
+            "table entry count",
+            entry_count as u64,
+            max_table_entries as u64,
+        ));
+    }
+    let left = end.saturating_sub(reader.stream_position()?);
+    if entry_count as u64 * entry_size > left {
+        return Err(Error::Malformed(format!(
+            "{entry_count} entries past the end of the box, room for {}",
+            left / entry_size
+        )));
+    }
+    Ok(())
+}
+
+/// Record a warning about the box being parsed, at reader position `position`.
+pub(crate) fn warn<R>(reader: &mut BoxReader<R>, position: u64, message: impl Into<String>) {
+    let context = &mut reader.context;
+    let path = context.path.clone();
+    context.warn(path, position, message.into())
+}
+
+/// Record a warning about the next child `name` of the box being parsed.
+pub(crate) fn warn_child<R>(
+    reader: &mut BoxReader<R>,
+    name: BoxType,
+    position: u64,
+    message: impl Into<String>,
+) {
+    let context = &mut reader.context;
+    let mut path = context.path.clone();
+    path.0.push((name, context.next_index(name)));
+    context.warn(path, position, message.into())
+}
+
+/// Number of the children kept so far by the parent of the box being parsed
+/// before it.
+pub(crate) fn child_position<R>(reader: &BoxReader<R>) -> usize {
+    let child_counts = &reader.context.child_counts;
+    match child_counts.len().checked_sub(2) {
+        Some(parent) => child_counts[parent]
+            .iter()
+            .map(|(_, count)| *count as usize)
+            .sum::<usize>()
+            .saturating_sub(1),
+        None => 0,
+    }
+}
+
+/// Parse with `f` a child box whose header has just been read. In lenient
+/// mode, a child failing to parse is skipped with a warning instead of failing
+/// its parent, and otherwise errors are given the path and offset of the box
+/// they come from.
+pub(crate) fn read_child<R, F>(
+    reader: &mut BoxReader<R>,
+    name: BoxType,
+    size: u64,
+    f: F,
+) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnOnce(&mut BoxReader<R>) -> Result<()>,
+{
+    let start = box_start(reader)?;
+    let context = &mut reader.context;
+    // The start of a box with a largesize is 8 bytes before `start`.
+    let header_size = std::mem::replace(&mut context.header_size, HEADER_SIZE);
+    let offset = (context.base_offset + start + HEADER_SIZE).saturating_sub(header_size);
+    let is_nested = !context.path.0.is_empty();
+    let index = context.enter(name);
+    let location_count = context.locations.len();
+    context.locations.push(BoxLocation {
+        path: context.path.clone(),
+        offset,
+        header_size,
+        size: (size + header_size).saturating_sub(HEADER_SIZE),
+    });
+    let depth = context.path.0.len() as u64;
+    let max_depth = context.limits.max_depth as u64;
+    let checked = if depth > max_depth {
+        Err(Error::LimitExceeded("box depth", depth, max_depth))
+    } else if is_nested {
+        context.check_box_size(size)
+    } else {
+        // Top-level boxes which are skipped, such as mdat, aren't limited.
+        Ok(())
+    };
+
+    let result = checked.and_then(|()| f(reader));
+    let context = &mut reader.context;
+    context.exit();
+    match result {
+        Ok(()) => Ok(()),
+        Err(err) if context.lenient => {
+            let mut path = context.path.clone();
+            path.0.push((name, index));
+            context.warnings.push(Mp4Warning {
+                path,
+                offset,
+                message: err.to_string(),
+            });
+            // The box isn't kept by its parent, so neither it nor its children
+            // are located, and it doesn't count in the index of its siblings.
+            context.locations.truncate(location_count);
+            context.forget(name);
+            skip_bytes_to(reader, start.saturating_add(size))
+        }
+        Err(err) => Err(err.in_box(name, index, offset)),
+    }
+}
+
+pub(crate) const UNKNOWN_BOX_SKIPPED: &str = "unknown box skipped";
+
+/// Skip a box of a type which isn't parsed, with a warning.
+pub(crate) fn skip_unknown_box<R: Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<()> {
+    let context = &mut reader.context;
+    // The box being parsed is the last one located by `read_child`.
+    if let Some(location) = context.locations.last() {
+        context.warnings.push(Mp4Warning {
+            path: context.path.clone(),
+            offset: location.offset,
+            message: UNKNOWN_BOX_SKIPPED.to_string(),
+        });
+    }
+    skip_box(reader, size)
+}
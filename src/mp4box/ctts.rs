@@ -48,13 +48,14 @@ impl Mp4Box for CttsBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for CttsBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for CttsBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
         let entry_count = reader.read_u32::<BigEndian>()?;
+        check_entry_count(reader, start + size, entry_count, 8)?;
         let mut entries = Vec::with_capacity(entry_count as usize);
         for _ in 0..entry_count {
             let entry = CttsEntry {
@@ -46,8 +46,8 @@ impl Mp4Box for DataBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for DataBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for DataBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let data_type = DataType::try_from(reader.read_u32::<BigEndian>()?)?;
@@ -55,8 +55,10 @@ impl<R: Read + Seek> ReadBox<&mut R> for DataBox {
         reader.read_u32::<BigEndian>()?; // reserved = 0
 
         let current = reader.stream_position()?;
-        let mut data = vec![0u8; (start + size - current) as usize];
-        reader.read_exact(&mut data)?;
+        let len = (start + size)
+            .checked_sub(current)
+            .ok_or(Error::InvalidData("data box too small"))?;
+        let data = read_bytes(reader, len)?;
 
         Ok(DataBox { data, data_type })
     }
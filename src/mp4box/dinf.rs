@@ -5,7 +5,10 @@ use crate::mp4box::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct DinfBox {
-    dref: DrefBox,
+    pub(crate) dref: DrefBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl DinfBox {
@@ -14,7 +17,7 @@ impl DinfBox {
     }
 
     pub fn get_size(&self) -> u64 {
-        HEADER_SIZE + self.dref.box_size()
+        HEADER_SIZE + self.dref.box_size() + unknown_boxes_size(&self.unknown_boxes)
     }
 }
 
@@ -37,28 +40,31 @@ impl Mp4Box for DinfBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for DinfBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for DinfBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut dref = None;
+        let mut unknown_boxes = Vec::new();
 
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::DrefBox => {
-                    dref = Some(DrefBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::DrefBox => {
+                        dref = Some(DrefBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -71,6 +77,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for DinfBox {
 
         Ok(DinfBox {
             dref: dref.unwrap(),
+            unknown_boxes,
         })
     }
 }
@@ -79,7 +86,9 @@ impl<W: Write> WriteBox<&mut W> for DinfBox {
     fn write_box(&self, writer: &mut W) -> Result<u64> {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
-        self.dref.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.dref)?;
+        children.finish()?;
         Ok(size)
     }
 }
@@ -91,6 +100,9 @@ pub struct DrefBox {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<UrlBox>,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl Default for DrefBox {
@@ -99,6 +111,7 @@ impl Default for DrefBox {
             version: 0,
             flags: 0,
             url: Some(UrlBox::default()),
+            unknown_boxes: Vec::new(),
         }
     }
 }
@@ -113,6 +126,7 @@ impl DrefBox {
         if let Some(ref url) = self.url {
             size += url.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -136,8 +150,8 @@ impl Mp4Box for DrefBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for DrefBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for DrefBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut current = reader.stream_position()?;
@@ -146,6 +160,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for DrefBox {
         let end = start + size;
 
         let mut url = None;
+        let mut unknown_boxes = Vec::new();
 
         let entry_count = reader.read_u32::<BigEndian>()?;
         for _i in 0..entry_count {
@@ -154,17 +169,20 @@ impl<R: Read + Seek> ReadBox<&mut R> for DrefBox {
             }
 
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::UrlBox => {
-                    url = Some(UrlBox::read_box(reader, s)?);
-                }
-                _ => {
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::UrlBox => {
+                        url = Some(UrlBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -175,6 +193,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for DrefBox {
             version,
             flags,
             url,
+            unknown_boxes,
         })
     }
 }
@@ -186,11 +205,14 @@ impl<W: Write> WriteBox<&mut W> for DrefBox {
 
         write_box_header_ext(writer, self.version, self.flags)?;
 
-        writer.write_u32::<BigEndian>(1)?;
+        let entry_count = self.url.is_some() as usize + self.unknown_boxes.len();
+        writer.write_u32::<BigEndian>(entry_count as u32)?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(ref url) = self.url {
-            url.write_box(writer)?;
+            children.write(url)?;
         }
+        children.finish()?;
 
         Ok(size)
     }
@@ -222,7 +244,7 @@ impl UrlBox {
         let mut size = HEADER_SIZE + HEADER_EXT_SIZE;
 
         if !self.location.is_empty() {
-            size += self.location.bytes().len() as u64 + 1;
+            size += self.location.len() as u64 + 1;
         }
 
         size
@@ -248,16 +270,15 @@ impl Mp4Box for UrlBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for UrlBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for UrlBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
-        let location = if size - HEADER_SIZE - HEADER_EXT_SIZE > 0 {
+        let location = if size > HEADER_SIZE + HEADER_EXT_SIZE {
             let buf_size = size - HEADER_SIZE - HEADER_EXT_SIZE - 1;
-            let mut buf = vec![0u8; buf_size as usize];
-            reader.read_exact(&mut buf)?;
+            let buf = read_bytes(reader, buf_size)?;
             match String::from_utf8(buf) {
                 Ok(t) => {
                     if t.len() != buf_size as usize {
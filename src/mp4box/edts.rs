@@ -7,6 +7,9 @@ use crate::mp4box::*;
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct EdtsBox {
     pub elst: Option<ElstBox>,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl EdtsBox {
@@ -23,6 +26,7 @@ impl EdtsBox {
         if let Some(ref elst) = self.elst {
             size += elst.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -46,23 +50,41 @@ impl Mp4Box for EdtsBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for EdtsBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for EdtsBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
-        let mut edts = EdtsBox::new();
-
-        let header = BoxHeader::read(reader)?;
-        let BoxHeader { name, size: s } = header;
-
-        if let BoxType::ElstBox = name {
-            let elst = ElstBox::read_box(reader, s)?;
-            edts.elst = Some(elst);
+        let mut elst = None;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        while current < end {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::ElstBox => {
+                        elst = Some(ElstBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
         }
 
         skip_bytes_to(reader, start + size)?;
 
-        Ok(edts)
+        Ok(EdtsBox {
+            elst,
+            unknown_boxes,
+        })
     }
 }
 
@@ -71,9 +93,11 @@ impl<W: Write> WriteBox<&mut W> for EdtsBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(ref elst) = self.elst {
-            elst.write_box(writer)?;
+            children.write(elst)?;
         }
+        children.finish()?;
 
         Ok(size)
     }
@@ -16,7 +16,9 @@ pub struct ElstBox {
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct ElstEntry {
     pub segment_duration: u64,
-    pub media_time: u64,
+
+    /// Start of the segment in the media, -1 for an empty edit.
+    pub media_time: i64,
     pub media_rate: u16,
     pub media_rate_fraction: u16,
 }
@@ -56,24 +58,26 @@ impl Mp4Box for ElstBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for ElstBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for ElstBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
         let entry_count = reader.read_u32::<BigEndian>()?;
+        let entry_size = if version == 1 { 20 } else { 12 };
+        check_entry_count(reader, start + size, entry_count, entry_size)?;
         let mut entries = Vec::with_capacity(entry_count as usize);
         for _ in 0..entry_count {
             let (segment_duration, media_time) = if version == 1 {
                 (
                     reader.read_u64::<BigEndian>()?,
-                    reader.read_u64::<BigEndian>()?,
+                    reader.read_i64::<BigEndian>()?,
                 )
             } else {
                 (
                     reader.read_u32::<BigEndian>()? as u64,
-                    reader.read_u32::<BigEndian>()? as u64,
+                    reader.read_i32::<BigEndian>()? as i64,
                 )
             };
 
@@ -107,10 +111,10 @@ impl<W: Write> WriteBox<&mut W> for ElstBox {
         for entry in self.entries.iter() {
             if self.version == 1 {
                 writer.write_u64::<BigEndian>(entry.segment_duration)?;
-                writer.write_u64::<BigEndian>(entry.media_time)?;
+                writer.write_i64::<BigEndian>(entry.media_time)?;
             } else {
                 writer.write_u32::<BigEndian>(entry.segment_duration as u32)?;
-                writer.write_u32::<BigEndian>(entry.media_time as u32)?;
+                writer.write_i32::<BigEndian>(entry.media_time as i32)?;
             }
             writer.write_u16::<BigEndian>(entry.media_rate)?;
             writer.write_u16::<BigEndian>(entry.media_rate_fraction)?;
@@ -175,4 +179,35 @@ mod tests {
         let dst_box = ElstBox::read_box(&mut reader, header.size).unwrap();
         assert_eq!(src_box, dst_box);
     }
+
+    #[test]
+    fn test_elst_empty_edit() {
+        let src_box = ElstBox {
+            version: 0,
+            flags: 0,
+            entries: vec![
+                ElstEntry {
+                    segment_duration: 1000,
+                    media_time: -1,
+                    media_rate: 1,
+                    media_rate_fraction: 0,
+                },
+                ElstEntry {
+                    segment_duration: 634634,
+                    media_time: 1024,
+                    media_rate: 1,
+                    media_rate_fraction: 0,
+                },
+            ],
+        };
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(&buf[20..24], &[0xff; 4]);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let dst_box = ElstBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+        assert_eq!(dst_box.entries[0].media_time, -1);
+    }
 }
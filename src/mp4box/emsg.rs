@@ -58,8 +58,8 @@ impl Mp4Box for EmsgBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for EmsgBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for EmsgBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
         let (version, flags) = read_box_header_ext(reader)?;
 
@@ -97,11 +97,10 @@ impl<R: Read + Seek> ReadBox<&mut R> for EmsgBox {
             _ => return Err(Error::InvalidData("version must be 0 or 1")),
         };
 
-        let message_size = size - Self::size_without_message(version, &scheme_id_uri, &value);
-        let mut message_data = Vec::with_capacity(message_size as usize);
-        for _ in 0..message_size {
-            message_data.push(reader.read_u8()?);
-        }
+        let message_size = size
+            .checked_sub(Self::size_without_message(version, &scheme_id_uri, &value))
+            .ok_or(Error::InvalidData("emsg strings past the end of the box"))?;
+        let message_data = read_bytes(reader, message_size)?;
 
         skip_bytes_to(reader, start + size)?;
 
@@ -0,0 +1,398 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+
+use crate::mp4box::*;
+
+/// FLAC sample entry, see Encapsulation of FLAC in ISOBMFF 3.3.1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlacBox {
+    pub data_reference_index: u16,
+    pub channelcount: u16,
+    pub samplesize: u16,
+
+    /// Sample rate, 0 if it doesn't fit in 16 bits.
+    #[serde(with = "value_u32")]
+    pub samplerate: FixedPointU16,
+    pub dfla: DflaBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
+}
+
+impl Default for FlacBox {
+    fn default() -> Self {
+        Self {
+            data_reference_index: 0,
+            channelcount: 2,
+            samplesize: 16,
+            samplerate: FixedPointU16::new(44100),
+            dfla: DflaBox::default(),
+            unknown_boxes: Vec::new(),
+        }
+    }
+}
+
+impl FlacBox {
+    pub fn new(config: &FlacConfig) -> Self {
+        let stream_info = &config.stream_info;
+        let samplerate = match u16::try_from(stream_info.sample_rate) {
+            Ok(sample_rate) => FixedPointU16::new(sample_rate),
+            Err(_) => FixedPointU16::new_raw(0),
+        };
+        Self {
+            data_reference_index: 1,
+            channelcount: stream_info.channels as u16,
+            samplesize: stream_info.bits_per_sample as u16,
+            samplerate,
+            dfla: DflaBox::new(config),
+            unknown_boxes: Vec::new(),
+        }
+    }
+
+    pub fn get_type(&self) -> BoxType {
+        BoxType::FlacBox
+    }
+
+    pub fn get_size(&self) -> u64 {
+        HEADER_SIZE + 8 + 20 + self.dfla.box_size() + unknown_boxes_size(&self.unknown_boxes)
+    }
+}
+
+impl Mp4Box for FlacBox {
+    fn box_type(&self) -> BoxType {
+        self.get_type()
+    }
+
+    fn box_size(&self) -> u64 {
+        self.get_size()
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self).unwrap())
+    }
+
+    fn summary(&self) -> Result<String> {
+        let s = format!(
+            "channel_count={} sample_size={} sample_rate={}",
+            self.channelcount, self.samplesize, self.dfla.stream_info.sample_rate
+        );
+        Ok(s)
+    }
+}
+
+impl ParseBox for FlacBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
+        let start = box_start(reader)?;
+
+        reader.read_u32::<BigEndian>()?; // reserved
+        reader.read_u16::<BigEndian>()?; // reserved
+        let data_reference_index = reader.read_u16::<BigEndian>()?;
+
+        reader.read_u64::<BigEndian>()?; // reserved
+        let channelcount = reader.read_u16::<BigEndian>()?;
+        let samplesize = reader.read_u16::<BigEndian>()?;
+        reader.read_u32::<BigEndian>()?; // pre-defined, reserved
+        let samplerate = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
+
+        let mut dfla = None;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        // Some writers end sample entries with padding shorter than a box.
+        while end.saturating_sub(current) >= HEADER_SIZE {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::DflaBox => {
+                        dfla = Some(DflaBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
+        }
+        let dfla = dfla.ok_or(Error::InvalidData("dfla not found"))?;
+
+        skip_bytes_to(reader, start + size)?;
+
+        Ok(FlacBox {
+            data_reference_index,
+            channelcount,
+            samplesize,
+            samplerate,
+            dfla,
+            unknown_boxes,
+        })
+    }
+}
+
+impl<W: Write> WriteBox<&mut W> for FlacBox {
+    fn write_box(&self, writer: &mut W) -> Result<u64> {
+        let size = self.box_size();
+        BoxHeader::new(self.box_type(), size).write(writer)?;
+
+        writer.write_u32::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(self.data_reference_index)?;
+
+        writer.write_u64::<BigEndian>(0)?; // reserved
+        writer.write_u16::<BigEndian>(self.channelcount)?;
+        writer.write_u16::<BigEndian>(self.samplesize)?;
+        writer.write_u32::<BigEndian>(0)?; // reserved
+        writer.write_u32::<BigEndian>(self.samplerate.raw_value())?;
+
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.dfla)?;
+        children.finish()?;
+
+        Ok(size)
+    }
+}
+
+/// FLACSpecificBox, see Encapsulation of FLAC in ISOBMFF 3.3.2.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct DflaBox {
+    pub version: u8,
+    pub flags: u32,
+    pub stream_info: FlacStreamInfo,
+
+    /// Metadata blocks following the STREAMINFO block.
+    pub metadata_blocks: Vec<FlacMetadataBlock>,
+}
+
+// Type and size of the STREAMINFO metadata block.
+const STREAMINFO_BLOCK_TYPE: u8 = 0;
+const STREAMINFO_SIZE: u32 = 34;
+
+impl DflaBox {
+    pub fn new(config: &FlacConfig) -> Self {
+        Self {
+            version: 0,
+            flags: 0,
+            stream_info: config.stream_info.clone(),
+            metadata_blocks: config.metadata_blocks.clone(),
+        }
+    }
+}
+
+impl Mp4Box for DflaBox {
+    fn box_type(&self) -> BoxType {
+        BoxType::DflaBox
+    }
+
+    fn box_size(&self) -> u64 {
+        let mut size = HEADER_SIZE + HEADER_EXT_SIZE + 4 + STREAMINFO_SIZE as u64;
+        for block in self.metadata_blocks.iter() {
+            size += 4 + block.data.len() as u64;
+        }
+        size
+    }
+
+    fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(&self).unwrap())
+    }
+
+    fn summary(&self) -> Result<String> {
+        let stream_info = &self.stream_info;
+        let s = format!(
+            "sample_rate={} channels={} bits_per_sample={} total_samples={} metadata_blocks={}",
+            stream_info.sample_rate,
+            stream_info.channels,
+            stream_info.bits_per_sample,
+            stream_info.total_samples,
+            self.metadata_blocks.len()
+        );
+        Ok(s)
+    }
+}
+
+impl ParseBox for DflaBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
+        let start = box_start(reader)?;
+        let end = start + size;
+
+        let (version, flags) = read_box_header_ext(reader)?;
+
+        let (mut is_last, block_type, length) = read_block_header(reader)?;
+        if block_type != STREAMINFO_BLOCK_TYPE || length != STREAMINFO_SIZE {
+            return Err(Error::InvalidData("dfla without streaminfo"));
+        }
+        let stream_info = read_stream_info(reader)?;
+
+        let mut metadata_blocks = Vec::new();
+        while !is_last && reader.stream_position()? < end {
+            let (last, block_type, length) = read_block_header(reader)?;
+            if reader.stream_position()? + length as u64 > end {
+                return Err(Error::InvalidData("flac metadata block exceeds dfla size"));
+            }
+            let data = read_bytes(reader, length as u64)?;
+            metadata_blocks.push(FlacMetadataBlock { block_type, data });
+            is_last = last;
+        }
+
+        skip_bytes_to(reader, end)?;
+
+        Ok(DflaBox {
+            version,
+            flags,
+            stream_info,
+            metadata_blocks,
+        })
+    }
+}
+
+impl<W: Write> WriteBox<&mut W> for DflaBox {
+    fn write_box(&self, writer: &mut W) -> Result<u64> {
+        let size = self.box_size();
+        BoxHeader::new(self.box_type(), size).write(writer)?;
+
+        write_box_header_ext(writer, self.version, self.flags)?;
+
+        let is_last = self.metadata_blocks.is_empty();
+        write_block_header(writer, is_last, STREAMINFO_BLOCK_TYPE, STREAMINFO_SIZE)?;
+        write_stream_info(writer, &self.stream_info)?;
+        for (i, block) in self.metadata_blocks.iter().enumerate() {
+            let is_last = i + 1 == self.metadata_blocks.len();
+            write_block_header(writer, is_last, block.block_type, block.data.len() as u32)?;
+            writer.write_all(&block.data)?;
+        }
+
+        Ok(size)
+    }
+}
+
+/// Last metadata block flag, block type and length of a metadata block.
+fn read_block_header<R: Read>(reader: &mut R) -> Result<(bool, u8, u32)> {
+    let header = reader.read_u32::<BigEndian>()?;
+    let is_last = header >> 31 == 1;
+    let block_type = ((header >> 24) & 0x7F) as u8;
+    Ok((is_last, block_type, header & 0xFF_FFFF))
+}
+
+fn write_block_header<W: Write>(
+    writer: &mut W,
+    is_last: bool,
+    block_type: u8,
+    length: u32,
+) -> Result<()> {
+    if length > 0xFF_FFFF {
+        return Err(Error::InvalidData("flac metadata block too large"));
+    }
+    let header = ((is_last as u32) << 31) | (((block_type & 0x7F) as u32) << 24) | length;
+    writer.write_u32::<BigEndian>(header)?;
+    Ok(())
+}
+
+fn read_stream_info<R: Read>(reader: &mut R) -> Result<FlacStreamInfo> {
+    let min_block_size = reader.read_u16::<BigEndian>()?;
+    let max_block_size = reader.read_u16::<BigEndian>()?;
+    let min_frame_size = reader.read_u24::<BigEndian>()?;
+    let max_frame_size = reader.read_u24::<BigEndian>()?;
+    let bits = reader.read_u64::<BigEndian>()?;
+    let mut md5 = [0u8; 16];
+    reader.read_exact(&mut md5)?;
+    Ok(FlacStreamInfo {
+        min_block_size,
+        max_block_size,
+        min_frame_size,
+        max_frame_size,
+        sample_rate: (bits >> 44) as u32,
+        channels: ((bits >> 41) & 0x7) as u8 + 1,
+        bits_per_sample: ((bits >> 36) & 0x1F) as u8 + 1,
+        total_samples: bits & 0xF_FFFF_FFFF,
+        md5,
+    })
+}
+
+fn write_stream_info<W: Write>(writer: &mut W, stream_info: &FlacStreamInfo) -> Result<()> {
+    writer.write_u16::<BigEndian>(stream_info.min_block_size)?;
+    writer.write_u16::<BigEndian>(stream_info.max_block_size)?;
+    writer.write_u24::<BigEndian>(stream_info.min_frame_size & 0xFF_FFFF)?;
+    writer.write_u24::<BigEndian>(stream_info.max_frame_size & 0xFF_FFFF)?;
+    let bits = ((stream_info.sample_rate as u64 & 0xF_FFFF) << 44)
+        | ((stream_info.channels.saturating_sub(1) as u64 & 0x7) << 41)
+        | ((stream_info.bits_per_sample.saturating_sub(1) as u64 & 0x1F) << 36)
+        | (stream_info.total_samples & 0xF_FFFF_FFFF);
+    writer.write_u64::<BigEndian>(bits)?;
+    writer.write_all(&stream_info.md5)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4box::BoxHeader;
+    use std::io::Cursor;
+
+    fn config() -> FlacConfig {
+        FlacConfig {
+            stream_info: FlacStreamInfo {
+                min_block_size: 4096,
+                max_block_size: 4096,
+                min_frame_size: 14,
+                max_frame_size: 12_345,
+                sample_rate: 96000,
+                channels: 2,
+                bits_per_sample: 24,
+                total_samples: 9_600_000,
+                md5: [0xAB; 16],
+            },
+            metadata_blocks: vec![FlacMetadataBlock {
+                block_type: 4,
+                data: b"\x09\x00\x00\x00reference\x00\x00\x00\x00".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_dfla() {
+        let src_box = DflaBox::new(&config());
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+        // STREAMINFO header, then 96 kHz, 2 channels, 24 bits and 9600000
+        // samples packed in 64 bits.
+        assert_eq!(buf[12..16], [0x00, 0x00, 0x00, 0x22]);
+        assert_eq!(
+            buf[26..34],
+            [0x17, 0x70, 0x03, 0x70, 0x00, 0x92, 0x7C, 0x00]
+        );
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::DflaBox);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = DflaBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
+
+    #[test]
+    fn test_flac() {
+        let src_box = FlacBox::new(&config());
+        assert_eq!(src_box.samplerate.raw_value(), 0);
+        assert_eq!(src_box.samplesize, 24);
+
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::FlacBox);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = FlacBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
+}
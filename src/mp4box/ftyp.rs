@@ -49,13 +49,13 @@ impl Mp4Box for FtypBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for FtypBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for FtypBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let major = reader.read_u32::<BigEndian>()?;
         let minor = reader.read_u32::<BigEndian>()?;
-        if size % 4 != 0 {
+        if size < 16 || size % 4 != 0 {
             return Err(Error::InvalidData("invalid ftyp size"));
         }
         let brand_count = (size - 16) / 4; // header + major + minor
@@ -41,8 +41,8 @@ impl Mp4Box for HdlrBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for HdlrBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for HdlrBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
@@ -52,9 +52,8 @@ impl<R: Read + Seek> ReadBox<&mut R> for HdlrBox {
 
         skip_bytes(reader, 12)?; // reserved
 
-        let buf_size = size - HEADER_SIZE - HEADER_EXT_SIZE - 20 - 1;
-        let mut buf = vec![0u8; buf_size as usize];
-        reader.read_exact(&mut buf)?;
+        let buf_size = size.saturating_sub(HEADER_SIZE + HEADER_EXT_SIZE + 20 + 1);
+        let buf = read_bytes(reader, buf_size)?;
 
         let handler_string = match String::from_utf8(buf) {
             Ok(t) => {
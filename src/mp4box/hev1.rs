@@ -1,168 +1,343 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde::Serialize;
+use std::convert::TryFrom;
 use std::io::{Read, Seek, Write};
 
+use crate::mp4box::avc1::NalUnit;
 use crate::mp4box::*;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
-pub struct Hev1Box {
-    pub data_reference_index: u16,
-    pub width: u16,
-    pub height: u16,
-
-    #[serde(with = "value_u32")]
-    pub horizresolution: FixedPointU16,
+// Both HEVC sample entries are a visual sample entry with a hvcC box, they
+// only differ in where the parameter sets may be.
+macro_rules! hevc_sample_entry {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+        pub struct $name {
+            pub data_reference_index: u16,
+            pub width: u16,
+            pub height: u16,
+
+            #[serde(with = "value_u32")]
+            pub horizresolution: FixedPointU16,
+
+            #[serde(with = "value_u32")]
+            pub vertresolution: FixedPointU16,
+            pub frame_count: u16,
+            pub depth: u16,
+            pub hvcc: HvcCBox,
+
+            #[serde(skip)]
+            pub unknown_boxes: Vec<UnknownBox>,
+        }
 
-    #[serde(with = "value_u32")]
-    pub vertresolution: FixedPointU16,
-    pub frame_count: u16,
-    pub depth: u16,
-    pub hvcc: HvcCBox,
-}
+        impl Default for $name {
+            fn default() -> Self {
+                $name {
+                    data_reference_index: 0,
+                    width: 0,
+                    height: 0,
+                    horizresolution: FixedPointU16::new(0x48),
+                    vertresolution: FixedPointU16::new(0x48),
+                    frame_count: 1,
+                    depth: 0x0018,
+                    hvcc: HvcCBox::default(),
+                    unknown_boxes: Vec::new(),
+                }
+            }
+        }
 
-impl Default for Hev1Box {
-    fn default() -> Self {
-        Hev1Box {
-            data_reference_index: 0,
-            width: 0,
-            height: 0,
-            horizresolution: FixedPointU16::new(0x48),
-            vertresolution: FixedPointU16::new(0x48),
-            frame_count: 1,
-            depth: 0x0018,
-            hvcc: HvcCBox::default(),
+        impl $name {
+            pub fn new(config: &HevcConfig) -> Self {
+                $name {
+                    data_reference_index: 1,
+                    width: config.width,
+                    height: config.height,
+                    horizresolution: FixedPointU16::new(0x48),
+                    vertresolution: FixedPointU16::new(0x48),
+                    frame_count: 1,
+                    depth: 0x0018,
+                    hvcc: HvcCBox::new(config),
+                    unknown_boxes: Vec::new(),
+                }
+            }
+
+            pub fn get_type(&self) -> BoxType {
+                BoxType::$name
+            }
+
+            pub fn get_size(&self) -> u64 {
+                HEADER_SIZE + 8 + 70 + self.hvcc.box_size() + unknown_boxes_size(&self.unknown_boxes)
+            }
         }
-    }
-}
 
-impl Hev1Box {
-    pub fn new(config: &HevcConfig) -> Self {
-        Hev1Box {
-            data_reference_index: 1,
-            width: config.width,
-            height: config.height,
-            horizresolution: FixedPointU16::new(0x48),
-            vertresolution: FixedPointU16::new(0x48),
-            frame_count: 1,
-            depth: 0x0018,
-            hvcc: HvcCBox::new(),
+        impl Mp4Box for $name {
+            fn box_type(&self) -> BoxType {
+                self.get_type()
+            }
+
+            fn box_size(&self) -> u64 {
+                self.get_size()
+            }
+
+            fn to_json(&self) -> Result<String> {
+                Ok(serde_json::to_string(&self).unwrap())
+            }
+
+            fn summary(&self) -> Result<String> {
+                let s = format!(
+                    "data_reference_index={} width={} height={} frame_count={}",
+                    self.data_reference_index, self.width, self.height, self.frame_count
+                );
+                Ok(s)
+            }
         }
-    }
 
-    pub fn get_type(&self) -> BoxType {
-        BoxType::Hev1Box
-    }
+        impl ParseBox for $name {
+            fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
+                let start = box_start(reader)?;
+
+                reader.read_u32::<BigEndian>()?; // reserved
+                reader.read_u16::<BigEndian>()?; // reserved
+                let data_reference_index = reader.read_u16::<BigEndian>()?;
+
+                reader.read_u32::<BigEndian>()?; // pre-defined, reserved
+                reader.read_u64::<BigEndian>()?; // pre-defined
+                reader.read_u32::<BigEndian>()?; // pre-defined
+                let width = reader.read_u16::<BigEndian>()?;
+                let height = reader.read_u16::<BigEndian>()?;
+                let horizresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
+                let vertresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
+                reader.read_u32::<BigEndian>()?; // reserved
+                let frame_count = reader.read_u16::<BigEndian>()?;
+                skip_bytes(reader, 32)?; // compressorname
+                let depth = reader.read_u16::<BigEndian>()?;
+                reader.read_i16::<BigEndian>()?; // pre-defined
+
+                let mut hvcc = None;
+                let mut unknown_boxes = Vec::new();
+
+                let mut current = reader.stream_position()?;
+                let end = start + size;
+                // Some writers end sample entries with padding shorter than a box.
+                while end.saturating_sub(current) >= HEADER_SIZE {
+                    // Get box header.
+                    let header = BoxHeader::read_within(reader, end)?;
+                    let BoxHeader { name, size: s } = header;
+
+                    read_child(reader, name, s, |reader| {
+                        match name {
+                            BoxType::HvcCBox => {
+                                hvcc = Some(HvcCBox::parse_box(reader, s)?);
+                            }
+                            _ => {
+                                unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                            }
+                        }
+                        Ok(())
+                    })?;
+
+                    current = reader.stream_position()?;
+                }
+                let hvcc = hvcc.ok_or(Error::InvalidData("hvcc not found"))?;
+
+                skip_bytes_to(reader, start + size)?;
+
+                Ok($name {
+                    data_reference_index,
+                    width,
+                    height,
+                    horizresolution,
+                    vertresolution,
+                    frame_count,
+                    depth,
+                    hvcc,
+                    unknown_boxes,
+                })
+            }
+        }
 
-    pub fn get_size(&self) -> u64 {
-        HEADER_SIZE + 8 + 70 + self.hvcc.box_size()
-    }
+        impl<W: Write> WriteBox<&mut W> for $name {
+            fn write_box(&self, writer: &mut W) -> Result<u64> {
+                let size = self.box_size();
+                BoxHeader::new(self.box_type(), size).write(writer)?;
+
+                writer.write_u32::<BigEndian>(0)?; // reserved
+                writer.write_u16::<BigEndian>(0)?; // reserved
+                writer.write_u16::<BigEndian>(self.data_reference_index)?;
+
+                writer.write_u32::<BigEndian>(0)?; // pre-defined, reserved
+                writer.write_u64::<BigEndian>(0)?; // pre-defined
+                writer.write_u32::<BigEndian>(0)?; // pre-defined
+                writer.write_u16::<BigEndian>(self.width)?;
+                writer.write_u16::<BigEndian>(self.height)?;
+                writer.write_u32::<BigEndian>(self.horizresolution.raw_value())?;
+                writer.write_u32::<BigEndian>(self.vertresolution.raw_value())?;
+                writer.write_u32::<BigEndian>(0)?; // reserved
+                writer.write_u16::<BigEndian>(self.frame_count)?;
+                // skip compressorname
+                write_zeros(writer, 32)?;
+                writer.write_u16::<BigEndian>(self.depth)?;
+                writer.write_i16::<BigEndian>(-1)?; // pre-defined
+
+                let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+                children.write(&self.hvcc)?;
+                children.finish()?;
+
+                Ok(size)
+            }
+        }
+    };
 }
 
-impl Mp4Box for Hev1Box {
-    fn box_type(&self) -> BoxType {
-        self.get_type()
-    }
+hevc_sample_entry! {
+    /// HEVC sample entry whose parameter sets may also be in the samples.
+    Hev1Box
+}
 
-    fn box_size(&self) -> u64 {
-        self.get_size()
-    }
+hevc_sample_entry! {
+    /// HEVC sample entry with all the parameter sets in its hvcC box.
+    Hvc1Box
+}
 
-    fn to_json(&self) -> Result<String> {
-        Ok(serde_json::to_string(&self).unwrap())
-    }
+/// HEVCDecoderConfigurationRecord, see ISO/IEC 14496-15 8.3.3.1.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct HvcCBox {
+    pub configuration_version: u8,
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+
+    /// The 48 bits of the general constraint indicator flags.
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+    pub min_spatial_segmentation_idc: u16,
+    pub parallelism_type: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    pub length_size_minus_one: u8,
+    pub arrays: Vec<HvcCArray>,
+}
 
-    fn summary(&self) -> Result<String> {
-        let s = format!(
-            "data_reference_index={} width={} height={} frame_count={}",
-            self.data_reference_index, self.width, self.height, self.frame_count
-        );
-        Ok(s)
-    }
+/// NAL units of one type in a [`HvcCBox`].
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
+pub struct HvcCArray {
+    /// Whether all the NAL units of this type are in the array, rather than
+    /// some being in the samples.
+    pub completeness: bool,
+    pub nal_unit_type: u8,
+    pub nalus: Vec<NalUnit>,
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for Hev1Box {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
-        let start = box_start(reader)?;
+// Types of the NAL units of the parameter sets, see ISO/IEC 23008-2 7.4.2.2.
+pub(crate) const VPS_NAL_UNIT_TYPE: u8 = 32;
+pub(crate) const SPS_NAL_UNIT_TYPE: u8 = 33;
+pub(crate) const PPS_NAL_UNIT_TYPE: u8 = 34;
 
-        reader.read_u32::<BigEndian>()?; // reserved
-        reader.read_u16::<BigEndian>()?; // reserved
-        let data_reference_index = reader.read_u16::<BigEndian>()?;
-
-        reader.read_u32::<BigEndian>()?; // pre-defined, reserved
-        reader.read_u64::<BigEndian>()?; // pre-defined
-        reader.read_u32::<BigEndian>()?; // pre-defined
-        let width = reader.read_u16::<BigEndian>()?;
-        let height = reader.read_u16::<BigEndian>()?;
-        let horizresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
-        let vertresolution = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
-        reader.read_u32::<BigEndian>()?; // reserved
-        let frame_count = reader.read_u16::<BigEndian>()?;
-        skip_bytes(reader, 32)?; // compressorname
-        let depth = reader.read_u16::<BigEndian>()?;
-        reader.read_i16::<BigEndian>()?; // pre-defined
-
-        let header = BoxHeader::read(reader)?;
-        let BoxHeader { name, size: s } = header;
-        if name == BoxType::HvcCBox {
-            let hvcc = HvcCBox::read_box(reader, s)?;
-
-            skip_bytes_to(reader, start + size)?;
-
-            Ok(Hev1Box {
-                data_reference_index,
-                width,
-                height,
-                horizresolution,
-                vertresolution,
-                frame_count,
-                depth,
-                hvcc,
-            })
-        } else {
-            Err(Error::InvalidData("hvcc not found"))
+impl HvcCBox {
+    /// Configuration with the parameter sets of `config`, the profile, level
+    /// and format being taken from its SPS if it can be parsed.
+    pub fn new(config: &HevcConfig) -> Self {
+        let mut hvcc = Self {
+            configuration_version: 1,
+            chroma_format_idc: 1,
+            length_size_minus_one: 3,
+            ..Default::default()
+        };
+        let parameter_sets = [
+            (VPS_NAL_UNIT_TYPE, &config.video_param_set),
+            (SPS_NAL_UNIT_TYPE, &config.seq_param_set),
+            (PPS_NAL_UNIT_TYPE, &config.pic_param_set),
+        ];
+        for (nal_unit_type, bytes) in parameter_sets {
+            if !bytes.is_empty() {
+                hvcc.arrays.push(HvcCArray {
+                    completeness: config.parameter_sets == HevcParameterSets::OutOfBand,
+                    nal_unit_type,
+                    nalus: vec![NalUnit::from(&bytes[..])],
+                });
+            }
         }
+        hvcc.read_sps(&config.seq_param_set);
+        hvcc
     }
-}
-
-impl<W: Write> WriteBox<&mut W> for Hev1Box {
-    fn write_box(&self, writer: &mut W) -> Result<u64> {
-        let size = self.box_size();
-        BoxHeader::new(self.box_type(), size).write(writer)?;
-
-        writer.write_u32::<BigEndian>(0)?; // reserved
-        writer.write_u16::<BigEndian>(0)?; // reserved
-        writer.write_u16::<BigEndian>(self.data_reference_index)?;
-
-        writer.write_u32::<BigEndian>(0)?; // pre-defined, reserved
-        writer.write_u64::<BigEndian>(0)?; // pre-defined
-        writer.write_u32::<BigEndian>(0)?; // pre-defined
-        writer.write_u16::<BigEndian>(self.width)?;
-        writer.write_u16::<BigEndian>(self.height)?;
-        writer.write_u32::<BigEndian>(self.horizresolution.raw_value())?;
-        writer.write_u32::<BigEndian>(self.vertresolution.raw_value())?;
-        writer.write_u32::<BigEndian>(0)?; // reserved
-        writer.write_u16::<BigEndian>(self.frame_count)?;
-        // skip compressorname
-        write_zeros(writer, 32)?;
-        writer.write_u16::<BigEndian>(self.depth)?;
-        writer.write_i16::<BigEndian>(-1)?; // pre-defined
-
-        self.hvcc.write_box(writer)?;
 
-        Ok(size)
+    /// First NAL unit of a type, such as a parameter set.
+    pub fn nal_unit(&self, nal_unit_type: u8) -> Option<&[u8]> {
+        self.arrays
+            .iter()
+            .filter(|array| array.nal_unit_type == nal_unit_type)
+            .flat_map(|array| array.nalus.iter())
+            .map(|nalu| nalu.bytes.as_ref())
+            .next()
     }
-}
 
-#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
-pub struct HvcCBox {
-    pub configuration_version: u8,
-}
+    /// Set the fields given by an SPS, up to the bit depths, see ISO/IEC
+    /// 23008-2 7.3.2.2.1. Nothing is set if the SPS is cut short.
+    fn read_sps(&mut self, sps: &[u8]) -> Option<()> {
+        let rbsp = rbsp(sps.get(2..)?);
+        let mut bits = BitReader::new(&rbsp);
+        bits.read(4)?; // sps_video_parameter_set_id
+        let max_sub_layers_minus1 = bits.read(3)? as usize;
+        let temporal_id_nested = bits.read(1)? == 1;
+
+        // profile_tier_level
+        let general_profile_space = bits.read(2)? as u8;
+        let general_tier_flag = bits.read(1)? == 1;
+        let general_profile_idc = bits.read(5)? as u8;
+        let general_profile_compatibility_flags = bits.read(32)? as u32;
+        let general_constraint_indicator_flags = bits.read(48)?;
+        let general_level_idc = bits.read(8)? as u8;
+        let mut sub_layers = [(false, false); 7];
+        for sub_layer in sub_layers.iter_mut().take(max_sub_layers_minus1) {
+            *sub_layer = (bits.read(1)? == 1, bits.read(1)? == 1);
+        }
+        if max_sub_layers_minus1 > 0 {
+            bits.read(2 * (8 - max_sub_layers_minus1 as u32))?; // reserved_zero_2bits
+        }
+        for &(profile_present, level_present) in sub_layers.iter() {
+            if profile_present {
+                bits.read(44)?;
+                bits.read(44)?;
+            }
+            if level_present {
+                bits.read(8)?;
+            }
+        }
 
-impl HvcCBox {
-    pub fn new() -> Self {
-        Self {
-            configuration_version: 1,
+        bits.read_ue()?; // sps_seq_parameter_set_id
+        let chroma_format_idc = bits.read_ue()?;
+        if chroma_format_idc == 3 {
+            bits.read(1)?; // separate_colour_plane_flag
+        }
+        bits.read_ue()?; // pic_width_in_luma_samples
+        bits.read_ue()?; // pic_height_in_luma_samples
+        if bits.read(1)? == 1 {
+            // conf_win_left/right/top/bottom_offset
+            for _ in 0..4 {
+                bits.read_ue()?;
+            }
         }
+        let bit_depth_luma_minus8 = bits.read_ue()?;
+        let bit_depth_chroma_minus8 = bits.read_ue()?;
+
+        self.general_profile_space = general_profile_space;
+        self.general_tier_flag = general_tier_flag;
+        self.general_profile_idc = general_profile_idc;
+        self.general_profile_compatibility_flags = general_profile_compatibility_flags;
+        self.general_constraint_indicator_flags = general_constraint_indicator_flags;
+        self.general_level_idc = general_level_idc;
+        self.chroma_format_idc = (chroma_format_idc & 0x3) as u8;
+        self.bit_depth_luma_minus8 = (bit_depth_luma_minus8 & 0x7) as u8;
+        self.bit_depth_chroma_minus8 = (bit_depth_chroma_minus8 & 0x7) as u8;
+        self.num_temporal_layers = max_sub_layers_minus1 as u8 + 1;
+        self.temporal_id_nested = temporal_id_nested;
+        Some(())
     }
 }
 
@@ -172,7 +347,14 @@ impl Mp4Box for HvcCBox {
     }
 
     fn box_size(&self) -> u64 {
-        HEADER_SIZE + 1
+        let mut size = HEADER_SIZE + 23;
+        for array in self.arrays.iter() {
+            size += 3;
+            for nalu in array.nalus.iter() {
+                size += nalu.size() as u64;
+            }
+        }
+        size
     }
 
     fn to_json(&self) -> Result<String> {
@@ -180,21 +362,89 @@ impl Mp4Box for HvcCBox {
     }
 
     fn summary(&self) -> Result<String> {
-        let s = format!("configuration_version={}", self.configuration_version);
+        let s = format!(
+            "general_profile_idc={} general_level_idc={} arrays={}",
+            self.general_profile_idc,
+            self.general_level_idc,
+            self.arrays.len()
+        );
         Ok(s)
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for HvcCBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for HvcCBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
+        let end = start + size;
 
         let configuration_version = reader.read_u8()?;
 
-        skip_bytes_to(reader, start + size)?;
+        // Older versions of this crate wrote only the configuration version.
+        if size < HEADER_SIZE + 23 {
+            skip_bytes_to(reader, end)?;
+            return Ok(HvcCBox {
+                configuration_version,
+                ..Default::default()
+            });
+        }
+
+        let byte = reader.read_u8()?;
+        let general_profile_space = byte >> 6;
+        let general_tier_flag = (byte >> 5) & 0x1 == 1;
+        let general_profile_idc = byte & 0x1F;
+        let general_profile_compatibility_flags = reader.read_u32::<BigEndian>()?;
+        let general_constraint_indicator_flags = reader.read_u48::<BigEndian>()?;
+        let general_level_idc = reader.read_u8()?;
+        let min_spatial_segmentation_idc = reader.read_u16::<BigEndian>()? & 0x0FFF;
+        let parallelism_type = reader.read_u8()? & 0x3;
+        let chroma_format_idc = reader.read_u8()? & 0x3;
+        let bit_depth_luma_minus8 = reader.read_u8()? & 0x7;
+        let bit_depth_chroma_minus8 = reader.read_u8()? & 0x7;
+        let avg_frame_rate = reader.read_u16::<BigEndian>()?;
+        let byte = reader.read_u8()?;
+        let constant_frame_rate = byte >> 6;
+        let num_temporal_layers = (byte >> 3) & 0x7;
+        let temporal_id_nested = (byte >> 2) & 0x1 == 1;
+        let length_size_minus_one = byte & 0x3;
+
+        let num_of_arrays = reader.read_u8()?;
+        let mut arrays = Vec::new();
+        for _ in 0..num_of_arrays {
+            let byte = reader.read_u8()?;
+            let num_nalus = reader.read_u16::<BigEndian>()?;
+            check_entry_count(reader, end, num_nalus as u32, 2)?;
+            let mut nalus = Vec::with_capacity(num_nalus as usize);
+            for _ in 0..num_nalus {
+                nalus.push(NalUnit::read(reader)?);
+            }
+            arrays.push(HvcCArray {
+                completeness: byte >> 7 == 1,
+                nal_unit_type: byte & 0x3F,
+                nalus,
+            });
+        }
+
+        skip_bytes_to(reader, end)?;
 
         Ok(HvcCBox {
             configuration_version,
+            general_profile_space,
+            general_tier_flag,
+            general_profile_idc,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags,
+            general_level_idc,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate,
+            num_temporal_layers,
+            temporal_id_nested,
+            length_size_minus_one,
+            arrays,
         })
     }
 }
@@ -205,16 +455,156 @@ impl<W: Write> WriteBox<&mut W> for HvcCBox {
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
         writer.write_u8(self.configuration_version)?;
+        writer.write_u8(
+            (self.general_profile_space << 6)
+                | ((self.general_tier_flag as u8) << 5)
+                | (self.general_profile_idc & 0x1F),
+        )?;
+        writer.write_u32::<BigEndian>(self.general_profile_compatibility_flags)?;
+        writer
+            .write_u48::<BigEndian>(self.general_constraint_indicator_flags & 0xFFFF_FFFF_FFFF)?;
+        writer.write_u8(self.general_level_idc)?;
+        writer.write_u16::<BigEndian>(self.min_spatial_segmentation_idc | 0xF000)?;
+        writer.write_u8(self.parallelism_type | 0xFC)?;
+        writer.write_u8(self.chroma_format_idc | 0xFC)?;
+        writer.write_u8(self.bit_depth_luma_minus8 | 0xF8)?;
+        writer.write_u8(self.bit_depth_chroma_minus8 | 0xF8)?;
+        writer.write_u16::<BigEndian>(self.avg_frame_rate)?;
+        writer.write_u8(
+            (self.constant_frame_rate << 6)
+                | ((self.num_temporal_layers & 0x7) << 3)
+                | ((self.temporal_id_nested as u8) << 2)
+                | (self.length_size_minus_one & 0x3),
+        )?;
+        writer.write_u8(self.arrays.len() as u8)?;
+        for array in self.arrays.iter() {
+            writer.write_u8(((array.completeness as u8) << 7) | (array.nal_unit_type & 0x3F))?;
+            writer.write_u16::<BigEndian>(array.nalus.len() as u16)?;
+            for nalu in array.nalus.iter() {
+                nalu.write(writer)?;
+            }
+        }
         Ok(size)
     }
 }
 
+/// Payload of a NAL unit without its emulation prevention bytes.
+fn rbsp(payload: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(payload.len());
+    let mut zeros = 0;
+    for &byte in payload {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+/// Reader of the bits of an RBSP, most significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Read up to 64 bits.
+    fn read(&mut self, count: u32) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.data.get(self.pos / 8)?;
+            value = (value << 1) | ((byte >> (7 - self.pos % 8)) & 1) as u64;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    /// Read an unsigned Exp-Golomb code.
+    fn read_ue(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while self.read(1)? == 0 {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return None;
+            }
+        }
+        let value = (1u64 << leading_zeros) - 1 + self.read(leading_zeros)?;
+        u32::try_from(value).ok()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::mp4box::BoxHeader;
     use std::io::Cursor;
 
+    const VPS: &[u8] = &[
+        0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+        0x03, 0x00, 0x00, 0x03, 0x00, 0x3C, 0x95, 0x98, 0x09,
+    ];
+    // Main profile, level 2, 4:2:0, 8 bits, with emulation prevention bytes.
+    const SPS: &[u8] = &[
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x3C, 0xA0, 0x0A, 0x08, 0x0F, 0x17,
+    ];
+    const PPS: &[u8] = &[0x44, 0x01, 0xC1, 0x72, 0xB4, 0x62, 0x40];
+
+    #[test]
+    fn test_hvcc_from_sps() {
+        let hvcc = HvcCBox::new(&HevcConfig {
+            seq_param_set: SPS.to_vec(),
+            pic_param_set: PPS.to_vec(),
+            ..Default::default()
+        });
+        assert_eq!(hvcc.general_profile_space, 0);
+        assert!(!hvcc.general_tier_flag);
+        assert_eq!(hvcc.general_profile_idc, 1);
+        assert_eq!(hvcc.general_profile_compatibility_flags, 0x6000_0000);
+        assert_eq!(hvcc.general_constraint_indicator_flags, 0x9000_0000_0000);
+        assert_eq!(hvcc.general_level_idc, 60);
+        assert_eq!(hvcc.chroma_format_idc, 1);
+        assert_eq!(hvcc.bit_depth_luma_minus8, 0);
+        assert_eq!(hvcc.bit_depth_chroma_minus8, 0);
+        assert_eq!(hvcc.num_temporal_layers, 1);
+        assert!(hvcc.temporal_id_nested);
+        assert_eq!(hvcc.length_size_minus_one, 3);
+
+        let types: Vec<_> = hvcc
+            .arrays
+            .iter()
+            .map(|array| array.nal_unit_type)
+            .collect();
+        assert_eq!(types, [SPS_NAL_UNIT_TYPE, PPS_NAL_UNIT_TYPE]);
+        assert_eq!(hvcc.nal_unit(SPS_NAL_UNIT_TYPE), Some(SPS));
+        assert_eq!(hvcc.nal_unit(VPS_NAL_UNIT_TYPE), None);
+
+        // Only the parameter sets are kept from an SPS cut short.
+        let hvcc = HvcCBox::new(&HevcConfig {
+            seq_param_set: SPS[..10].to_vec(),
+            ..Default::default()
+        });
+        assert_eq!(hvcc.general_profile_idc, 0);
+        assert_eq!(hvcc.nal_unit(SPS_NAL_UNIT_TYPE), Some(&SPS[..10]));
+    }
+
+    #[test]
+    fn test_hvcc_configuration_version_only() {
+        let buf = [0, 0, 0, 9, b'h', b'v', b'c', b'C', 1];
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let hvcc = HvcCBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(hvcc.configuration_version, 1);
+        assert!(hvcc.arrays.is_empty());
+        assert_eq!(reader.position(), 9);
+    }
+
     #[test]
     fn test_hev1() {
         let src_box = Hev1Box {
@@ -225,9 +615,15 @@ mod tests {
             vertresolution: FixedPointU16::new(0x48),
             frame_count: 1,
             depth: 24,
-            hvcc: HvcCBox {
-                configuration_version: 1,
-            },
+            hvcc: HvcCBox::new(&HevcConfig {
+                width: 320,
+                height: 240,
+                video_param_set: VPS.to_vec(),
+                seq_param_set: SPS.to_vec(),
+                pic_param_set: PPS.to_vec(),
+                ..Default::default()
+            }),
+            unknown_boxes: Vec::new(),
         };
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
@@ -241,4 +637,30 @@ mod tests {
         let dst_box = Hev1Box::read_box(&mut reader, header.size).unwrap();
         assert_eq!(src_box, dst_box);
     }
+
+    #[test]
+    fn test_hvc1() {
+        let config = HevcConfig {
+            width: 320,
+            height: 240,
+            video_param_set: VPS.to_vec(),
+            seq_param_set: SPS.to_vec(),
+            pic_param_set: PPS.to_vec(),
+            parameter_sets: HevcParameterSets::OutOfBand,
+        };
+        let src_box = Hvc1Box::new(&config);
+        assert!(src_box.hvcc.arrays.iter().all(|array| array.completeness));
+
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::Hvc1Box);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = Hvc1Box::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
 }
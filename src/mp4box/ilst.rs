@@ -11,6 +11,9 @@ use crate::mp4box::*;
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct IlstBox {
     pub items: HashMap<MetadataKey, IlstItemBox>,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl IlstBox {
@@ -23,8 +26,16 @@ impl IlstBox {
         for item in self.items.values() {
             size += item.get_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
+
+    /// Items in the order they are written, by box type.
+    pub(crate) fn sorted_items(&self) -> Vec<(&MetadataKey, &IlstItemBox)> {
+        let mut items: Vec<_> = self.items.iter().collect();
+        items.sort_by_key(|(key, _)| u32::from(item_box_type(key)));
+        items
+    }
 }
 
 impl Mp4Box for IlstBox {
@@ -46,44 +57,50 @@ impl Mp4Box for IlstBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for IlstBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for IlstBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut items = HashMap::new();
+        let mut unknown_boxes = Vec::new();
 
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::NameBox => {
-                    items.insert(MetadataKey::Title, IlstItemBox::read_box(reader, s)?);
-                }
-                BoxType::DayBox => {
-                    items.insert(MetadataKey::Year, IlstItemBox::read_box(reader, s)?);
-                }
-                BoxType::CovrBox => {
-                    items.insert(MetadataKey::Poster, IlstItemBox::read_box(reader, s)?);
-                }
-                BoxType::DescBox => {
-                    items.insert(MetadataKey::Summary, IlstItemBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::NameBox => {
+                        items.insert(MetadataKey::Title, IlstItemBox::parse_box(reader, s)?);
+                    }
+                    BoxType::DayBox => {
+                        items.insert(MetadataKey::Year, IlstItemBox::parse_box(reader, s)?);
+                    }
+                    BoxType::CovrBox => {
+                        items.insert(MetadataKey::Poster, IlstItemBox::parse_box(reader, s)?);
+                    }
+                    BoxType::DescBox => {
+                        items.insert(MetadataKey::Summary, IlstItemBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
 
         skip_bytes_to(reader, start + size)?;
 
-        Ok(IlstBox { items })
+        Ok(IlstBox {
+            items,
+            unknown_boxes,
+        })
     }
 }
 
@@ -92,16 +109,18 @@ impl<W: Write> WriteBox<&mut W> for IlstBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        for (key, value) in &self.items {
-            let name = match key {
-                MetadataKey::Title => BoxType::NameBox,
-                MetadataKey::Year => BoxType::DayBox,
-                MetadataKey::Poster => BoxType::CovrBox,
-                MetadataKey::Summary => BoxType::DescBox,
-            };
-            BoxHeader::new(name, value.get_size()).write(writer)?;
-            value.data.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        for (key, value) in self.sorted_items() {
+            children.write_with(|writer| {
+                let size = value.get_size();
+                BoxHeader::new(item_box_type(key), size).write(writer)?;
+                let mut children = ChildWriter::new(writer, &value.unknown_boxes);
+                children.write(&value.data)?;
+                children.finish()?;
+                Ok(size)
+            })?;
         }
+        children.finish()?;
         Ok(size)
     }
 }
@@ -109,36 +128,42 @@ impl<W: Write> WriteBox<&mut W> for IlstBox {
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct IlstItemBox {
     pub data: DataBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl IlstItemBox {
-    fn get_size(&self) -> u64 {
-        HEADER_SIZE + self.data.box_size()
+    pub(crate) fn get_size(&self) -> u64 {
+        HEADER_SIZE + self.data.box_size() + unknown_boxes_size(&self.unknown_boxes)
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for IlstItemBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for IlstItemBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut data = None;
+        let mut unknown_boxes = Vec::new();
 
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::DataBox => {
-                    data = Some(DataBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::DataBox => {
+                        data = Some(DataBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -151,12 +176,23 @@ impl<R: Read + Seek> ReadBox<&mut R> for IlstItemBox {
 
         Ok(IlstItemBox {
             data: data.unwrap(),
+            unknown_boxes,
         })
     }
 }
 
+/// Type of the box of an item.
+pub(crate) fn item_box_type(key: &MetadataKey) -> BoxType {
+    match key {
+        MetadataKey::Title => BoxType::NameBox,
+        MetadataKey::Year => BoxType::DayBox,
+        MetadataKey::Poster => BoxType::CovrBox,
+        MetadataKey::Summary => BoxType::DescBox,
+    }
+}
+
 impl<'a> Metadata<'a> for IlstBox {
-    fn title(&self) -> Option<Cow<str>> {
+    fn title(&self) -> Option<Cow<'_, str>> {
         self.items.get(&MetadataKey::Title).map(item_to_str)
     }
 
@@ -168,7 +204,7 @@ impl<'a> Metadata<'a> for IlstBox {
         self.items.get(&MetadataKey::Poster).map(item_to_bytes)
     }
 
-    fn summary(&self) -> Option<Cow<str>> {
+    fn summary(&self) -> Option<Cow<'_, str>> {
         self.items.get(&MetadataKey::Summary).map(item_to_str)
     }
 }
@@ -177,7 +213,7 @@ fn item_to_bytes(item: &IlstItemBox) -> &[u8] {
     &item.data.data
 }
 
-fn item_to_str(item: &IlstItemBox) -> Cow<str> {
+fn item_to_str(item: &IlstItemBox) -> Cow<'_, str> {
     String::from_utf8_lossy(&item.data.data)
 }
 
@@ -202,6 +238,7 @@ mod tests {
                 data_type: DataType::Text,
                 data: b"test_year".to_vec(),
             },
+            ..Default::default()
         };
         let src_box = IlstBox {
             items: [
@@ -211,6 +248,7 @@ mod tests {
                 (MetadataKey::Summary, IlstItemBox::default()),
             ]
             .into(),
+            ..Default::default()
         };
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
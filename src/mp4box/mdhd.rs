@@ -70,8 +70,8 @@ impl Mp4Box for MdhdBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MdhdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MdhdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
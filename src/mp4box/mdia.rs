@@ -9,6 +9,8 @@ pub struct MdiaBox {
     pub mdhd: MdhdBox,
     pub hdlr: HdlrBox,
     pub minf: MinfBox,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl MdiaBox {
@@ -17,7 +19,11 @@ impl MdiaBox {
     }
 
     pub fn get_size(&self) -> u64 {
-        HEADER_SIZE + self.mdhd.box_size() + self.hdlr.box_size() + self.minf.box_size()
+        HEADER_SIZE
+            + self.mdhd.box_size()
+            + self.hdlr.box_size()
+            + self.minf.box_size()
+            + unknown_boxes_size(&self.unknown_boxes)
     }
 }
 
@@ -40,36 +46,39 @@ impl Mp4Box for MdiaBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MdiaBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MdiaBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut mdhd = None;
         let mut hdlr = None;
         let mut minf = None;
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::MdhdBox => {
-                    mdhd = Some(MdhdBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::MdhdBox => {
+                        mdhd = Some(MdhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::HdlrBox => {
+                        hdlr = Some(HdlrBox::parse_box(reader, s)?);
+                    }
+                    BoxType::MinfBox => {
+                        minf = Some(MinfBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::HdlrBox => {
-                    hdlr = Some(HdlrBox::read_box(reader, s)?);
-                }
-                BoxType::MinfBox => {
-                    minf = Some(MinfBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -90,6 +99,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for MdiaBox {
             mdhd: mdhd.unwrap(),
             hdlr: hdlr.unwrap(),
             minf: minf.unwrap(),
+            unknown_boxes,
         })
     }
 }
@@ -99,9 +109,11 @@ impl<W: Write> WriteBox<&mut W> for MdiaBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.mdhd.write_box(writer)?;
-        self.hdlr.write_box(writer)?;
-        self.minf.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.mdhd)?;
+        children.write(&self.hdlr)?;
+        children.write(&self.minf)?;
+        children.finish()?;
 
         Ok(size)
     }
@@ -47,8 +47,8 @@ impl Mp4Box for MehdBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MehdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MehdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
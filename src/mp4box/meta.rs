@@ -13,6 +13,9 @@ pub enum MetaBox {
     Mdir {
         #[serde(skip_serializing_if = "Option::is_none")]
         ilst: Option<IlstBox>,
+
+        #[serde(skip)]
+        unknown_boxes: Vec<UnknownBox>,
     },
 
     #[serde(skip)]
@@ -27,19 +30,38 @@ pub enum MetaBox {
 
 const MDIR: FourCC = FourCC { value: *b"mdir" };
 
+static MDIR_HDLR: HdlrBox = HdlrBox {
+    version: 0,
+    flags: 0,
+    handler_type: MDIR,
+    name: String::new(),
+};
+
 impl MetaBox {
     pub fn get_type(&self) -> BoxType {
         BoxType::MetaBox
     }
 
+    /// Handler of the meta box, written before its other children.
+    pub fn hdlr(&self) -> &HdlrBox {
+        match self {
+            Self::Mdir { .. } => &MDIR_HDLR,
+            Self::Unknown { hdlr, .. } => hdlr,
+        }
+    }
+
     pub fn get_size(&self) -> u64 {
         let mut size = HEADER_SIZE + HEADER_EXT_SIZE;
         match self {
-            Self::Mdir { ilst } => {
-                size += HdlrBox::default().box_size();
+            Self::Mdir {
+                ilst,
+                unknown_boxes,
+            } => {
+                size += MDIR_HDLR.box_size();
                 if let Some(ilst) = ilst {
                     size += ilst.box_size();
                 }
+                size += unknown_boxes_size(unknown_boxes);
             }
             Self::Unknown { hdlr, data } => size += hdlr.box_size() + data.len() as u64,
         }
@@ -80,8 +102,8 @@ impl Default for MetaBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MetaBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MetaBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, _) = read_box_header_ext(reader)?;
@@ -89,13 +111,19 @@ impl<R: Read + Seek> ReadBox<&mut R> for MetaBox {
             return Err(Error::UnsupportedBoxVersion(BoxType::UdtaBox, version));
         }
 
-        let hdlr_header = BoxHeader::read(reader)?;
+        let hdlr_header = BoxHeader::read_within(reader, start + size)?;
         if hdlr_header.name != BoxType::HdlrBox {
             return Err(Error::BoxNotFound(BoxType::HdlrBox));
         }
-        let hdlr = HdlrBox::read_box(reader, hdlr_header.size)?;
+        let mut hdlr = None;
+        read_child(reader, hdlr_header.name, hdlr_header.size, |reader| {
+            hdlr = Some(HdlrBox::parse_box(reader, hdlr_header.size)?);
+            Ok(())
+        })?;
+        let hdlr = hdlr.ok_or(Error::BoxNotFound(BoxType::HdlrBox))?;
 
         let mut ilst = None;
+        let mut unknown_boxes = Vec::new();
 
         let mut current = reader.stream_position()?;
         let end = start + size;
@@ -104,27 +132,31 @@ impl<R: Read + Seek> ReadBox<&mut R> for MetaBox {
             MDIR => {
                 while current < end {
                     // Get box header.
-                    let header = BoxHeader::read(reader)?;
+                    let header = BoxHeader::read_within(reader, start + size)?;
                     let BoxHeader { name, size: s } = header;
 
-                    match name {
-                        BoxType::IlstBox => {
-                            ilst = Some(IlstBox::read_box(reader, s)?);
+                    read_child(reader, name, s, |reader| {
+                        match name {
+                            BoxType::IlstBox => {
+                                ilst = Some(IlstBox::parse_box(reader, s)?);
+                            }
+                            _ => {
+                                unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                            }
                         }
-                        _ => {
-                            // XXX warn!()
-                            skip_box(reader, s)?;
-                        }
-                    }
+                        Ok(())
+                    })?;
 
                     current = reader.stream_position()?;
                 }
 
-                Ok(MetaBox::Mdir { ilst })
+                Ok(MetaBox::Mdir {
+                    ilst,
+                    unknown_boxes,
+                })
             }
             _ => {
-                let mut data = vec![0u8; (end - current) as usize];
-                reader.read_exact(&mut data)?;
+                let data = read_bytes(reader, end.saturating_sub(current))?;
 
                 Ok(MetaBox::Unknown { hdlr, data })
             }
@@ -139,22 +171,22 @@ impl<W: Write> WriteBox<&mut W> for MetaBox {
 
         write_box_header_ext(writer, 0, 0)?;
 
-        let hdlr = match self {
-            Self::Mdir { .. } => HdlrBox {
-                handler_type: MDIR,
-                ..Default::default()
-            },
-            Self::Unknown { hdlr, .. } => hdlr.clone(),
-        };
-        hdlr.write_box(writer)?;
-
         match self {
-            Self::Mdir { ilst } => {
+            Self::Mdir {
+                ilst,
+                unknown_boxes,
+            } => {
+                let mut children = ChildWriter::new(writer, unknown_boxes);
+                children.write(self.hdlr())?;
                 if let Some(ilst) = ilst {
-                    ilst.write_box(writer)?;
+                    children.write(ilst)?;
                 }
+                children.finish()?;
+            }
+            Self::Unknown { hdlr, data } => {
+                hdlr.write_box(writer)?;
+                writer.write_all(data)?;
             }
-            Self::Unknown { data, .. } => writer.write_all(data)?,
         }
         Ok(size)
     }
@@ -168,7 +200,10 @@ mod tests {
 
     #[test]
     fn test_meta_mdir_empty() {
-        let src_box = MetaBox::Mdir { ilst: None };
+        let src_box = MetaBox::Mdir {
+            ilst: None,
+            unknown_boxes: Vec::new(),
+        };
 
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
@@ -187,6 +222,7 @@ mod tests {
     fn test_meta_mdir() {
         let src_box = MetaBox::Mdir {
             ilst: Some(IlstBox::default()),
+            unknown_boxes: Vec::new(),
         };
 
         let mut buf = Vec::new();
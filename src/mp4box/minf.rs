@@ -14,6 +14,8 @@ pub struct MinfBox {
 
     pub dinf: DinfBox,
     pub stbl: StblBox,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl MinfBox {
@@ -31,6 +33,7 @@ impl MinfBox {
         }
         size += self.dinf.box_size();
         size += self.stbl.box_size();
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -54,8 +57,8 @@ impl Mp4Box for MinfBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MinfBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MinfBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut vmhd = None;
@@ -63,31 +66,34 @@ impl<R: Read + Seek> ReadBox<&mut R> for MinfBox {
         let mut dinf = None;
         let mut stbl = None;
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::VmhdBox => {
-                    vmhd = Some(VmhdBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::VmhdBox => {
+                        vmhd = Some(VmhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::SmhdBox => {
+                        smhd = Some(SmhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::DinfBox => {
+                        dinf = Some(DinfBox::parse_box(reader, s)?);
+                    }
+                    BoxType::StblBox => {
+                        stbl = Some(StblBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::SmhdBox => {
-                    smhd = Some(SmhdBox::read_box(reader, s)?);
-                }
-                BoxType::DinfBox => {
-                    dinf = Some(DinfBox::read_box(reader, s)?);
-                }
-                BoxType::StblBox => {
-                    stbl = Some(StblBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -106,6 +112,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for MinfBox {
             smhd,
             dinf: dinf.unwrap(),
             stbl: stbl.unwrap(),
+            unknown_boxes,
         })
     }
 }
@@ -115,14 +122,16 @@ impl<W: Write> WriteBox<&mut W> for MinfBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(ref vmhd) = self.vmhd {
-            vmhd.write_box(writer)?;
+            children.write(vmhd)?;
         }
         if let Some(ref smhd) = self.smhd {
-            smhd.write_box(writer)?;
+            children.write(smhd)?;
         }
-        self.dinf.write_box(writer)?;
-        self.stbl.write_box(writer)?;
+        children.write(&self.dinf)?;
+        children.write(&self.stbl)?;
+        children.finish()?;
 
         Ok(size)
     }
@@ -27,7 +27,11 @@
 //!                     stsd
 //!                         avc1
 //!                         hev1
+//!                         hvc1
+//!                         av01
 //!                         mp4a
+//!                         Opus
+//!                         fLaC
 //!                         tx3g
 //!                     stts
 //!                     stsc
@@ -62,14 +66,18 @@ use std::io::{Read, Seek, SeekFrom, Write};
 
 use crate::*;
 
+pub(crate) mod anybox;
+pub(crate) mod av01;
 pub(crate) mod avc1;
 pub(crate) mod co64;
+mod context;
 pub(crate) mod ctts;
 pub(crate) mod data;
 pub(crate) mod dinf;
 pub(crate) mod edts;
 pub(crate) mod elst;
 pub(crate) mod emsg;
+pub(crate) mod flac;
 pub(crate) mod ftyp;
 pub(crate) mod hdlr;
 pub(crate) mod hev1;
@@ -85,6 +93,7 @@ pub(crate) mod moov;
 pub(crate) mod mp4a;
 pub(crate) mod mvex;
 pub(crate) mod mvhd;
+pub(crate) mod opus;
 pub(crate) mod smhd;
 pub(crate) mod stbl;
 pub(crate) mod stco;
@@ -106,10 +115,13 @@ pub(crate) mod vmhd;
 pub(crate) mod vp09;
 pub(crate) mod vpcc;
 
+pub use anybox::{AnyBox, BoxNode, BoxWalk};
+pub(crate) use context::*;
 pub use emsg::EmsgBox;
 pub use ftyp::FtypBox;
 pub use moof::MoofBox;
 pub use moov::MoovBox;
+pub use stsd::SampleEntry;
 
 pub const HEADER_SIZE: u64 = 8;
 // const HEADER_LARGE_SIZE: u64 = 16;
@@ -117,7 +129,7 @@ pub const HEADER_EXT_SIZE: u64 = 4;
 
 macro_rules! boxtype {
     ($( $name:ident => $value:expr ),*) => {
-        #[derive(Clone, Copy, PartialEq, Eq)]
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
         pub enum BoxType {
             $( $name, )*
             UnknownBox(u32),
@@ -186,12 +198,19 @@ boxtype! {
     Avc1Box => 0x61766331,
     AvcCBox => 0x61766343,
     Hev1Box => 0x68657631,
+    Hvc1Box => 0x68766331,
     HvcCBox => 0x68766343,
     Mp4aBox => 0x6d703461,
     EsdsBox => 0x65736473,
+    OpusBox => 0x4F707573,
+    DopsBox => 0x644F7073,
+    FlacBox => 0x664C6143,
+    DflaBox => 0x64664C61,
     Tx3gBox => 0x74783367,
     VpccBox => 0x76706343,
     Vp09Box => 0x76703039,
+    Av01Box => 0x61763031,
+    Av1CBox => 0x61763143,
     DataBox => 0x64617461,
     IlstBox => 0x696c7374,
     NameBox => 0xa96e616d,
@@ -227,8 +246,14 @@ impl BoxHeader {
         Self { name, size }
     }
 
-    // TODO: if size is 0, then this box is the last one in the file
+    /// Read a box header. A size of 0 is returned as is for a box extending
+    /// to the end of the file, see `read_within` to resolve it.
     pub fn read<R: Read>(reader: &mut R) -> Result<Self> {
+        Self::read_with_len(reader).map(|(header, _)| header)
+    }
+
+    /// Read a box header, with its length of 8 or 16 with a largesize.
+    fn read_with_len<R: Read>(reader: &mut R) -> Result<(Self, u64)> {
         // Create and read to buf.
         let mut buf = [0u8; 8]; // 8 bytes for box header.
         reader.read_exact(&mut buf)?;
@@ -246,7 +271,7 @@ impl BoxHeader {
             reader.read_exact(&mut buf)?;
             let largesize = u64::from_be_bytes(buf);
 
-            Ok(BoxHeader {
+            let header = BoxHeader {
                 name: BoxType::from(typ),
 
                 // Subtract the length of the serialized largesize, as callers assume `size - HEADER_SIZE` is the length
@@ -257,13 +282,61 @@ impl BoxHeader {
                     1..=15 => return Err(Error::InvalidData("64-bit box size too small")),
                     16..=u64::MAX => largesize - 8,
                 },
-            })
+            };
+            Ok((header, 16))
         } else {
-            Ok(BoxHeader {
+            let header = BoxHeader {
                 name: BoxType::from(typ),
                 size: size as u64,
-            })
+            };
+            Ok((header, 8))
+        }
+    }
+
+    /// Read the header of a box contained in a parent ending at `end`, or in
+    /// a file of size `end`. A box of size 0 extends to the end of its parent,
+    /// which is resolved to its actual size.
+    ///
+    /// A box extending past the end of its parent is an error, or in lenient
+    /// mode is cut to the end of its parent with a warning. Top-level boxes
+    /// extending past the end of the file are kept, for truncated files.
+    pub(crate) fn read_within<R: Read + Seek>(reader: &mut BoxReader<R>, end: u64) -> Result<Self> {
+        let (mut header, header_size) = BoxHeader::read_with_len(reader)?;
+        reader.context_mut().set_header_size(header_size);
+        let start = box_start(reader)?;
+        if header.size == 0 {
+            header.size = end
+                .checked_sub(start)
+                .filter(|&size| size >= HEADER_SIZE)
+                .ok_or_else(|| {
+                    Error::Malformed(format!(
+                        "{} of size 0 starts past the end of its parent",
+                        header.name
+                    ))
+                })?;
+        } else if header.size < HEADER_SIZE || start.saturating_add(header.size) > end {
+            if !is_lenient(reader) {
+                if is_top_level(reader) {
+                    return Ok(header);
+                }
+                return Err(Error::Malformed(format!(
+                    "{} of size {} extends past the end of its parent",
+                    header.name, header.size
+                )));
+            }
+            warn_child(
+                reader,
+                header.name,
+                start,
+                format!(
+                    "box size {} extends past the end of its parent, cut to {}",
+                    header.size,
+                    end.saturating_sub(start)
+                ),
+            );
+            header.size = end.saturating_sub(start).max(HEADER_SIZE);
         }
+        Ok(header)
     }
 
     pub fn write<W: Write>(&self, writer: &mut W) -> Result<u64> {
@@ -308,10 +381,210 @@ pub fn skip_bytes_to<S: Seek>(seeker: &mut S, pos: u64) -> Result<()> {
 
 pub fn skip_box<S: Seek>(seeker: &mut S, size: u64) -> Result<()> {
     let start = box_start(seeker)?;
-    skip_bytes_to(seeker, start + size)?;
+    let end = start
+        .checked_add(size)
+        .ok_or(Error::InvalidData("box size too large"))?;
+    skip_bytes_to(seeker, end)?;
     Ok(())
 }
 
+/// A child box of a type which isn't parsed, kept to be written back as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownBox {
+    pub name: BoxType,
+    pub data: Vec<u8>,
+
+    /// Number of the children of its parent before it, so that it is written
+    /// back in its place, or last if its parent has fewer children.
+    ///
+    /// The known children of a box are written in a fixed order, which is
+    /// only the order they were read in if the file used it too. Otherwise the
+    /// unknown box is written after as many of them, not next to the same
+    /// siblings.
+    pub position: usize,
+}
+
+impl UnknownBox {
+    pub fn box_size(&self) -> u64 {
+        HEADER_SIZE + self.data.len() as u64
+    }
+}
+
+impl<W: Write> WriteBox<&mut W> for UnknownBox {
+    fn write_box(&self, writer: &mut W) -> Result<u64> {
+        let size = self.box_size();
+        BoxHeader::new(self.name, size).write(writer)?;
+        writer.write_all(&self.data)?;
+        Ok(size)
+    }
+}
+
+/// Read a child box of a type which isn't parsed as its type and payload, so
+/// that it can be written back as is by a `ChildWriter`.
+pub(crate) fn read_unknown_box<R: Read>(
+    reader: &mut BoxReader<R>,
+    name: BoxType,
+    size: u64,
+) -> Result<UnknownBox> {
+    let position = child_position(reader);
+    let data = read_bytes(reader, size.saturating_sub(HEADER_SIZE))?;
+    Ok(UnknownBox {
+        name,
+        data,
+        position,
+    })
+}
+
+pub(crate) fn unknown_boxes_size(boxes: &[UnknownBox]) -> u64 {
+    boxes.iter().map(UnknownBox::box_size).sum()
+}
+
+/// Writer of the children of a box, which writes its unknown boxes back in
+/// their place among the others, see `UnknownBox::position`.
+pub(crate) struct ChildWriter<'a, W> {
+    writer: &'a mut W,
+    unknown_boxes: &'a [UnknownBox],
+    written: usize,
+}
+
+impl<'a, W: Write> ChildWriter<'a, W> {
+    pub(crate) fn new(writer: &'a mut W, unknown_boxes: &'a [UnknownBox]) -> Self {
+        ChildWriter {
+            writer,
+            unknown_boxes,
+            written: 0,
+        }
+    }
+
+    pub(crate) fn write<B>(&mut self, child: &B) -> Result<()>
+    where
+        B: for<'w> WriteBox<&'w mut W>,
+    {
+        self.write_with(|writer| child.write_box(writer))
+    }
+
+    /// Write a child with `f`, for the children which aren't written by their
+    /// `WriteBox`.
+    pub(crate) fn write_with<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut W) -> Result<u64>,
+    {
+        while let Some((unknown_box, rest)) = self.unknown_boxes.split_first() {
+            if unknown_box.position > self.written {
+                break;
+            }
+            unknown_box.write_box(self.writer)?;
+            self.unknown_boxes = rest;
+            self.written += 1;
+        }
+        f(self.writer)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Write the unknown boxes left, after all the other children.
+    pub(crate) fn finish(self) -> Result<()> {
+        for unknown_box in self.unknown_boxes {
+            unknown_box.write_box(self.writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read `len` bytes of a box into a buffer which grows with the data actually
+/// read, rather than being allocated from the declared size.
+pub(crate) fn read_bytes<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let read = reader.by_ref().take(len).read_to_end(&mut buf)? as u64;
+    if read < len {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(buf)
+}
+
+/// Parse `data` as the payload of a box of every type, strictly and then
+/// leniently, for the fuzz targets.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub fn fuzz_read_boxes(data: &[u8]) {
+    macro_rules! read_boxes {
+        ($($box:ty),*) => {
+            for &lenient in [false, true].iter() {
+                $(
+                    let mut buf = vec![0u8; HEADER_SIZE as usize];
+                    buf.extend_from_slice(data);
+                    let size = buf.len() as u64;
+                    let mut reader = std::io::Cursor::new(buf);
+                    reader.set_position(HEADER_SIZE);
+                    let context = ParseContext::new(&Mp4ReaderConfig {
+                        lenient,
+                        ..Default::default()
+                    });
+                    let _ = <$box>::parse_box(&mut BoxReader::new(reader, context), size);
+                )*
+            }
+        };
+    }
+    read_boxes!(
+        av01::Av01Box,
+        av01::Av1CBox,
+        avc1::Avc1Box,
+        avc1::AvcCBox,
+        co64::Co64Box,
+        ctts::CttsBox,
+        data::DataBox,
+        dinf::DinfBox,
+        dinf::DrefBox,
+        dinf::UrlBox,
+        edts::EdtsBox,
+        elst::ElstBox,
+        emsg::EmsgBox,
+        flac::DflaBox,
+        flac::FlacBox,
+        ftyp::FtypBox,
+        hdlr::HdlrBox,
+        hev1::Hev1Box,
+        hev1::Hvc1Box,
+        hev1::HvcCBox,
+        ilst::IlstBox,
+        ilst::IlstItemBox,
+        mdhd::MdhdBox,
+        mdia::MdiaBox,
+        mehd::MehdBox,
+        meta::MetaBox,
+        mfhd::MfhdBox,
+        minf::MinfBox,
+        moof::MoofBox,
+        moov::MoovBox,
+        mp4a::EsdsBox,
+        mp4a::Mp4aBox,
+        mvex::MvexBox,
+        mvhd::MvhdBox,
+        opus::DopsBox,
+        opus::OpusBox,
+        smhd::SmhdBox,
+        stbl::StblBox,
+        stco::StcoBox,
+        stsc::StscBox,
+        stsd::StsdBox,
+        stss::StssBox,
+        stsz::StszBox,
+        stts::SttsBox,
+        tfdt::TfdtBox,
+        tfhd::TfhdBox,
+        tkhd::TkhdBox,
+        traf::TrafBox,
+        trak::TrakBox,
+        trex::TrexBox,
+        trun::TrunBox,
+        tx3g::Tx3gBox,
+        udta::UdtaBox,
+        vmhd::VmhdBox,
+        vp09::Vp09Box,
+        vpcc::VpccBox
+    );
+}
+
 pub fn write_zeros<W: Write>(writer: &mut W, size: u64) -> Result<()> {
     for _ in 0..size {
         writer.write_u8(0)?;
@@ -358,6 +631,7 @@ mod value_u8 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Cursor;
 
     #[test]
     fn test_fourcc() {
@@ -391,4 +665,29 @@ mod tests {
         let header = BoxHeader::read(&mut &[0, 0, 0, 1, 1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 16][..]);
         assert!(matches!(header, Ok(BoxHeader { size: 8, .. })));
     }
+
+    fn box_reader(data: Vec<u8>) -> BoxReader<Cursor<Vec<u8>>> {
+        BoxReader::new(Cursor::new(data), ParseContext::default())
+    }
+
+    #[test]
+    fn test_zero_size_within() {
+        let mut reader = box_reader(vec![0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let header = BoxHeader::read_within(&mut reader, 12).unwrap();
+        assert_eq!(header.size, 12);
+        assert_eq!(reader.stream_position().unwrap(), 8);
+
+        // With a largesize of 0, the size follows the `size - HEADER_SIZE`
+        // payload length convention.
+        let mut buf = vec![4; 4];
+        buf.extend_from_slice(&[0, 0, 0, 1, 1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0, 0, 9, 9]);
+        let mut reader = box_reader(buf);
+        reader.seek(SeekFrom::Start(4)).unwrap();
+        let header = BoxHeader::read_within(&mut reader, 22).unwrap();
+        assert_eq!(header.size - HEADER_SIZE, 2);
+
+        let mut reader = box_reader(vec![0, 0, 0, 0, 1, 2, 3, 4]);
+        let error = BoxHeader::read_within(&mut reader, 4);
+        assert!(matches!(error, Err(Error::Malformed(_))));
+    }
 }
@@ -10,6 +10,8 @@ pub struct MoofBox {
 
     #[serde(rename = "traf")]
     pub trafs: Vec<TrafBox>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl MoofBox {
@@ -22,6 +24,7 @@ impl MoofBox {
         for traf in self.trafs.iter() {
             size += traf.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -45,33 +48,36 @@ impl Mp4Box for MoofBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MoofBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MoofBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut mfhd = None;
         let mut trafs = Vec::new();
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::MfhdBox => {
-                    mfhd = Some(MfhdBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::MfhdBox => {
+                        mfhd = Some(MfhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::TrafBox => {
+                        let traf = TrafBox::parse_box(reader, s)?;
+                        trafs.push(traf);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::TrafBox => {
-                    let traf = TrafBox::read_box(reader, s)?;
-                    trafs.push(traf);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
             current = reader.stream_position()?;
         }
 
@@ -84,6 +90,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for MoofBox {
         Ok(MoofBox {
             mfhd: mfhd.unwrap(),
             trafs,
+            unknown_boxes,
         })
     }
 }
@@ -93,10 +100,12 @@ impl<W: Write> WriteBox<&mut W> for MoofBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.mfhd.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.mfhd)?;
         for traf in self.trafs.iter() {
-            traf.write_box(writer)?;
+            children.write(traf)?;
         }
+        children.finish()?;
         Ok(0)
     }
 }
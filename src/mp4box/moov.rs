@@ -20,6 +20,8 @@ pub struct MoovBox {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub udta: Option<UdtaBox>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl MoovBox {
@@ -32,12 +34,16 @@ impl MoovBox {
         for trak in self.traks.iter() {
             size += trak.box_size();
         }
+        if let Some(mvex) = &self.mvex {
+            size += mvex.box_size();
+        }
         if let Some(meta) = &self.meta {
             size += meta.box_size();
         }
         if let Some(udta) = &self.udta {
             size += udta.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -61,8 +67,8 @@ impl Mp4Box for MoovBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MoovBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MoovBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut mvhd = None;
@@ -71,35 +77,38 @@ impl<R: Read + Seek> ReadBox<&mut R> for MoovBox {
         let mut mvex = None;
         let mut traks = Vec::new();
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::MvhdBox => {
-                    mvhd = Some(MvhdBox::read_box(reader, s)?);
-                }
-                BoxType::MetaBox => {
-                    meta = Some(MetaBox::read_box(reader, s)?);
-                }
-                BoxType::MvexBox => {
-                    mvex = Some(MvexBox::read_box(reader, s)?);
-                }
-                BoxType::TrakBox => {
-                    let trak = TrakBox::read_box(reader, s)?;
-                    traks.push(trak);
-                }
-                BoxType::UdtaBox => {
-                    udta = Some(UdtaBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::MvhdBox => {
+                        mvhd = Some(MvhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::MetaBox => {
+                        meta = Some(MetaBox::parse_box(reader, s)?);
+                    }
+                    BoxType::MvexBox => {
+                        mvex = Some(MvexBox::parse_box(reader, s)?);
+                    }
+                    BoxType::TrakBox => {
+                        let trak = TrakBox::parse_box(reader, s)?;
+                        traks.push(trak);
+                    }
+                    BoxType::UdtaBox => {
+                        udta = Some(UdtaBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -116,6 +125,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for MoovBox {
             udta,
             mvex,
             traks,
+            unknown_boxes,
         })
     }
 }
@@ -125,16 +135,21 @@ impl<W: Write> WriteBox<&mut W> for MoovBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.mvhd.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.mvhd)?;
         for trak in self.traks.iter() {
-            trak.write_box(writer)?;
+            children.write(trak)?;
+        }
+        if let Some(mvex) = &self.mvex {
+            children.write(mvex)?;
         }
         if let Some(meta) = &self.meta {
-            meta.write_box(writer)?;
+            children.write(meta)?;
         }
         if let Some(udta) = &self.udta {
-            udta.write_box(writer)?;
+            children.write(udta)?;
         }
+        children.finish()?;
         Ok(0)
     }
 }
@@ -142,6 +157,7 @@ impl<W: Write> WriteBox<&mut W> for MoovBox {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mp4box::trex::TrexBox;
     use crate::mp4box::BoxHeader;
     use std::io::Cursor;
 
@@ -149,10 +165,15 @@ mod tests {
     fn test_moov() {
         let src_box = MoovBox {
             mvhd: MvhdBox::default(),
-            mvex: None, // XXX mvex is not written currently
+            mvex: Some(MvexBox {
+                mehd: None,
+                trexs: vec![TrexBox::default()],
+                ..MvexBox::default()
+            }),
             traks: vec![],
             meta: Some(MetaBox::default()),
             udta: Some(UdtaBox::default()),
+            ..MoovBox::default()
         };
 
         let mut buf = Vec::new();
@@ -168,6 +189,40 @@ mod tests {
         assert_eq!(dst_box, src_box);
     }
 
+    #[test]
+    fn test_moov_unknown_boxes() {
+        let src_box = MoovBox {
+            udta: Some(UdtaBox::default()),
+            unknown_boxes: vec![
+                UnknownBox {
+                    name: BoxType::from(u32::from_be_bytes(*b"xtra")),
+                    data: vec![1, 2, 3],
+                    position: 1,
+                },
+                UnknownBox {
+                    name: BoxType::FreeBox,
+                    data: vec![],
+                    position: 3,
+                },
+            ],
+            ..MoovBox::default()
+        };
+
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        // Written back in their place, between mvhd and udta, then last.
+        let mvhd_size = src_box.mvhd.box_size() as usize;
+        assert_eq!(&buf[HEADER_SIZE as usize + mvhd_size..][4..8], b"xtra");
+        assert_eq!(&buf[buf.len() - 4..], b"free");
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let dst_box = MoovBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(dst_box, src_box);
+    }
+
     #[test]
     fn test_moov_empty() {
         let src_box = MoovBox::default();
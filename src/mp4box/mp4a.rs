@@ -13,6 +13,9 @@ pub struct Mp4aBox {
     #[serde(with = "value_u32")]
     pub samplerate: FixedPointU16,
     pub esds: Option<EsdsBox>,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl Default for Mp4aBox {
@@ -23,6 +26,7 @@ impl Default for Mp4aBox {
             samplesize: 16,
             samplerate: FixedPointU16::new(48000),
             esds: Some(EsdsBox::default()),
+            unknown_boxes: Vec::new(),
         }
     }
 }
@@ -35,6 +39,7 @@ impl Mp4aBox {
             samplesize: 16,
             samplerate: FixedPointU16::new(config.freq_index.freq() as u16),
             esds: Some(EsdsBox::new(config)),
+            unknown_boxes: Vec::new(),
         }
     }
 
@@ -47,6 +52,7 @@ impl Mp4aBox {
         if let Some(ref esds) = self.esds {
             size += esds.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -75,8 +81,8 @@ impl Mp4Box for Mp4aBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for Mp4aBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for Mp4aBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         reader.read_u32::<BigEndian>()?; // reserved
@@ -90,23 +96,40 @@ impl<R: Read + Seek> ReadBox<&mut R> for Mp4aBox {
         let samplerate = FixedPointU16::new_raw(reader.read_u32::<BigEndian>()?);
 
         let mut esds = None;
-        let current = reader.stream_position()?;
-        if current < start + size {
-            let header = BoxHeader::read(reader)?;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        // Some writers end sample entries with padding shorter than a box.
+        while end.saturating_sub(current) >= HEADER_SIZE {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
             let BoxHeader { name, size: s } = header;
 
-            if name == BoxType::EsdsBox {
-                esds = Some(EsdsBox::read_box(reader, s)?);
-            }
-            skip_bytes_to(reader, start + size)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::EsdsBox => {
+                        esds = Some(EsdsBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
         }
 
+        skip_bytes_to(reader, start + size)?;
+
         Ok(Mp4aBox {
             data_reference_index,
             channelcount,
             samplesize,
             samplerate,
             esds,
+            unknown_boxes,
         })
     }
 }
@@ -126,9 +149,11 @@ impl<W: Write> WriteBox<&mut W> for Mp4aBox {
         writer.write_u32::<BigEndian>(0)?; // reserved
         writer.write_u32::<BigEndian>(self.samplerate.raw_value())?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(ref esds) = self.esds {
-            esds.write_box(writer)?;
+            children.write(esds)?;
         }
+        children.finish()?;
 
         Ok(size)
     }
@@ -173,8 +198,8 @@ impl Mp4Box for EsdsBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for EsdsBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for EsdsBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
@@ -261,7 +286,7 @@ fn size_of_length(size: u32) -> u32 {
 fn write_desc<W: Write>(writer: &mut W, tag: u8, size: u32) -> Result<u64> {
     writer.write_u8(tag)?;
 
-    if size as u64 > std::u32::MAX as u64 {
+    if size as u64 > u32::MAX as u64 {
         return Err(Error::InvalidData("invalid descriptor length range"));
     }
 
@@ -621,6 +646,7 @@ mod tests {
                     sl_config: SLConfigDescriptor::default(),
                 },
             }),
+            unknown_boxes: Vec::new(),
         };
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
@@ -643,6 +669,7 @@ mod tests {
             samplesize: 16,
             samplerate: FixedPointU16::new(48000),
             esds: None,
+            unknown_boxes: Vec::new(),
         };
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
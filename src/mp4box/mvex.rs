@@ -7,16 +7,30 @@ use crate::mp4box::{mehd::MehdBox, trex::TrexBox};
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct MvexBox {
     pub mehd: Option<MehdBox>,
-    pub trex: TrexBox,
+
+    #[serde(rename = "trex")]
+    pub trexs: Vec<TrexBox>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl MvexBox {
     pub fn get_type(&self) -> BoxType {
-        BoxType::MdiaBox
+        BoxType::MvexBox
     }
 
     pub fn get_size(&self) -> u64 {
-        HEADER_SIZE + self.mehd.as_ref().map(|x| x.box_size()).unwrap_or(0) + self.trex.box_size()
+        let mut size = HEADER_SIZE + self.mehd.as_ref().map(|x| x.box_size()).unwrap_or(0);
+        for trex in self.trexs.iter() {
+            size += trex.box_size();
+        }
+        size += unknown_boxes_size(&self.unknown_boxes);
+        size
+    }
+
+    /// The trex box holding the fragment defaults of the given track.
+    pub fn trex(&self, track_id: u32) -> Option<&TrexBox> {
+        self.trexs.iter().find(|trex| trex.track_id == track_id)
     }
 }
 
@@ -39,37 +53,41 @@ impl Mp4Box for MvexBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MvexBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MvexBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut mehd = None;
-        let mut trex = None;
+        let mut trexs = Vec::new();
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::MehdBox => {
-                    mehd = Some(MehdBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::MehdBox => {
+                        mehd = Some(MehdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::TrexBox => {
+                        let trex = TrexBox::parse_box(reader, s)?;
+                        trexs.push(trex);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::TrexBox => {
-                    trex = Some(TrexBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
 
-        if trex.is_none() {
+        if trexs.is_empty() {
             return Err(Error::BoxNotFound(BoxType::TrexBox));
         }
 
@@ -77,7 +95,8 @@ impl<R: Read + Seek> ReadBox<&mut R> for MvexBox {
 
         Ok(MvexBox {
             mehd,
-            trex: trex.unwrap(),
+            trexs,
+            unknown_boxes,
         })
     }
 }
@@ -87,11 +106,104 @@ impl<W: Write> WriteBox<&mut W> for MvexBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(mehd) = &self.mehd {
-            mehd.write_box(writer)?;
+            children.write(mehd)?;
+        }
+        for trex in self.trexs.iter() {
+            children.write(trex)?;
         }
-        self.trex.write_box(writer)?;
+        children.finish()?;
 
         Ok(size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4box::BoxHeader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_mvex_multiple_trex() {
+        let src_box = MvexBox {
+            mehd: Some(MehdBox {
+                version: 0,
+                flags: 0,
+                fragment_duration: 30000,
+            }),
+            trexs: vec![
+                TrexBox {
+                    version: 0,
+                    flags: 0,
+                    track_id: 1,
+                    default_sample_description_index: 1,
+                    default_sample_duration: 1000,
+                    default_sample_size: 0,
+                    default_sample_flags: 65536,
+                },
+                TrexBox {
+                    version: 0,
+                    flags: 0,
+                    track_id: 2,
+                    default_sample_description_index: 1,
+                    default_sample_duration: 1024,
+                    default_sample_size: 0,
+                    default_sample_flags: 0,
+                },
+            ],
+            ..MvexBox::default()
+        };
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::MvexBox);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = MvexBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+        assert_eq!(dst_box.trex(2).unwrap().default_sample_duration, 1024);
+        assert!(dst_box.trex(3).is_none());
+    }
+
+    #[test]
+    fn test_mvex_unknown_box_position() {
+        let mehd = MehdBox::default();
+        let trex = TrexBox::default();
+        let unknown = UnknownBox {
+            name: BoxType::from(u32::from_be_bytes(*b"abcd")),
+            data: vec![1, 2, 3],
+            position: 1,
+        };
+        let size = HEADER_SIZE + trex.box_size() + unknown.box_size() + mehd.box_size();
+        let mut buf = Vec::new();
+        BoxHeader::new(BoxType::MvexBox, size)
+            .write(&mut buf)
+            .unwrap();
+        trex.write_box(&mut buf).unwrap();
+        unknown.write_box(&mut buf).unwrap();
+        mehd.write_box(&mut buf).unwrap();
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let mvex = MvexBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(mvex.unknown_boxes.len(), 1);
+        assert_eq!(mvex.unknown_boxes[0], unknown);
+
+        // Read between trex and mehd, the unknown box is written back after
+        // the first child, which is mehd as mehd is written before the trexs.
+        let mut written = Vec::new();
+        mvex.write_box(&mut written).unwrap();
+        let mut reader = Cursor::new(&written[HEADER_SIZE as usize..]);
+        let mut names = Vec::new();
+        while let Ok(header) = BoxHeader::read(&mut reader) {
+            names.push(header.name);
+            skip_box(&mut reader, header.size).unwrap();
+        }
+        assert_eq!(names, [BoxType::MehdBox, unknown.name, BoxType::TrexBox]);
+    }
+}
@@ -85,8 +85,8 @@ impl Mp4Box for MvhdBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for MvhdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for MvhdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
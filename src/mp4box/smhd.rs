@@ -52,8 +52,8 @@ impl Mp4Box for SmhdBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for SmhdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for SmhdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
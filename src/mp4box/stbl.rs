@@ -25,6 +25,8 @@ pub struct StblBox {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub co64: Option<Co64Box>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl StblBox {
@@ -32,6 +34,48 @@ impl StblBox {
         BoxType::StblBox
     }
 
+    /// Cut the samples to those with a duration and in a chunk, with a
+    /// warning, when the sample tables don't agree on the number of samples.
+    fn cut_sample_count<R>(&mut self, reader: &mut BoxReader<R>, position: u64) {
+        let sample_count = self.stsz.sample_count as u64;
+        let timed_count: u64 = self
+            .stts
+            .entries
+            .iter()
+            .map(|entry| entry.sample_count as u64)
+            .sum();
+
+        let chunk_count = match (&self.stco, &self.co64) {
+            (Some(stco), _) => stco.entries.len() as u64,
+            (None, Some(co64)) => co64.entries.len() as u64,
+            (None, None) => 0,
+        };
+        let mut chunked_count = 0u64;
+        for (i, entry) in self.stsc.entries.iter().enumerate() {
+            let next_first_chunk = match self.stsc.entries.get(i + 1) {
+                Some(next) => (next.first_chunk as u64).min(chunk_count + 1),
+                None => chunk_count + 1,
+            };
+            let chunks = next_first_chunk.saturating_sub(entry.first_chunk as u64);
+            chunked_count =
+                chunked_count.saturating_add(chunks.saturating_mul(entry.samples_per_chunk as u64));
+        }
+
+        let count = sample_count.min(timed_count).min(chunked_count);
+        if count < sample_count {
+            warn(
+                reader,
+                position,
+                format!(
+                    "{sample_count} samples in stsz, {timed_count} in stts and \
+                     {chunked_count} in chunks, cut to {count}"
+                ),
+            );
+            self.stsz.sample_count = count as u32;
+            self.stsz.sample_sizes.truncate(count as usize);
+        }
+    }
+
     pub fn get_size(&self) -> u64 {
         let mut size = HEADER_SIZE;
         size += self.stsd.box_size();
@@ -50,6 +94,7 @@ impl StblBox {
         if let Some(ref co64) = self.co64 {
             size += co64.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -73,8 +118,8 @@ impl Mp4Box for StblBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for StblBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for StblBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut stsd = None;
@@ -86,43 +131,46 @@ impl<R: Read + Seek> ReadBox<&mut R> for StblBox {
         let mut stco = None;
         let mut co64 = None;
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::StsdBox => {
-                    stsd = Some(StsdBox::read_box(reader, s)?);
-                }
-                BoxType::SttsBox => {
-                    stts = Some(SttsBox::read_box(reader, s)?);
-                }
-                BoxType::CttsBox => {
-                    ctts = Some(CttsBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::StsdBox => {
+                        stsd = Some(StsdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::SttsBox => {
+                        stts = Some(SttsBox::parse_box(reader, s)?);
+                    }
+                    BoxType::CttsBox => {
+                        ctts = Some(CttsBox::parse_box(reader, s)?);
+                    }
+                    BoxType::StssBox => {
+                        stss = Some(StssBox::parse_box(reader, s)?);
+                    }
+                    BoxType::StscBox => {
+                        stsc = Some(StscBox::parse_box(reader, s)?);
+                    }
+                    BoxType::StszBox => {
+                        stsz = Some(StszBox::parse_box(reader, s)?);
+                    }
+                    BoxType::StcoBox => {
+                        stco = Some(StcoBox::parse_box(reader, s)?);
+                    }
+                    BoxType::Co64Box => {
+                        co64 = Some(Co64Box::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::StssBox => {
-                    stss = Some(StssBox::read_box(reader, s)?);
-                }
-                BoxType::StscBox => {
-                    stsc = Some(StscBox::read_box(reader, s)?);
-                }
-                BoxType::StszBox => {
-                    stsz = Some(StszBox::read_box(reader, s)?);
-                }
-                BoxType::StcoBox => {
-                    stco = Some(StcoBox::read_box(reader, s)?);
-                }
-                BoxType::Co64Box => {
-                    co64 = Some(Co64Box::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
             current = reader.stream_position()?;
         }
 
@@ -144,7 +192,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for StblBox {
 
         skip_bytes_to(reader, start + size)?;
 
-        Ok(StblBox {
+        let mut stbl = StblBox {
             stsd: stsd.unwrap(),
             stts: stts.unwrap(),
             ctts,
@@ -153,7 +201,12 @@ impl<R: Read + Seek> ReadBox<&mut R> for StblBox {
             stsz: stsz.unwrap(),
             stco,
             co64,
-        })
+            unknown_boxes,
+        };
+        if is_lenient(reader) {
+            stbl.cut_sample_count(reader, start);
+        }
+        Ok(stbl)
     }
 }
 
@@ -162,22 +215,24 @@ impl<W: Write> WriteBox<&mut W> for StblBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.stsd.write_box(writer)?;
-        self.stts.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.stsd)?;
+        children.write(&self.stts)?;
         if let Some(ref ctts) = self.ctts {
-            ctts.write_box(writer)?;
+            children.write(ctts)?;
         }
         if let Some(ref stss) = self.stss {
-            stss.write_box(writer)?;
+            children.write(stss)?;
         }
-        self.stsc.write_box(writer)?;
-        self.stsz.write_box(writer)?;
+        children.write(&self.stsc)?;
+        children.write(&self.stsz)?;
         if let Some(ref stco) = self.stco {
-            stco.write_box(writer)?;
+            children.write(stco)?;
         }
         if let Some(ref co64) = self.co64 {
-            co64.write_box(writer)?;
+            children.write(co64)?;
         }
+        children.finish()?;
 
         Ok(size)
     }
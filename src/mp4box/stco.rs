@@ -42,13 +42,14 @@ impl Mp4Box for StcoBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for StcoBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for StcoBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
         let entry_count = reader.read_u32::<BigEndian>()?;
+        check_entry_count(reader, start + size, entry_count, 4)?;
         let mut entries = Vec::with_capacity(entry_count as usize);
         for _i in 0..entry_count {
             let chunk_offset = reader.read_u32::<BigEndian>()?;
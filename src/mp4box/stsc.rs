@@ -50,13 +50,14 @@ impl Mp4Box for StscBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for StscBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for StscBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
         let entry_count = reader.read_u32::<BigEndian>()?;
+        check_entry_count(reader, start + size, entry_count, 12)?;
         let mut entries = Vec::with_capacity(entry_count as usize);
         for _ in 0..entry_count {
             let entry = StscEntry {
@@ -71,13 +72,18 @@ impl<R: Read + Seek> ReadBox<&mut R> for StscBox {
         let mut sample_id = 1;
         for i in 0..entry_count {
             let (first_chunk, samples_per_chunk) = {
-                let mut entry = entries.get_mut(i as usize).unwrap();
+                let entry = entries.get_mut(i as usize).unwrap();
                 entry.first_sample = sample_id;
                 (entry.first_chunk, entry.samples_per_chunk)
             };
             if i < entry_count - 1 {
                 let next_entry = entries.get(i as usize + 1).unwrap();
-                sample_id += (next_entry.first_chunk - first_chunk) * samples_per_chunk;
+                sample_id = next_entry
+                    .first_chunk
+                    .checked_sub(first_chunk)
+                    .and_then(|chunks| chunks.checked_mul(samples_per_chunk))
+                    .and_then(|samples| samples.checked_add(sample_id))
+                    .ok_or(Error::InvalidData("stsc first chunks out of order"))?;
             }
         }
 
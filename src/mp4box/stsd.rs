@@ -2,29 +2,59 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use serde::Serialize;
 use std::io::{Read, Seek, Write};
 
+use crate::mp4box::av01::Av01Box;
 use crate::mp4box::vp09::Vp09Box;
 use crate::mp4box::*;
-use crate::mp4box::{avc1::Avc1Box, hev1::Hev1Box, mp4a::Mp4aBox, tx3g::Tx3gBox};
+use crate::mp4box::{
+    avc1::Avc1Box,
+    flac::FlacBox,
+    hev1::{Hev1Box, Hvc1Box},
+    mp4a::Mp4aBox,
+    opus::OpusBox,
+    tx3g::Tx3gBox,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub struct StsdBox {
     pub version: u8,
     pub flags: u32,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub avc1: Option<Avc1Box>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub hev1: Option<Hev1Box>,
-
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub vp09: Option<Vp09Box>,
+    /// Sample entries in order, referred to by the 1-based sample description
+    /// index of the stsc entries or of the track fragments.
+    pub entries: Vec<SampleEntry>,
+}
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub mp4a: Option<Mp4aBox>,
+/// Sample entry of a stsd box, describing the coding of the samples.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleEntry {
+    Avc1(Avc1Box),
+    Hev1(Hev1Box),
+    Hvc1(Hvc1Box),
+    Vp09(Vp09Box),
+    Av01(Av01Box),
+    Mp4a(Mp4aBox),
+    Opus(OpusBox),
+    Flac(FlacBox),
+    Tx3g(Tx3gBox),
+
+    /// Entry of a type which isn't parsed, as its type and payload.
+    #[serde(skip)]
+    Unknown(BoxType, Vec<u8>),
+}
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub tx3g: Option<Tx3gBox>,
+macro_rules! sample_entry_getters {
+    ($( $name:ident: $variant:ident($box:ty) ),*) => {
+        $(
+            #[doc = concat!("First ", stringify!($name), " sample entry.")]
+            pub fn $name(&self) -> Option<&$box> {
+                self.entries.iter().find_map(|entry| match entry {
+                    SampleEntry::$variant(entry) => Some(entry),
+                    _ => None,
+                })
+            }
+        )*
+    };
 }
 
 impl StsdBox {
@@ -34,19 +64,88 @@ impl StsdBox {
 
     pub fn get_size(&self) -> u64 {
         let mut size = HEADER_SIZE + HEADER_EXT_SIZE + 4;
-        if let Some(ref avc1) = self.avc1 {
-            size += avc1.box_size();
-        } else if let Some(ref hev1) = self.hev1 {
-            size += hev1.box_size();
-        } else if let Some(ref vp09) = self.vp09 {
-            size += vp09.box_size();
-        } else if let Some(ref mp4a) = self.mp4a {
-            size += mp4a.box_size();
-        } else if let Some(ref tx3g) = self.tx3g {
-            size += tx3g.box_size();
+        for entry in self.entries.iter() {
+            size += entry.box_size();
         }
         size
     }
+
+    /// Sample entry of a 1-based sample description index.
+    pub fn entry(&self, sample_description_index: u32) -> Option<&SampleEntry> {
+        let idx = sample_description_index.checked_sub(1)?;
+        self.entries.get(idx as usize)
+    }
+
+    sample_entry_getters! {
+        avc1: Avc1(Avc1Box),
+        hev1: Hev1(Hev1Box),
+        hvc1: Hvc1(Hvc1Box),
+        vp09: Vp09(Vp09Box),
+        av01: Av01(Av01Box),
+        mp4a: Mp4a(Mp4aBox),
+        opus: Opus(OpusBox),
+        flac: Flac(FlacBox),
+        tx3g: Tx3g(Tx3gBox)
+    }
+
+    pub(crate) fn mp4a_mut(&mut self) -> Option<&mut Mp4aBox> {
+        self.entries.iter_mut().find_map(|entry| match entry {
+            SampleEntry::Mp4a(mp4a) => Some(mp4a),
+            _ => None,
+        })
+    }
+}
+
+impl SampleEntry {
+    pub fn box_type(&self) -> BoxType {
+        match self {
+            SampleEntry::Avc1(avc1) => avc1.box_type(),
+            SampleEntry::Hev1(hev1) => hev1.box_type(),
+            SampleEntry::Hvc1(hvc1) => hvc1.box_type(),
+            SampleEntry::Vp09(vp09) => vp09.box_type(),
+            SampleEntry::Av01(av01) => av01.box_type(),
+            SampleEntry::Mp4a(mp4a) => mp4a.box_type(),
+            SampleEntry::Opus(opus) => opus.box_type(),
+            SampleEntry::Flac(flac) => flac.box_type(),
+            SampleEntry::Tx3g(tx3g) => tx3g.box_type(),
+            SampleEntry::Unknown(name, _) => *name,
+        }
+    }
+
+    pub fn box_size(&self) -> u64 {
+        match self {
+            SampleEntry::Avc1(avc1) => avc1.box_size(),
+            SampleEntry::Hev1(hev1) => hev1.box_size(),
+            SampleEntry::Hvc1(hvc1) => hvc1.box_size(),
+            SampleEntry::Vp09(vp09) => vp09.box_size(),
+            SampleEntry::Av01(av01) => av01.box_size(),
+            SampleEntry::Mp4a(mp4a) => mp4a.box_size(),
+            SampleEntry::Opus(opus) => opus.box_size(),
+            SampleEntry::Flac(flac) => flac.box_size(),
+            SampleEntry::Tx3g(tx3g) => tx3g.box_size(),
+            SampleEntry::Unknown(_, data) => HEADER_SIZE + data.len() as u64,
+        }
+    }
+
+    fn write_box<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        match self {
+            SampleEntry::Avc1(avc1) => avc1.write_box(writer),
+            SampleEntry::Hev1(hev1) => hev1.write_box(writer),
+            SampleEntry::Hvc1(hvc1) => hvc1.write_box(writer),
+            SampleEntry::Vp09(vp09) => vp09.write_box(writer),
+            SampleEntry::Av01(av01) => av01.write_box(writer),
+            SampleEntry::Mp4a(mp4a) => mp4a.write_box(writer),
+            SampleEntry::Opus(opus) => opus.write_box(writer),
+            SampleEntry::Flac(flac) => flac.write_box(writer),
+            SampleEntry::Tx3g(tx3g) => tx3g.write_box(writer),
+            SampleEntry::Unknown(name, data) => {
+                let size = self.box_size();
+                BoxHeader::new(*name, size).write(writer)?;
+                writer.write_all(data)?;
+                Ok(size)
+            }
+        }
+    }
 }
 
 impl Mp4Box for StsdBox {
@@ -63,46 +162,59 @@ impl Mp4Box for StsdBox {
     }
 
     fn summary(&self) -> Result<String> {
-        let s = String::new();
+        let s = format!("entry_count={}", self.entries.len());
         Ok(s)
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for StsdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for StsdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
-        reader.read_u32::<BigEndian>()?; // XXX entry_count
-
-        let mut avc1 = None;
-        let mut hev1 = None;
-        let mut vp09 = None;
-        let mut mp4a = None;
-        let mut tx3g = None;
+        let entry_count = reader.read_u32::<BigEndian>()?;
 
-        // Get box header.
-        let header = BoxHeader::read(reader)?;
-        let BoxHeader { name, size: s } = header;
-
-        match name {
-            BoxType::Avc1Box => {
-                avc1 = Some(Avc1Box::read_box(reader, s)?);
-            }
-            BoxType::Hev1Box => {
-                hev1 = Some(Hev1Box::read_box(reader, s)?);
+        let mut entries = Vec::new();
+        let end = start + size;
+        for _ in 0..entry_count {
+            if reader.stream_position()? >= end {
+                break;
             }
-            BoxType::Vp09Box => {
-                vp09 = Some(Vp09Box::read_box(reader, s)?);
-            }
-            BoxType::Mp4aBox => {
-                mp4a = Some(Mp4aBox::read_box(reader, s)?);
-            }
-            BoxType::Tx3gBox => {
-                tx3g = Some(Tx3gBox::read_box(reader, s)?);
+
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            let payload_start = reader.stream_position()?;
+            let read_count = entries.len();
+            read_child(reader, name, s, |reader| {
+                let entry = match name {
+                    BoxType::Avc1Box => SampleEntry::Avc1(Avc1Box::parse_box(reader, s)?),
+                    BoxType::Hev1Box => SampleEntry::Hev1(Hev1Box::parse_box(reader, s)?),
+                    BoxType::Hvc1Box => SampleEntry::Hvc1(Hvc1Box::parse_box(reader, s)?),
+                    BoxType::Vp09Box => SampleEntry::Vp09(Vp09Box::parse_box(reader, s)?),
+                    BoxType::Av01Box => SampleEntry::Av01(Av01Box::parse_box(reader, s)?),
+                    BoxType::Mp4aBox => SampleEntry::Mp4a(Mp4aBox::parse_box(reader, s)?),
+                    BoxType::OpusBox => SampleEntry::Opus(OpusBox::parse_box(reader, s)?),
+                    BoxType::FlacBox => SampleEntry::Flac(FlacBox::parse_box(reader, s)?),
+                    BoxType::Tx3gBox => SampleEntry::Tx3g(Tx3gBox::parse_box(reader, s)?),
+                    _ => {
+                        let UnknownBox { name, data, .. } = read_unknown_box(reader, name, s)?;
+                        SampleEntry::Unknown(name, data)
+                    }
+                };
+                entries.push(entry);
+                Ok(())
+            })?;
+
+            // A malformed entry skipped in lenient mode is kept as is, so that
+            // the following entries keep their index.
+            if entries.len() == read_count {
+                skip_bytes_to(reader, payload_start)?;
+                let UnknownBox { name, data, .. } = read_unknown_box(reader, name, s)?;
+                entries.push(SampleEntry::Unknown(name, data));
             }
-            _ => {}
         }
 
         skip_bytes_to(reader, start + size)?;
@@ -110,11 +222,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for StsdBox {
         Ok(StsdBox {
             version,
             flags,
-            avc1,
-            hev1,
-            vp09,
-            mp4a,
-            tx3g,
+            entries,
         })
     }
 }
@@ -126,18 +234,9 @@ impl<W: Write> WriteBox<&mut W> for StsdBox {
 
         write_box_header_ext(writer, self.version, self.flags)?;
 
-        writer.write_u32::<BigEndian>(1)?; // entry_count
-
-        if let Some(ref avc1) = self.avc1 {
-            avc1.write_box(writer)?;
-        } else if let Some(ref hev1) = self.hev1 {
-            hev1.write_box(writer)?;
-        } else if let Some(ref vp09) = self.vp09 {
-            vp09.write_box(writer)?;
-        } else if let Some(ref mp4a) = self.mp4a {
-            mp4a.write_box(writer)?;
-        } else if let Some(ref tx3g) = self.tx3g {
-            tx3g.write_box(writer)?;
+        writer.write_u32::<BigEndian>(self.entries.len() as u32)?;
+        for entry in self.entries.iter() {
+            entry.write_box(writer)?;
         }
 
         Ok(size)
@@ -49,16 +49,21 @@ impl Mp4Box for StszBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for StszBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for StszBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
 
         let sample_size = reader.read_u32::<BigEndian>()?;
         let sample_count = reader.read_u32::<BigEndian>()?;
-        let mut sample_sizes = Vec::with_capacity(sample_count as usize);
+        // The samples of a constant size still count against the limit, as
+        // other tables are built for each of them.
+        let entry_size = if sample_size == 0 { 4 } else { 0 };
+        check_entry_count(reader, start + size, sample_count, entry_size)?;
+        let mut sample_sizes = Vec::new();
         if sample_size == 0 {
+            sample_sizes.reserve(sample_count as usize);
             for _ in 0..sample_count {
                 let sample_number = reader.read_u32::<BigEndian>()?;
                 sample_sizes.push(sample_number);
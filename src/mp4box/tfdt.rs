@@ -46,8 +46,8 @@ impl Mp4Box for TfdtBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TfdtBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TfdtBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
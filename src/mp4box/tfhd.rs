@@ -22,6 +22,8 @@ impl TfhdBox {
     pub const FLAG_DEFAULT_SAMPLE_DURATION: u32 = 0x08;
     pub const FLAG_DEFAULT_SAMPLE_SIZE: u32 = 0x10;
     pub const FLAG_DEFAULT_SAMPLE_FLAGS: u32 = 0x20;
+    pub const FLAG_DURATION_IS_EMPTY: u32 = 0x010000;
+    pub const FLAG_DEFAULT_BASE_IS_MOOF: u32 = 0x020000;
 
     pub fn get_type(&self) -> BoxType {
         BoxType::TfhdBox
@@ -67,8 +69,8 @@ impl Mp4Box for TfhdBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TfhdBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TfhdBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
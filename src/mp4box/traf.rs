@@ -8,7 +8,11 @@ use crate::mp4box::{tfdt::TfdtBox, tfhd::TfhdBox, trun::TrunBox};
 pub struct TrafBox {
     pub tfhd: TfhdBox,
     pub tfdt: Option<TfdtBox>,
-    pub trun: Option<TrunBox>,
+
+    #[serde(rename = "trun")]
+    pub truns: Vec<TrunBox>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl TrafBox {
@@ -19,9 +23,13 @@ impl TrafBox {
     pub fn get_size(&self) -> u64 {
         let mut size = HEADER_SIZE;
         size += self.tfhd.box_size();
-        if let Some(ref trun) = self.trun {
+        if let Some(ref tfdt) = self.tfdt {
+            size += tfdt.box_size();
+        }
+        for trun in self.truns.iter() {
             size += trun.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -45,36 +53,40 @@ impl Mp4Box for TrafBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TrafBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TrafBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut tfhd = None;
         let mut tfdt = None;
-        let mut trun = None;
+        let mut truns = Vec::new();
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::TfhdBox => {
-                    tfhd = Some(TfhdBox::read_box(reader, s)?);
-                }
-                BoxType::TfdtBox => {
-                    tfdt = Some(TfdtBox::read_box(reader, s)?);
-                }
-                BoxType::TrunBox => {
-                    trun = Some(TrunBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::TfhdBox => {
+                        tfhd = Some(TfhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::TfdtBox => {
+                        tfdt = Some(TfdtBox::parse_box(reader, s)?);
+                    }
+                    BoxType::TrunBox => {
+                        let trun = TrunBox::parse_box(reader, s)?;
+                        truns.push(trun);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -88,7 +100,8 @@ impl<R: Read + Seek> ReadBox<&mut R> for TrafBox {
         Ok(TrafBox {
             tfhd: tfhd.unwrap(),
             tfdt,
-            trun,
+            truns,
+            unknown_boxes,
         })
     }
 }
@@ -98,8 +111,80 @@ impl<W: Write> WriteBox<&mut W> for TrafBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.tfhd.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.tfhd)?;
+        if let Some(ref tfdt) = self.tfdt {
+            children.write(tfdt)?;
+        }
+        for trun in self.truns.iter() {
+            children.write(trun)?;
+        }
+        children.finish()?;
 
         Ok(size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4box::BoxHeader;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_traf_multiple_truns() {
+        let src_box = TrafBox {
+            tfhd: TfhdBox {
+                version: 0,
+                flags: TfhdBox::FLAG_DEFAULT_SAMPLE_SIZE | TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF,
+                track_id: 1,
+                base_data_offset: None,
+                sample_description_index: None,
+                default_sample_duration: None,
+                default_sample_size: Some(128),
+                default_sample_flags: None,
+            },
+            tfdt: Some(TfdtBox {
+                version: 1,
+                flags: 0,
+                base_media_decode_time: 90000,
+            }),
+            truns: vec![
+                TrunBox {
+                    version: 0,
+                    flags: TrunBox::FLAG_DATA_OFFSET | TrunBox::FLAG_SAMPLE_SIZE,
+                    sample_count: 2,
+                    data_offset: Some(120),
+                    first_sample_flags: None,
+                    sample_durations: vec![],
+                    sample_sizes: vec![100, 200],
+                    sample_flags: vec![],
+                    sample_cts: vec![],
+                },
+                TrunBox {
+                    version: 0,
+                    flags: 0,
+                    sample_count: 3,
+                    data_offset: None,
+                    first_sample_flags: None,
+                    sample_durations: vec![],
+                    sample_sizes: vec![],
+                    sample_flags: vec![],
+                    sample_cts: vec![],
+                },
+            ],
+            ..TrafBox::default()
+        };
+        let mut buf = Vec::new();
+        src_box.write_box(&mut buf).unwrap();
+        assert_eq!(buf.len(), src_box.box_size() as usize);
+
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        assert_eq!(header.name, BoxType::TrafBox);
+        assert_eq!(src_box.box_size(), header.size);
+
+        let dst_box = TrafBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(src_box, dst_box);
+    }
+}
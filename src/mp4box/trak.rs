@@ -16,6 +16,8 @@ pub struct TrakBox {
     pub meta: Option<MetaBox>,
 
     pub mdia: MdiaBox,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl TrakBox {
@@ -29,7 +31,11 @@ impl TrakBox {
         if let Some(ref edts) = self.edts {
             size += edts.box_size();
         }
+        if let Some(ref meta) = self.meta {
+            size += meta.box_size();
+        }
         size += self.mdia.box_size();
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -53,8 +59,8 @@ impl Mp4Box for TrakBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TrakBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TrakBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut tkhd = None;
@@ -62,31 +68,34 @@ impl<R: Read + Seek> ReadBox<&mut R> for TrakBox {
         let mut meta = None;
         let mut mdia = None;
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::TkhdBox => {
-                    tkhd = Some(TkhdBox::read_box(reader, s)?);
-                }
-                BoxType::EdtsBox => {
-                    edts = Some(EdtsBox::read_box(reader, s)?);
-                }
-                BoxType::MetaBox => {
-                    meta = Some(MetaBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::TkhdBox => {
+                        tkhd = Some(TkhdBox::parse_box(reader, s)?);
+                    }
+                    BoxType::EdtsBox => {
+                        edts = Some(EdtsBox::parse_box(reader, s)?);
+                    }
+                    BoxType::MetaBox => {
+                        meta = Some(MetaBox::parse_box(reader, s)?);
+                    }
+                    BoxType::MdiaBox => {
+                        mdia = Some(MdiaBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                BoxType::MdiaBox => {
-                    mdia = Some(MdiaBox::read_box(reader, s)?);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
@@ -105,6 +114,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for TrakBox {
             edts,
             meta,
             mdia: mdia.unwrap(),
+            unknown_boxes,
         })
     }
 }
@@ -114,11 +124,16 @@ impl<W: Write> WriteBox<&mut W> for TrakBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
-        self.tkhd.write_box(writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.tkhd)?;
         if let Some(ref edts) = self.edts {
-            edts.write_box(writer)?;
+            children.write(edts)?;
+        }
+        if let Some(ref meta) = self.meta {
+            children.write(meta)?;
         }
-        self.mdia.write_box(writer)?;
+        children.write(&self.mdia)?;
+        children.finish()?;
 
         Ok(size)
     }
@@ -47,8 +47,8 @@ impl Mp4Box for TrexBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TrexBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TrexBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
@@ -77,8 +77,8 @@ impl Mp4Box for TrunBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for TrunBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for TrunBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let (version, flags) = read_box_header_ext(reader)?;
@@ -97,10 +97,31 @@ impl<R: Read + Seek> ReadBox<&mut R> for TrunBox {
             None
         };
 
-        let mut sample_durations = Vec::with_capacity(sample_count as usize);
-        let mut sample_sizes = Vec::with_capacity(sample_count as usize);
-        let mut sample_flags = Vec::with_capacity(sample_count as usize);
-        let mut sample_cts = Vec::with_capacity(sample_count as usize);
+        let sample_fields = [
+            TrunBox::FLAG_SAMPLE_DURATION,
+            TrunBox::FLAG_SAMPLE_SIZE,
+            TrunBox::FLAG_SAMPLE_FLAGS,
+            TrunBox::FLAG_SAMPLE_CTS,
+        ];
+        let sample_field_count = sample_fields.iter().filter(|&&f| f & flags > 0).count();
+        check_entry_count(
+            reader,
+            start + size,
+            sample_count,
+            4 * sample_field_count as u64,
+        )?;
+
+        let capacity = |flag: u32| {
+            if flag & flags > 0 {
+                sample_count as usize
+            } else {
+                0
+            }
+        };
+        let mut sample_durations = Vec::with_capacity(capacity(TrunBox::FLAG_SAMPLE_DURATION));
+        let mut sample_sizes = Vec::with_capacity(capacity(TrunBox::FLAG_SAMPLE_SIZE));
+        let mut sample_flags = Vec::with_capacity(capacity(TrunBox::FLAG_SAMPLE_FLAGS));
+        let mut sample_cts = Vec::with_capacity(capacity(TrunBox::FLAG_SAMPLE_CTS));
         for _ in 0..sample_count {
             if TrunBox::FLAG_SAMPLE_DURATION & flags > 0 {
                 let duration = reader.read_u32::<BigEndian>()?;
@@ -153,7 +174,15 @@ impl<W: Write> WriteBox<&mut W> for TrunBox {
         if let Some(v) = self.first_sample_flags {
             writer.write_u32::<BigEndian>(v)?;
         }
-        if self.sample_count != self.sample_sizes.len() as u32 {
+        let sample_count = self.sample_count as usize;
+        if (TrunBox::FLAG_SAMPLE_DURATION & self.flags > 0
+            && self.sample_durations.len() != sample_count)
+            || (TrunBox::FLAG_SAMPLE_SIZE & self.flags > 0
+                && self.sample_sizes.len() != sample_count)
+            || (TrunBox::FLAG_SAMPLE_FLAGS & self.flags > 0
+                && self.sample_flags.len() != sample_count)
+            || (TrunBox::FLAG_SAMPLE_CTS & self.flags > 0 && self.sample_cts.len() != sample_count)
+        {
             return Err(Error::InvalidData("sample count out of sync"));
         }
         for i in 0..self.sample_count as usize {
@@ -74,8 +74,8 @@ impl Mp4Box for Tx3gBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for Tx3gBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for Tx3gBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         reader.read_u32::<BigEndian>()?; // reserved
@@ -9,6 +9,8 @@ use crate::mp4box::*;
 pub struct UdtaBox {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub meta: Option<MetaBox>,
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl UdtaBox {
@@ -21,6 +23,7 @@ impl UdtaBox {
         if let Some(meta) = &self.meta {
             size += meta.box_size();
         }
+        size += unknown_boxes_size(&self.unknown_boxes);
         size
     }
 }
@@ -43,35 +46,41 @@ impl Mp4Box for UdtaBox {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for UdtaBox {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for UdtaBox {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
 
         let mut meta = None;
 
+        let mut unknown_boxes = Vec::new();
         let mut current = reader.stream_position()?;
         let end = start + size;
         while current < end {
             // Get box header.
-            let header = BoxHeader::read(reader)?;
+            let header = BoxHeader::read_within(reader, start + size)?;
             let BoxHeader { name, size: s } = header;
 
-            match name {
-                BoxType::MetaBox => {
-                    meta = Some(MetaBox::read_box(reader, s)?);
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::MetaBox => {
+                        meta = Some(MetaBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
                 }
-                _ => {
-                    // XXX warn!()
-                    skip_box(reader, s)?;
-                }
-            }
+                Ok(())
+            })?;
 
             current = reader.stream_position()?;
         }
 
         skip_bytes_to(reader, start + size)?;
 
-        Ok(UdtaBox { meta })
+        Ok(UdtaBox {
+            meta,
+            unknown_boxes,
+        })
     }
 }
 
@@ -80,9 +89,11 @@ impl<W: Write> WriteBox<&mut W> for UdtaBox {
         let size = self.box_size();
         BoxHeader::new(self.box_type(), size).write(writer)?;
 
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
         if let Some(meta) = &self.meta {
-            meta.write_box(writer)?;
+            children.write(meta)?;
         }
+        children.finish()?;
         Ok(size)
     }
 }
@@ -95,7 +106,10 @@ mod tests {
 
     #[test]
     fn test_udta_empty() {
-        let src_box = UdtaBox { meta: None };
+        let src_box = UdtaBox {
+            meta: None,
+            ..UdtaBox::default()
+        };
 
         let mut buf = Vec::new();
         src_box.write_box(&mut buf).unwrap();
@@ -114,6 +128,7 @@ mod tests {
     fn test_udta() {
         let src_box = UdtaBox {
             meta: Some(MetaBox::default()),
+            ..UdtaBox::default()
         };
 
         let mut buf = Vec::new();
@@ -20,6 +20,9 @@ pub struct Vp09Box {
     pub depth: u16,
     pub end_code: u16,
     pub vpcc: VpccBox,
+
+    #[serde(skip)]
+    pub unknown_boxes: Vec<UnknownBox>,
 }
 
 impl Vp09Box {
@@ -61,6 +64,7 @@ impl Vp09Box {
                 matrix_coefficients: 0,
                 codec_initialization_data_size: 0,
             },
+            unknown_boxes: Vec::new(),
         }
     }
 }
@@ -71,7 +75,7 @@ impl Mp4Box for Vp09Box {
     }
 
     fn box_size(&self) -> u64 {
-        0x6A
+        0x6A + unknown_boxes_size(&self.unknown_boxes)
     }
 
     fn to_json(&self) -> Result<String> {
@@ -83,8 +87,8 @@ impl Mp4Box for Vp09Box {
     }
 }
 
-impl<R: Read + Seek> ReadBox<&mut R> for Vp09Box {
-    fn read_box(reader: &mut R, size: u64) -> Result<Self> {
+impl ParseBox for Vp09Box {
+    fn parse_box<R: Read + Seek>(reader: &mut BoxReader<R>, size: u64) -> Result<Self> {
         let start = box_start(reader)?;
         let (version, flags) = read_box_header_ext(reader)?;
 
@@ -119,10 +123,32 @@ impl<R: Read + Seek> ReadBox<&mut R> for Vp09Box {
         let depth: u16 = reader.read_u16::<BigEndian>()?;
         let end_code: u16 = reader.read_u16::<BigEndian>()?;
 
-        let vpcc = {
-            let header = BoxHeader::read(reader)?;
-            VpccBox::read_box(reader, header.size)?
-        };
+        let mut vpcc = None;
+        let mut unknown_boxes = Vec::new();
+
+        let mut current = reader.stream_position()?;
+        let end = start + size;
+        // Some writers end sample entries with padding shorter than a box.
+        while end.saturating_sub(current) >= HEADER_SIZE {
+            // Get box header.
+            let header = BoxHeader::read_within(reader, end)?;
+            let BoxHeader { name, size: s } = header;
+
+            read_child(reader, name, s, |reader| {
+                match name {
+                    BoxType::VpccBox => {
+                        vpcc = Some(VpccBox::parse_box(reader, s)?);
+                    }
+                    _ => {
+                        unknown_boxes.push(read_unknown_box(reader, name, s)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            current = reader.stream_position()?;
+        }
+        let vpcc = vpcc.ok_or(Error::BoxNotFound(BoxType::VpccBox))?;
 
         skip_bytes_to(reader, start + size)?;
 
@@ -142,6 +168,7 @@ impl<R: Read + Seek> ReadBox<&mut R> for Vp09Box {
             depth,
             end_code,
             vpcc,
+            unknown_boxes,
         })
     }
 }
@@ -167,7 +194,9 @@ impl<W: Write> WriteBox<&mut W> for Vp09Box {
         writer.write_all(&self.compressorname)?;
         writer.write_u16::<BigEndian>(self.depth)?;
         writer.write_u16::<BigEndian>(self.end_code)?;
-        VpccBox::write_box(&self.vpcc, writer)?;
+        let mut children = ChildWriter::new(writer, &self.unknown_boxes);
+        children.write(&self.vpcc)?;
+        children.finish()?;
 
         Ok(size)
     }
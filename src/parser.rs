@@ -0,0 +1,428 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Cursor;
+
+use crate::reader::{fragment_samples, tracks_from_moov, FragmentSample};
+use crate::*;
+
+/// Something found by [`Mp4Parser`] in the data pushed so far.
+#[derive(Debug)]
+pub enum Mp4Event {
+    /// The header of a top-level box starting at `offset`.
+    BoxHeader {
+        name: BoxType,
+        offset: u64,
+        size: u64,
+    },
+    Ftyp(FtypBox),
+    Moov(Box<MoovBox>),
+    Moof(MoofBox),
+    Emsg(EmsgBox),
+
+    /// The payload of an mdat box, as absolute offsets. The size is 0 for an
+    /// mdat box extending to the end of the data.
+    Mdat {
+        offset: u64,
+        size: u64,
+    },
+
+    /// A sample of the last moof whose bytes are complete.
+    Sample {
+        track_id: u32,
+        sample: Mp4Sample,
+    },
+}
+
+#[derive(Debug)]
+enum State {
+    Header,
+    Box {
+        name: BoxType,
+        offset: u64,
+        header_size: u64,
+        size: u64,
+    },
+    // A box of size 0, buffered until `finish` as it extends to the end of
+    // the data.
+    BoxToEnd {
+        name: BoxType,
+        offset: u64,
+        header_size: u64,
+    },
+    Skip {
+        end: u64,
+    },
+    Mdat {
+        end: u64,
+    },
+    Done,
+}
+
+/// Push-based parser for data arriving in chunks, without doing any I/O.
+///
+/// Chunks of any size are given to `push`, which returns the events they
+/// complete. Only the bytes of the boxes being parsed and of the samples
+/// being assembled are buffered, the rest of the data is skipped over. The
+/// boxes over the size limit are failed or, in lenient mode, skipped before
+/// they are buffered.
+#[derive(Debug)]
+pub struct Mp4Parser {
+    state: State,
+    limits: Mp4Limits,
+    context: ParseContext,
+    // Absolute offset of the next pushed byte.
+    offset: u64,
+    // Partial box header, or partial box with room for its header.
+    buf: Vec<u8>,
+
+    tracks: HashMap<u32, Mp4Track>,
+    // Samples of the last moof waiting for their data, sorted by offset.
+    pending: VecDeque<FragmentSample>,
+    // Bytes of the current mdat from `mdat_offset` on.
+    mdat_buf: Vec<u8>,
+    mdat_offset: u64,
+}
+
+impl Default for Mp4Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mp4Parser {
+    pub fn new() -> Self {
+        Self::with_config(&Mp4ReaderConfig::default())
+    }
+
+    /// Parser with the options of an `Mp4Reader`, see
+    /// [`Mp4Reader::read_header_with_config`].
+    pub fn with_config(config: &Mp4ReaderConfig) -> Self {
+        Mp4Parser {
+            state: State::Header,
+            limits: config.limits,
+            context: ParseContext::new(config),
+            offset: 0,
+            buf: Vec::new(),
+            tracks: HashMap::new(),
+            pending: VecDeque::new(),
+            mdat_buf: Vec::new(),
+            mdat_offset: 0,
+        }
+    }
+
+    /// Absolute offset of the next byte to be pushed.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Tracks of the movie once its moov is parsed, holding the trafs of the
+    /// last parsed moof.
+    pub fn tracks(&self) -> &HashMap<u32, Mp4Track> {
+        &self.tracks
+    }
+
+    /// Problems found in lenient mode so far, see [`Mp4Reader::warnings`].
+    pub fn warnings(&self) -> &[Mp4Warning] {
+        self.context.warnings()
+    }
+
+    /// Parse a chunk of data following the previously pushed ones.
+    pub fn push(&mut self, mut data: &[u8]) -> Result<Vec<Mp4Event>> {
+        let mut events = Vec::new();
+        while !data.is_empty() {
+            let n = match self.state {
+                State::Header => self.push_header(data, &mut events)?,
+                State::Box {
+                    name,
+                    offset,
+                    header_size,
+                    size,
+                } => {
+                    let n = take_len(size - self.buf.len() as u64, data);
+                    self.buf.extend_from_slice(&data[..n]);
+                    if self.buf.len() as u64 == size {
+                        self.parse_box(name, offset, header_size, size, &mut events)?;
+                        self.state = State::Header;
+                    }
+                    n
+                }
+                State::BoxToEnd {
+                    name,
+                    offset,
+                    header_size,
+                } => {
+                    let max_size = self.limits.max_box_size.saturating_add(1);
+                    let n = take_len(max_size - self.buf.len() as u64, data);
+                    self.buf.extend_from_slice(&data[..n]);
+                    if self.buf.len() as u64 == max_size {
+                        // Failed by `parse_box`, or skipped up to the end.
+                        self.buf.truncate(HEADER_SIZE as usize);
+                        self.parse_box(name, offset, header_size, max_size, &mut events)?;
+                        self.state = State::Done;
+                    }
+                    n
+                }
+                State::Skip { end } => {
+                    let n = take_len(end - self.offset, data);
+                    if self.offset + n as u64 == end {
+                        self.state = State::Header;
+                    }
+                    n
+                }
+                State::Mdat { end } => {
+                    let n = take_len(end - self.offset, data);
+                    self.push_mdat(&data[..n], &mut events)?;
+                    if self.offset + n as u64 == end {
+                        self.end_mdat()?;
+                    }
+                    n
+                }
+                State::Done => data.len(),
+            };
+            self.offset += n as u64;
+            data = &data[n..];
+        }
+        Ok(events)
+    }
+
+    /// Check that the data ended at a box boundary, returning the events of a
+    /// last box of size 0 which extends to the end of the data.
+    pub fn finish(&mut self) -> Result<Vec<Mp4Event>> {
+        let mut events = Vec::new();
+        if let State::BoxToEnd {
+            name,
+            offset,
+            header_size,
+        } = self.state
+        {
+            let size = self.buf.len() as u64;
+            self.parse_box(name, offset, header_size, size, &mut events)?;
+            self.state = State::Done;
+        }
+        match self.state {
+            State::Header | State::Done if self.buf.is_empty() && self.pending.is_empty() => {
+                Ok(events)
+            }
+            State::Header | State::Done if self.buf.is_empty() => {
+                Err(Error::BoxNotFound(BoxType::MdatBox))
+            }
+            State::Mdat { end: u64::MAX } if self.pending.is_empty() => Ok(events),
+            State::Mdat { end: u64::MAX } => Err(Error::InvalidData("sample data outside of mdat")),
+            _ => Err(Error::InvalidData("incomplete box at end of data")),
+        }
+    }
+
+    fn push_header(&mut self, data: &[u8], events: &mut Vec<Mp4Event>) -> Result<usize> {
+        let header_len = if self.buf.len() >= 8 && self.buf[0..4] == [0, 0, 0, 1] {
+            16
+        } else {
+            8
+        };
+        let n = take_len((header_len - self.buf.len()) as u64, data);
+        self.buf.extend_from_slice(&data[..n]);
+        if self.buf.len() < header_len || self.buf[0..4] == [0, 0, 0, 1] && header_len == 8 {
+            return Ok(n);
+        }
+
+        let BoxHeader { name, size } = BoxHeader::read(&mut self.buf.as_slice())?;
+        let offset = self.offset + n as u64 - header_len as u64;
+        self.buf.clear();
+        events.push(Mp4Event::BoxHeader { name, offset, size });
+
+        let payload_offset = self.offset + n as u64;
+
+        let header_size = header_len as u64;
+        // A size zero BoxHeader extends to the end of the stream, keep
+        // emitting the samples of an mdat, buffer a parsed box until `finish`
+        // and ignore the rest of the data otherwise.
+        if size == 0 {
+            self.state = match name {
+                BoxType::FtypBox | BoxType::MoovBox | BoxType::MoofBox | BoxType::EmsgBox => {
+                    if name == BoxType::MoofBox && !self.pending.is_empty() {
+                        return Err(Error::BoxNotFound(BoxType::MdatBox));
+                    }
+                    self.buf.resize(HEADER_SIZE as usize, 0);
+                    State::BoxToEnd {
+                        name,
+                        offset,
+                        header_size,
+                    }
+                }
+                BoxType::MdatBox => {
+                    self.start_mdat(payload_offset, 0, events)?;
+                    State::Mdat { end: u64::MAX }
+                }
+                _ => State::Done,
+            };
+            return Ok(n);
+        }
+        if size < HEADER_SIZE {
+            return Err(Error::InvalidData("box size too small"));
+        }
+
+        let end = payload_offset
+            .checked_add(size - HEADER_SIZE)
+            .ok_or(Error::InvalidData("box size too large"))?;
+        self.state = match name {
+            BoxType::FtypBox | BoxType::MoovBox | BoxType::MoofBox | BoxType::EmsgBox => {
+                if name == BoxType::MoofBox && !self.pending.is_empty() {
+                    return Err(Error::BoxNotFound(BoxType::MdatBox));
+                }
+                // Room for the header, so that the box starts at position 0.
+                self.buf.resize(HEADER_SIZE as usize, 0);
+                if size > self.limits.max_box_size {
+                    // Failed by `parse_box` before it is buffered, or skipped.
+                    self.parse_box(name, offset, header_size, size, events)?;
+                    State::Skip { end }
+                } else {
+                    State::Box {
+                        name,
+                        offset,
+                        header_size,
+                        size,
+                    }
+                }
+            }
+            BoxType::MdatBox => {
+                self.start_mdat(payload_offset, end - payload_offset, events)?;
+                State::Mdat { end }
+            }
+            _ => State::Skip { end },
+        };
+
+        // Boxes with an empty payload are complete right away.
+        if payload_offset == end {
+            match self.state {
+                State::Box {
+                    name,
+                    offset,
+                    header_size,
+                    size,
+                } => self.parse_box(name, offset, header_size, size, events)?,
+                State::Mdat { .. } => self.end_mdat()?,
+                _ => {}
+            }
+            self.state = State::Header;
+        }
+        Ok(n)
+    }
+
+    /// Parse a box buffered with room for its header, or in lenient mode skip
+    /// it with a warning if it fails to parse.
+    fn parse_box(
+        &mut self,
+        name: BoxType,
+        offset: u64,
+        header_size: u64,
+        size: u64,
+        events: &mut Vec<Mp4Event>,
+    ) -> Result<()> {
+        let mut cursor = Cursor::new(std::mem::take(&mut self.buf));
+        cursor.set_position(HEADER_SIZE);
+        let mut context = std::mem::take(&mut self.context);
+        context.set_base_offset(offset + header_size - HEADER_SIZE);
+        context.set_header_size(header_size);
+        let mut reader = BoxReader::new(cursor, context);
+
+        let tracks = &mut self.tracks;
+        let pending = &mut self.pending;
+        let limits = &self.limits;
+        let result = read_child(&mut reader, name, size, |reader| {
+            // Checked before the box is buffered.
+            check_top_level_box_size(reader, size)?;
+            let event = match name {
+                BoxType::FtypBox => Mp4Event::Ftyp(FtypBox::parse_box(reader, size)?),
+                BoxType::MoovBox => {
+                    let moov = MoovBox::parse_box(reader, size)?;
+                    *tracks = tracks_from_moov(&moov)?;
+                    for track in tracks.values_mut() {
+                        track.set_limits(limits);
+                    }
+                    Mp4Event::Moov(Box::new(moov))
+                }
+                BoxType::MoofBox => {
+                    let moof = MoofBox::parse_box(reader, size)?;
+                    let mut samples = fragment_samples(tracks, &moof, offset)?;
+                    samples.sort_by_key(|sample| sample.offset);
+                    *pending = samples.into();
+                    Mp4Event::Moof(moof)
+                }
+                _ => Mp4Event::Emsg(EmsgBox::parse_box(reader, size)?),
+            };
+            events.push(event);
+            Ok(())
+        });
+
+        // Only the warnings are kept, not the locations of the boxes.
+        self.context = reader.into_parts().1;
+        self.context.take_locations();
+        result
+    }
+
+    fn start_mdat(&self, offset: u64, size: u64, events: &mut Vec<Mp4Event>) -> Result<()> {
+        events.push(Mp4Event::Mdat { offset, size });
+        if let Some(sample) = self.pending.front() {
+            if sample.offset < offset {
+                return Err(Error::InvalidData("sample data outside of mdat"));
+            }
+        }
+        Ok(())
+    }
+
+    fn push_mdat(&mut self, data: &[u8], events: &mut Vec<Mp4Event>) -> Result<()> {
+        let keep_from = match self.pending.front() {
+            Some(sample) => sample.offset,
+            None => return Ok(()),
+        };
+
+        // Buffer the data from the first byte of the first pending sample on.
+        if self.mdat_buf.is_empty() {
+            let skip = keep_from.saturating_sub(self.offset);
+            if skip >= data.len() as u64 {
+                return Ok(());
+            }
+            self.mdat_offset = self.offset + skip;
+            self.mdat_buf.extend_from_slice(&data[skip as usize..]);
+        } else {
+            self.mdat_buf.extend_from_slice(data);
+        }
+
+        let buf_end = self.mdat_offset + self.mdat_buf.len() as u64;
+        while let Some(pending) = self.pending.front() {
+            if pending.offset + pending.size > buf_end {
+                break;
+            }
+            let mut pending = self.pending.pop_front().unwrap();
+            let start = (pending.offset - self.mdat_offset) as usize;
+            let bytes = &self.mdat_buf[start..start + pending.size as usize];
+            pending.sample.bytes = Bytes::copy_from_slice(bytes);
+            events.push(Mp4Event::Sample {
+                track_id: pending.track_id,
+                sample: pending.sample,
+            });
+        }
+
+        // Drop the data before the next pending sample.
+        let drop_to = match self.pending.front() {
+            Some(sample) => sample.offset.min(buf_end),
+            None => buf_end,
+        };
+        self.mdat_buf.drain(..(drop_to - self.mdat_offset) as usize);
+        self.mdat_offset = drop_to;
+        Ok(())
+    }
+
+    fn end_mdat(&mut self) -> Result<()> {
+        self.mdat_buf.clear();
+        self.state = State::Header;
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            return Err(Error::InvalidData("sample data outside of mdat"));
+        }
+        Ok(())
+    }
+}
+
+/// Number of bytes of `data` to take when `needed` more bytes are wanted.
+fn take_len(needed: u64, data: &[u8]) -> usize {
+    needed.min(data.len() as u64) as usize
+}
@@ -1,125 +1,219 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+#[cfg(feature = "tokio")]
+use std::io::{Cursor, SeekFrom};
 use std::io::{Read, Seek};
 use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 
 use crate::meta::MetaBox;
+use crate::mp4box::tfhd::TfhdBox;
 use crate::*;
 
 #[derive(Debug)]
 pub struct Mp4Reader<R> {
-    reader: R,
+    pub(crate) reader: R,
     pub ftyp: FtypBox,
     pub moov: MoovBox,
     pub moofs: Vec<MoofBox>,
     pub emsgs: Vec<EmsgBox>,
 
-    tracks: HashMap<u32, Mp4Track>,
+    pub(crate) tracks: HashMap<u32, Mp4Track>,
     size: u64,
+    warnings: Vec<Mp4Warning>,
+    box_locations: Vec<BoxLocation>,
+}
+
+/// Options of [`Mp4Reader::read_header_with_config`].
+#[derive(Debug, Clone, Default)]
+pub struct Mp4ReaderConfig {
+    /// Skip the malformed boxes with a warning rather than failing, see
+    /// [`Mp4Reader::warnings`].
+    pub lenient: bool,
+    pub limits: Mp4Limits,
+}
+
+/// Bounds on what a file may make the reader allocate or recurse into, so
+/// that untrusted files fail with `Error::LimitExceeded` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct Mp4Limits {
+    /// Largest box parsed or read into memory, skipped boxes such as the mdat
+    /// of an `Mp4Reader` aren't limited.
+    pub max_box_size: u64,
+
+    /// Largest number of entries in a table such as stts, stsz or trun.
+    pub max_table_entries: u32,
+
+    /// Largest sample read.
+    pub max_sample_size: u32,
+
+    /// Deepest nesting of boxes.
+    pub max_depth: u32,
+}
+
+impl Default for Mp4Limits {
+    fn default() -> Self {
+        Mp4Limits {
+            max_box_size: 1 << 30,
+            max_table_entries: 1 << 24,
+            max_sample_size: 1 << 28,
+            max_depth: 32,
+        }
+    }
 }
 
 impl<R: Read + Seek> Mp4Reader<R> {
-    pub fn read_header(mut reader: R, size: u64) -> Result<Self> {
+    pub fn read_header(reader: R, size: u64) -> Result<Self> {
+        Self::read_header_with_config(reader, size, &Mp4ReaderConfig::default())
+    }
+
+    pub fn read_header_with_config(reader: R, size: u64, config: &Mp4ReaderConfig) -> Result<Self> {
+        let context = ParseContext::new(config);
+        let mut reader = BoxReader::new(reader, context);
         let start = reader.stream_position()?;
 
-        let mut ftyp = None;
-        let mut moov = None;
-        let mut moofs = Vec::new();
-        let mut emsgs = Vec::new();
+        let mut boxes = HeaderBoxes::default();
 
         let mut current = start;
         while current < size {
-            // Get box header.
-            let header = BoxHeader::read(&mut reader)?;
-            let BoxHeader { name, size: s } = header;
-
-            // Break if size zero BoxHeader, which can result in dead-loop.
-            if s == 0 {
-                break;
-            }
+            let (BoxHeader { name, size: s }, end) = read_top_level_header(&mut reader, size)?;
 
-            // Match and parse the atom boxes.
-            match name {
-                BoxType::FtypBox => {
-                    ftyp = Some(FtypBox::read_box(&mut reader, s)?);
-                }
-                BoxType::FreeBox => {
-                    skip_box(&mut reader, s)?;
-                }
-                BoxType::MdatBox => {
-                    skip_box(&mut reader, s)?;
-                }
-                BoxType::MoovBox => {
-                    moov = Some(MoovBox::read_box(&mut reader, s)?);
-                }
-                BoxType::MoofBox => {
-                    let moof = MoofBox::read_box(&mut reader, s)?;
-                    moofs.push(moof);
-                }
-                BoxType::EmsgBox => {
-                    let emsg = EmsgBox::read_box(&mut reader, s)?;
-                    emsgs.push(emsg);
-                }
-                _ => {
-                    // XXX warn!()
-                    skip_box(&mut reader, s)?;
-                }
-            }
+            read_child(&mut reader, name, s, |reader| {
+                boxes.read_box(reader, name, s, current, end > size)
+            })?;
             current = reader.stream_position()?;
         }
+        let (reader, mut context) = reader.into_parts();
+        boxes.locations = context.take_locations();
+        boxes.warnings = context.into_warnings();
 
-        if ftyp.is_none() {
-            return Err(Error::BoxNotFound(BoxType::FtypBox));
-        }
-        if moov.is_none() {
-            return Err(Error::BoxNotFound(BoxType::MoovBox));
-        }
+        boxes.into_reader(reader, current - start, size, &config.limits)
+    }
 
-        let size = current - start;
-        let mut tracks = if let Some(ref moov) = moov {
-            if moov.traks.iter().any(|trak| trak.tkhd.track_id == 0) {
-                return Err(Error::InvalidData("illegal track id 0"));
-            }
-            moov.traks
-                .iter()
-                .map(|trak| (trak.tkhd.track_id, Mp4Track::from(trak)))
-                .collect()
+    pub fn read_sample(&mut self, track_id: u32, sample_id: u32) -> Result<Option<Mp4Sample>> {
+        if let Some(track) = self.tracks.get(&track_id) {
+            track.read_sample(&mut self.reader, sample_id)
         } else {
-            HashMap::new()
-        };
+            Err(Error::TrakNotFound(track_id))
+        }
+    }
 
-        // Update tracks if any fragmented (moof) boxes are found.
-        if !moofs.is_empty() {
-            let mut default_sample_duration = 0;
-            if let Some(ref moov) = moov {
-                if let Some(ref mvex) = &moov.mvex {
-                    default_sample_duration = mvex.trex.default_sample_duration
-                }
+    /// Iterate over the samples of a track, reading the contiguous samples of
+    /// each chunk at once. The sample index of the track is built if needed.
+    pub fn samples(&mut self, track_id: u32) -> Result<Samples<'_, R>> {
+        Samples::new(self, track_id)
+    }
+
+    /// Iterate over the samples of all tracks in the given order, with their
+    /// track id. The sample indexes of the tracks are built if needed.
+    pub fn interleaved_samples(&mut self, order: SampleOrder) -> Result<InterleavedSamples<'_, R>> {
+        InterleavedSamples::new(self, order)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + AsyncSeek + Unpin> Mp4Reader<R> {
+    /// Async counterpart of [`Mp4Reader::read_header`]. The boxes it parses
+    /// are read into memory first, the others are seeked over.
+    pub async fn read_header_async(reader: R, size: u64) -> Result<Self> {
+        Self::read_header_async_with_config(reader, size, &Mp4ReaderConfig::default()).await
+    }
+
+    /// Async counterpart of [`Mp4Reader::read_header_with_config`].
+    pub async fn read_header_async_with_config(
+        mut reader: R,
+        size: u64,
+        config: &Mp4ReaderConfig,
+    ) -> Result<Self> {
+        let start = reader.stream_position().await?;
+
+        let mut boxes = HeaderBoxes::default();
+        let mut context = ParseContext::new(config);
+
+        let mut current = start;
+        while current < size {
+            let mut buf = [0u8; 16];
+            let mut header_len = 8;
+            reader.read_exact(&mut buf[..8]).await?;
+            if buf[0..4] == [0, 0, 0, 1] {
+                reader.read_exact(&mut buf[8..]).await?;
+                header_len = 16;
             }
+            // The header is parsed from the buffer, at positions from `current`.
+            context.set_base_offset(current);
+            let mut header_reader = BoxReader::new(Cursor::new(&buf[..header_len]), context);
+            let (BoxHeader { name, size: s }, end) =
+                read_top_level_header(&mut header_reader, size - current)?;
+            context = header_reader.into_parts().1;
+            let end = current
+                .checked_add(end)
+                .ok_or(Error::InvalidData("box size too large"))?;
+            let header_len = header_len as u64;
 
-            for moof in moofs.iter() {
-                for traf in moof.trafs.iter() {
-                    let track_id = traf.tfhd.track_id;
-                    if let Some(track) = tracks.get_mut(&track_id) {
-                        track.default_sample_duration = default_sample_duration;
-                        track.trafs.push(traf.clone())
-                    } else {
-                        return Err(Error::TrakNotFound(track_id));
+            if HeaderBoxes::is_parsed(name) {
+                // Room for the header, so that the box starts at position 0.
+                let mut buf = vec![0u8; HEADER_SIZE as usize];
+                // Checked before the box is read into memory, a box cut off by
+                // the end of a truncated file isn't read.
+                if s <= config.limits.max_box_size && end <= size {
+                    let len = s - HEADER_SIZE;
+                    let read = (&mut reader).take(len).read_to_end(&mut buf).await? as u64;
+                    if read < len {
+                        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
                     }
                 }
+                let mut cursor = Cursor::new(buf);
+                cursor.set_position(HEADER_SIZE);
+                context.set_base_offset(current + header_len - HEADER_SIZE);
+                let mut cursor = BoxReader::new(cursor, context);
+                read_child(&mut cursor, name, s, |cursor| {
+                    boxes.read_box(cursor, name, s, current, end > size)
+                })?;
+                context = cursor.into_parts().1;
+            } else {
+                let location = BoxLocation {
+                    path: BoxPath::default(),
+                    offset: current,
+                    header_size: header_len,
+                    size: end - current,
+                };
+                context.skip_box(name, location, HeaderBoxes::is_skipped(name));
             }
+            current = reader.seek(SeekFrom::Start(end)).await?;
         }
 
-        Ok(Mp4Reader {
-            reader,
-            ftyp: ftyp.unwrap(),
-            moov: moov.unwrap(),
-            moofs,
-            emsgs,
-            size,
-            tracks,
-        })
+        boxes.locations = context.take_locations();
+        boxes.warnings = context.into_warnings();
+        boxes.into_reader(reader, current - start, size, &config.limits)
+    }
+
+    /// Async counterpart of [`Mp4Reader::read_sample`].
+    pub async fn read_sample_async(
+        &mut self,
+        track_id: u32,
+        sample_id: u32,
+    ) -> Result<Option<Mp4Sample>> {
+        let track = match self.tracks.get(&track_id) {
+            Some(track) => track,
+            None => return Err(Error::TrakNotFound(track_id)),
+        };
+        let (sample_offset, sample_size) = match track.checked_sample_location(sample_id)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+
+        let mut buffer = vec![0x0u8; sample_size as usize];
+        self.reader.seek(SeekFrom::Start(sample_offset)).await?;
+        self.reader.read_exact(&mut buffer).await?;
+
+        track
+            .sample_with_bytes(sample_id, Bytes::from(buffer))
+            .map(Some)
     }
+}
 
+impl<R> Mp4Reader<R> {
     pub fn size(&self) -> u64 {
         self.size
     }
@@ -137,7 +231,11 @@ impl<R: Read + Seek> Mp4Reader<R> {
     }
 
     pub fn duration(&self) -> Duration {
-        Duration::from_millis(self.moov.mvhd.duration * 1000 / self.moov.mvhd.timescale as u64)
+        let duration = self.moov.mvhd.duration as u128 * 1000;
+        let duration = duration
+            .checked_div(self.moov.mvhd.timescale as u128)
+            .unwrap_or(0);
+        Duration::from_millis(u64::try_from(duration).unwrap_or(u64::MAX))
     }
 
     pub fn timescale(&self) -> u32 {
@@ -148,10 +246,75 @@ impl<R: Read + Seek> Mp4Reader<R> {
         !self.moofs.is_empty()
     }
 
+    /// Problems found while reading the header: skipped unknown boxes, and in
+    /// lenient mode the malformed boxes which were skipped or repaired.
+    pub fn warnings(&self) -> &[Mp4Warning] {
+        &self.warnings
+    }
+
+    /// Locations of the boxes read in the header, in file order with each
+    /// box before its children. The payloads of the top-level boxes which
+    /// aren't parsed, such as mdat, aren't read for their children, and the
+    /// boxes skipped in lenient mode aren't located.
+    pub fn box_locations(&self) -> &[BoxLocation] {
+        &self.box_locations
+    }
+
+    /// Location of the moov box.
+    pub fn moov_location(&self) -> Option<&BoxLocation> {
+        self.top_level_locations(BoxType::MoovBox).next()
+    }
+
+    /// Locations of the moof boxes, those of `moofs` unless some were
+    /// skipped in lenient mode.
+    pub fn moof_locations(&self) -> impl Iterator<Item = &BoxLocation> {
+        self.top_level_locations(BoxType::MoofBox)
+    }
+
+    /// Locations of the mdat boxes, see [`BoxLocation::payload_range`] for
+    /// the range of their data.
+    pub fn mdat_locations(&self) -> impl Iterator<Item = &BoxLocation> {
+        self.top_level_locations(BoxType::MdatBox)
+    }
+
+    fn top_level_locations(&self, name: BoxType) -> impl Iterator<Item = &BoxLocation> {
+        self.box_locations
+            .iter()
+            .filter(move |location| location.path.0.len() == 1 && location.name() == Some(name))
+    }
+
+    /// Walk over all the boxes read in the header, in file order, with the
+    /// top-level boxes which aren't parsed as `AnyBox::Skipped`.
+    pub fn walk_boxes(&self) -> BoxWalk<'_> {
+        let mut roots = Vec::new();
+        let top_level = self.box_locations.iter().filter(|l| l.path.0.len() == 1);
+        for location in top_level {
+            let (name, index) = location.path.0[0];
+            let i = index as usize - 1;
+            let mp4box = match name {
+                BoxType::FtypBox if index == 1 => Some(AnyBox::Ftyp(&self.ftyp)),
+                BoxType::MoovBox if index == 1 => Some(AnyBox::Moov(&self.moov)),
+                BoxType::MoofBox => self.moofs.get(i).map(AnyBox::Moof),
+                BoxType::EmsgBox => self.emsgs.get(i).map(AnyBox::Emsg),
+                _ => None,
+            };
+            roots.push(mp4box.unwrap_or(AnyBox::Skipped(name, location.size)));
+        }
+        BoxWalk::new(roots, &self.box_locations)
+    }
+
     pub fn tracks(&self) -> &HashMap<u32, Mp4Track> {
         &self.tracks
     }
 
+    /// Build the sample index of every track, see [`Mp4Track::build_sample_index`].
+    pub fn build_sample_index(&mut self) -> Result<()> {
+        for track in self.tracks.values_mut() {
+            track.build_sample_index()?;
+        }
+        Ok(())
+    }
+
     pub fn sample_count(&self, track_id: u32) -> Result<u32> {
         if let Some(track) = self.tracks.get(&track_id) {
             Ok(track.sample_count())
@@ -160,22 +323,836 @@ impl<R: Read + Seek> Mp4Reader<R> {
         }
     }
 
-    pub fn read_sample(&mut self, track_id: u32, sample_id: u32) -> Result<Option<Mp4Sample>> {
+    /// Number of samples of a track fully in the file, see
+    /// [`Mp4Track::available_sample_count`].
+    pub fn available_sample_count(&self, track_id: u32) -> Result<u32> {
         if let Some(track) = self.tracks.get(&track_id) {
-            track.read_sample(&mut self.reader, sample_id)
+            Ok(track.available_sample_count())
         } else {
             Err(Error::TrakNotFound(track_id))
         }
     }
-}
 
-impl<R> Mp4Reader<R> {
     pub fn metadata(&self) -> impl Metadata<'_> {
         self.moov.udta.as_ref().and_then(|udta| {
             udta.meta.as_ref().and_then(|meta| match meta {
-                MetaBox::Mdir { ilst } => ilst.as_ref(),
+                MetaBox::Mdir { ilst, .. } => ilst.as_ref(),
                 _ => None,
             })
         })
     }
 }
+
+/// Read the header of a top-level box of a file of size `size`, with the
+/// position of the end of the box, which may be past the end of a truncated
+/// file.
+pub(crate) fn read_top_level_header<R: Read + Seek>(
+    reader: &mut BoxReader<R>,
+    size: u64,
+) -> Result<(BoxHeader, u64)> {
+    // A size zero box extends to the end of the file.
+    let header = BoxHeader::read_within(reader, size)?;
+    let end = box_start(reader)?
+        .checked_add(header.size)
+        .ok_or(Error::InvalidData("box size too large"))?;
+    Ok((header, end))
+}
+
+/// Top-level boxes collected while reading the header of a file.
+#[derive(Debug, Default)]
+pub(crate) struct HeaderBoxes {
+    ftyp: Option<FtypBox>,
+    moov: Option<MoovBox>,
+    moofs: Vec<MoofBox>,
+    moof_offsets: Vec<u64>,
+    emsgs: Vec<EmsgBox>,
+    warnings: Vec<Mp4Warning>,
+    locations: Vec<BoxLocation>,
+}
+
+impl HeaderBoxes {
+    /// Whether boxes of this type are parsed by `read_box` rather than skipped.
+    pub(crate) fn is_parsed(name: BoxType) -> bool {
+        matches!(
+            name,
+            BoxType::FtypBox | BoxType::MoovBox | BoxType::MoofBox | BoxType::EmsgBox
+        )
+    }
+
+    /// Whether boxes of this type are known but skipped by `read_box`.
+    pub(crate) fn is_skipped(name: BoxType) -> bool {
+        matches!(name, BoxType::MdatBox | BoxType::FreeBox | BoxType::WideBox)
+    }
+
+    /// Parse a box whose header starting at `offset` has just been read, or
+    /// fail if it's `truncated` by the end of the file.
+    pub(crate) fn read_box<R: Read + Seek>(
+        &mut self,
+        reader: &mut BoxReader<R>,
+        name: BoxType,
+        size: u64,
+        offset: u64,
+        truncated: bool,
+    ) -> Result<()> {
+        if Self::is_parsed(name) {
+            check_top_level_box_size(reader, size)?;
+            // Boxes cut off by the end of a truncated file can't be parsed,
+            // only the samples of the mdat before the cut.
+            if truncated {
+                return Err(Error::InvalidData("box extends past the end of the file"));
+            }
+        }
+        match name {
+            BoxType::FtypBox => {
+                self.ftyp = Some(FtypBox::parse_box(reader, size)?);
+            }
+            BoxType::MoovBox => {
+                self.moov = Some(MoovBox::parse_box(reader, size)?);
+            }
+            BoxType::MoofBox => {
+                let moof = MoofBox::parse_box(reader, size)?;
+                self.moofs.push(moof);
+                self.moof_offsets.push(offset);
+            }
+            BoxType::EmsgBox => {
+                let emsg = EmsgBox::parse_box(reader, size)?;
+                self.emsgs.push(emsg);
+            }
+            _ if Self::is_skipped(name) => {
+                skip_box(reader, size)?;
+            }
+            _ => {
+                skip_unknown_box(reader, size)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the required boxes and build the tracks, with their fragments,
+    /// checking their samples against the size of the file and the limits.
+    pub(crate) fn into_reader<R>(
+        self,
+        reader: R,
+        size: u64,
+        file_size: u64,
+        limits: &Mp4Limits,
+    ) -> Result<Mp4Reader<R>> {
+        let ftyp = self.ftyp.ok_or(Error::BoxNotFound(BoxType::FtypBox))?;
+        let moov = self.moov.ok_or(Error::BoxNotFound(BoxType::MoovBox))?;
+        let mut tracks = tracks_from_moov(&moov)?;
+        for track in tracks.values_mut() {
+            track.set_limits(limits);
+        }
+
+        // Update tracks if any fragmented (moof) boxes are found.
+        for (moof, &moof_offset) in self.moofs.iter().zip(self.moof_offsets.iter()) {
+            add_moof(&mut tracks, moof, moof_offset)?;
+        }
+        for track in tracks.values_mut() {
+            track.set_data_size(file_size)?;
+        }
+        Ok(Mp4Reader {
+            reader,
+            ftyp,
+            moov,
+            moofs: self.moofs,
+            emsgs: self.emsgs,
+            size,
+            tracks,
+            warnings: self.warnings,
+            box_locations: self.locations,
+        })
+    }
+}
+
+/// Build the tracks of a movie, with the fragment defaults of its trex boxes.
+pub(crate) fn tracks_from_moov(moov: &MoovBox) -> Result<HashMap<u32, Mp4Track>> {
+    if moov.traks.iter().any(|trak| trak.tkhd.track_id == 0) {
+        return Err(Error::InvalidData("illegal track id 0"));
+    }
+    let mut tracks: HashMap<u32, Mp4Track> = moov
+        .traks
+        .iter()
+        .map(|trak| (trak.tkhd.track_id, Mp4Track::new(trak, moov.mvhd.timescale)))
+        .collect();
+
+    if let Some(ref mvex) = moov.mvex {
+        for trex in mvex.trexs.iter() {
+            if let Some(track) = tracks.get_mut(&trex.track_id) {
+                track.default_sample_duration = trex.default_sample_duration;
+                track.default_sample_size = trex.default_sample_size;
+                track.default_sample_flags = trex.default_sample_flags;
+                track.default_sample_description_index = trex.default_sample_description_index;
+            }
+        }
+    }
+    Ok(tracks)
+}
+
+/// Add the track fragments of a moof starting at `moof_offset` to their tracks.
+pub(crate) fn add_moof(
+    tracks: &mut HashMap<u32, Mp4Track>,
+    moof: &MoofBox,
+    moof_offset: u64,
+) -> Result<()> {
+    // End of the data of the previous traf in this moof, which is the
+    // implicit base data offset of the next one.
+    let mut data_end = moof_offset;
+    for (traf_idx, traf) in moof.trafs.iter().enumerate() {
+        let track_id = traf.tfhd.track_id;
+        if let Some(track) = tracks.get_mut(&track_id) {
+            let base_data_offset = match traf.tfhd.base_data_offset {
+                Some(base_data_offset) => base_data_offset,
+                None if traf_idx == 0
+                    || TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF & traf.tfhd.flags > 0 =>
+                {
+                    moof_offset
+                }
+                None => data_end,
+            };
+            data_end = track.add_traf(traf.clone(), base_data_offset)?;
+        } else {
+            return Err(Error::TrakNotFound(track_id));
+        }
+    }
+    Ok(())
+}
+
+/// A sample of a fragment, whose bytes are still to be read at `offset`.
+#[derive(Debug)]
+pub(crate) struct FragmentSample {
+    pub track_id: u32,
+    pub offset: u64,
+    pub size: u64,
+    pub sample: Mp4Sample,
+}
+
+/// Replace the trafs of the tracks by those of a moof and resolve its samples,
+/// in the order of the trafs of the moof.
+pub(crate) fn fragment_samples(
+    tracks: &mut HashMap<u32, Mp4Track>,
+    moof: &MoofBox,
+    moof_offset: u64,
+) -> Result<Vec<FragmentSample>> {
+    for track in tracks.values_mut() {
+        track.clear_trafs();
+    }
+    add_moof(tracks, moof, moof_offset)?;
+
+    let mut track_ids: Vec<u32> = Vec::new();
+    for traf in moof.trafs.iter() {
+        if !track_ids.contains(&traf.tfhd.track_id) {
+            track_ids.push(traf.tfhd.track_id);
+        }
+    }
+
+    let mut samples = Vec::new();
+    for track_id in track_ids {
+        let track = tracks.get_mut(&track_id).unwrap();
+        track.build_sample_index()?;
+        for sample_id in 1..=track.sample_count() {
+            let size = track.sample_size(sample_id)?;
+            track.check_sample_size(size)?;
+            samples.push(FragmentSample {
+                track_id,
+                offset: track.sample_offset(sample_id)?,
+                size: size as u64,
+                sample: track.sample_with_bytes(sample_id, Bytes::new())?,
+            });
+        }
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp4box::{
+        edts::EdtsBox,
+        elst::{ElstBox, ElstEntry},
+        mfhd::MfhdBox,
+        mvex::MvexBox,
+        stco::StcoBox,
+        stsc::StscEntry,
+        stsd::StsdBox,
+        stts::SttsEntry,
+        tfdt::TfdtBox,
+        traf::TrafBox,
+        trak::TrakBox,
+        trex::TrexBox,
+        trun::TrunBox,
+    };
+    use crate::track::Mp4TrackWriter;
+    use std::io::{Cursor, Write};
+
+    fn audio_trak(track_id: u32) -> TrakBox {
+        let config = TrackConfig::from(AacConfig::default());
+        let mut track = Mp4TrackWriter::new(track_id, &config).unwrap();
+        track.write_end(&mut Cursor::new(Vec::new()), 1000).unwrap()
+    }
+
+    fn trun(flags: u32, data_offset: Option<i32>, sample_sizes: Vec<u32>, count: u32) -> TrunBox {
+        TrunBox {
+            flags,
+            sample_count: count,
+            data_offset,
+            sample_sizes,
+            ..TrunBox::default()
+        }
+    }
+
+    fn write_mdat(buf: &mut Cursor<Vec<u8>>, payload: &[u8]) {
+        BoxHeader::new(BoxType::MdatBox, HEADER_SIZE + payload.len() as u64)
+            .write(buf)
+            .unwrap();
+        buf.write_all(payload).unwrap();
+    }
+
+    /// A fragmented file, to which moofs are added each followed by the mdat
+    /// of their samples.
+    struct FragmentedFile {
+        buf: Cursor<Vec<u8>>,
+    }
+
+    impl FragmentedFile {
+        fn new(moov: &MoovBox) -> Self {
+            let mut buf = Cursor::new(Vec::new());
+            FtypBox::default().write_box(&mut buf).unwrap();
+            moov.write_box(&mut buf).unwrap();
+            FragmentedFile { buf }
+        }
+
+        /// A file of empty AAC tracks with ids from 1 to `track_count`.
+        fn with_tracks(track_count: u32) -> Self {
+            Self::new(&MoovBox {
+                traks: (1..=track_count).map(audio_trak).collect(),
+                ..MoovBox::default()
+            })
+        }
+
+        /// Offset from the start of `moof` of the data of its mdat.
+        fn data_offset(moof: &MoofBox) -> i32 {
+            (moof.box_size() + HEADER_SIZE) as i32
+        }
+
+        /// Offset in the file of the data of the mdat of `moof`, if it's the
+        /// next fragment added.
+        fn data_position(&self, moof: &MoofBox) -> u64 {
+            self.buf.position() + moof.box_size() + HEADER_SIZE
+        }
+
+        fn add_fragment(&mut self, moof: &MoofBox, data: &[u8]) {
+            moof.write_box(&mut self.buf).unwrap();
+            write_mdat(&mut self.buf, data);
+        }
+
+        fn read(mut self) -> Mp4Reader<Cursor<Vec<u8>>> {
+            let size = self.buf.position();
+            self.buf.set_position(0);
+            Mp4Reader::read_header(self.buf, size).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_fragmented_sample_offsets() {
+        let mut file = FragmentedFile::with_tracks(2);
+
+        // First fragment: track 1 is based on the moof and has two truns, the
+        // second one following the first. Track 2 has no base offset, so its
+        // data follows the data of track 1.
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![
+                TrafBox {
+                    tfhd: TfhdBox {
+                        flags: TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF
+                            | TfhdBox::FLAG_DEFAULT_SAMPLE_SIZE,
+                        track_id: 1,
+                        default_sample_size: Some(4),
+                        ..TfhdBox::default()
+                    },
+                    tfdt: None,
+                    truns: vec![
+                        trun(
+                            TrunBox::FLAG_DATA_OFFSET | TrunBox::FLAG_SAMPLE_SIZE,
+                            Some(0),
+                            vec![3, 5],
+                            2,
+                        ),
+                        trun(0, None, vec![], 1),
+                    ],
+                    ..TrafBox::default()
+                },
+                TrafBox {
+                    tfhd: TfhdBox {
+                        flags: TfhdBox::FLAG_DEFAULT_SAMPLE_SIZE,
+                        track_id: 2,
+                        default_sample_size: Some(2),
+                        ..TfhdBox::default()
+                    },
+                    tfdt: None,
+                    truns: vec![trun(0, None, vec![], 2)],
+                    ..TrafBox::default()
+                },
+            ],
+            ..MoofBox::default()
+        };
+        moof.trafs[0].truns[0].data_offset = Some(FragmentedFile::data_offset(&moof));
+        file.add_fragment(&moof, &[1, 1, 1, 2, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 5, 5]);
+
+        // Second fragment uses an explicit base data offset.
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![TrafBox {
+                tfhd: TfhdBox {
+                    flags: TfhdBox::FLAG_BASE_DATA_OFFSET,
+                    track_id: 1,
+                    base_data_offset: Some(0),
+                    ..TfhdBox::default()
+                },
+                tfdt: None,
+                truns: vec![trun(TrunBox::FLAG_SAMPLE_SIZE, None, vec![6], 1)],
+                ..TrafBox::default()
+            }],
+            ..MoofBox::default()
+        };
+        moof.trafs[0].tfhd.base_data_offset = Some(file.data_position(&moof));
+        file.add_fragment(&moof, &[6; 6]);
+
+        let mut mp4 = file.read();
+        assert!(mp4.is_fragmented());
+
+        let expected: &[(u32, &[u8])] = &[
+            (1, &[1, 1, 1]),
+            (1, &[2, 2, 2, 2, 2]),
+            (1, &[3, 3, 3, 3]),
+            (1, &[6, 6, 6, 6, 6, 6]),
+            (2, &[4, 4]),
+            (2, &[5, 5]),
+        ];
+        for track_id in [1, 2] {
+            let samples: Vec<&[u8]> = expected
+                .iter()
+                .filter(|(id, _)| *id == track_id)
+                .map(|(_, bytes)| *bytes)
+                .collect();
+            assert_eq!(mp4.sample_count(track_id).unwrap(), samples.len() as u32);
+            for (i, bytes) in samples.iter().enumerate() {
+                let sample = mp4.read_sample(track_id, i as u32 + 1).unwrap().unwrap();
+                assert_eq!(sample.bytes.as_ref(), *bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fragmented_sample_times() {
+        let mut file = FragmentedFile::with_tracks(1);
+
+        let tfhd = TfhdBox {
+            flags: TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF
+                | TfhdBox::FLAG_DEFAULT_SAMPLE_DURATION
+                | TfhdBox::FLAG_DEFAULT_SAMPLE_SIZE,
+            track_id: 1,
+            default_sample_duration: Some(10),
+            default_sample_size: Some(1),
+            ..TfhdBox::default()
+        };
+
+        // First fragment starts at its tfdt, with per-sample durations and
+        // composition offsets in the first trun and tfhd defaults in the second.
+        // The offset of the second trun doesn't fit in an i32.
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![TrafBox {
+                tfhd: tfhd.clone(),
+                tfdt: Some(TfdtBox {
+                    version: 1,
+                    flags: 0,
+                    base_media_decode_time: 1000,
+                }),
+                truns: vec![
+                    TrunBox {
+                        flags: TrunBox::FLAG_DATA_OFFSET
+                            | TrunBox::FLAG_SAMPLE_DURATION
+                            | TrunBox::FLAG_SAMPLE_CTS,
+                        sample_count: 2,
+                        data_offset: Some(0),
+                        sample_durations: vec![20, 30],
+                        sample_cts: vec![0, 40],
+                        ..TrunBox::default()
+                    },
+                    TrunBox {
+                        flags: TrunBox::FLAG_SAMPLE_CTS,
+                        sample_count: 1,
+                        sample_cts: vec![0x8000_0000],
+                        ..TrunBox::default()
+                    },
+                ],
+                ..TrafBox::default()
+            }],
+            ..MoofBox::default()
+        };
+        moof.trafs[0].truns[0].data_offset = Some(FragmentedFile::data_offset(&moof));
+        file.add_fragment(&moof, &[0; 3]);
+
+        // Second fragment has no tfdt and continues where the first one ended.
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![TrafBox {
+                tfhd,
+                tfdt: None,
+                truns: vec![TrunBox {
+                    version: 1,
+                    flags: TrunBox::FLAG_DATA_OFFSET | TrunBox::FLAG_SAMPLE_CTS,
+                    sample_count: 1,
+                    data_offset: Some(0),
+                    sample_cts: vec![-10i32 as u32],
+                    ..TrunBox::default()
+                }],
+                ..TrafBox::default()
+            }],
+            ..MoofBox::default()
+        };
+        moof.trafs[0].truns[0].data_offset = Some(FragmentedFile::data_offset(&moof));
+        file.add_fragment(&moof, &[0; 1]);
+
+        let mut mp4 = file.read();
+
+        let expected = [
+            (1000, 20, 0),
+            (1020, 30, 40),
+            (1050, 10, i32::MAX),
+            (1060, 10, -10),
+        ];
+        assert_eq!(mp4.sample_count(1).unwrap(), expected.len() as u32);
+        for (i, &(start_time, duration, rendering_offset)) in expected.iter().enumerate() {
+            let sample = mp4.read_sample(1, i as u32 + 1).unwrap().unwrap();
+            assert_eq!(sample.start_time, start_time);
+            assert_eq!(sample.duration, duration);
+            assert_eq!(sample.rendering_offset, rendering_offset);
+        }
+    }
+
+    #[test]
+    fn test_fragmented_sample_flags() {
+        let mut file = FragmentedFile::with_tracks(1);
+
+        let sync = 0x02000000;
+        let non_sync = 0x01010000;
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![TrafBox {
+                tfhd: TfhdBox {
+                    flags: TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF
+                        | TfhdBox::FLAG_DEFAULT_SAMPLE_SIZE
+                        | TfhdBox::FLAG_DEFAULT_SAMPLE_FLAGS,
+                    track_id: 1,
+                    default_sample_size: Some(1),
+                    default_sample_flags: Some(non_sync),
+                    ..TfhdBox::default()
+                },
+                tfdt: None,
+                truns: vec![
+                    TrunBox {
+                        flags: TrunBox::FLAG_DATA_OFFSET | TrunBox::FLAG_FIRST_SAMPLE_FLAGS,
+                        sample_count: 3,
+                        data_offset: Some(0),
+                        first_sample_flags: Some(sync),
+                        ..TrunBox::default()
+                    },
+                    TrunBox {
+                        flags: TrunBox::FLAG_SAMPLE_FLAGS,
+                        sample_count: 2,
+                        sample_flags: vec![non_sync, sync],
+                        ..TrunBox::default()
+                    },
+                ],
+                ..TrafBox::default()
+            }],
+            ..MoofBox::default()
+        };
+        moof.trafs[0].truns[0].data_offset = Some(FragmentedFile::data_offset(&moof));
+        file.add_fragment(&moof, &[0; 5]);
+
+        let mut mp4 = file.read();
+
+        let expected = [true, false, false, false, true];
+        for (i, &is_sync) in expected.iter().enumerate() {
+            let sample = mp4.read_sample(1, i as u32 + 1).unwrap().unwrap();
+            assert_eq!(sample.is_sync, is_sync);
+
+            let flags = sample.flags.unwrap();
+            assert_eq!(flags.is_sync(), is_sync);
+            assert_eq!(flags.depends_on, if is_sync { 2 } else { 1 });
+            assert_eq!(u32::from(flags), if is_sync { sync } else { non_sync });
+        }
+    }
+
+    #[test]
+    fn test_fragmented_trex_per_track() {
+        let trex = |track_id, default_sample_duration, default_sample_size| TrexBox {
+            track_id,
+            default_sample_description_index: 1,
+            default_sample_duration,
+            default_sample_size,
+            ..TrexBox::default()
+        };
+        let mut file = FragmentedFile::new(&MoovBox {
+            traks: vec![audio_trak(1), audio_trak(2)],
+            mvex: Some(MvexBox {
+                mehd: None,
+                trexs: vec![trex(1, 10, 1), trex(2, 20, 2)],
+                ..MvexBox::default()
+            }),
+            ..MoovBox::default()
+        });
+
+        let traf = |track_id| TrafBox {
+            tfhd: TfhdBox {
+                flags: TfhdBox::FLAG_DEFAULT_BASE_IS_MOOF,
+                track_id,
+                ..TfhdBox::default()
+            },
+            tfdt: None,
+            truns: vec![trun(TrunBox::FLAG_DATA_OFFSET, Some(0), vec![], 2)],
+            ..TrafBox::default()
+        };
+        let mut moof = MoofBox {
+            mfhd: MfhdBox::default(),
+            trafs: vec![traf(1), traf(2)],
+            ..MoofBox::default()
+        };
+        let data_offset = FragmentedFile::data_offset(&moof);
+        moof.trafs[0].truns[0].data_offset = Some(data_offset);
+        moof.trafs[1].truns[0].data_offset = Some(data_offset + 2);
+        file.add_fragment(&moof, &[1, 2, 3, 3, 4, 4]);
+
+        let mut mp4 = file.read();
+        assert_eq!(mp4.moov.mvex.as_ref().unwrap().trexs.len(), 2);
+
+        let expected: &[(u32, u32, u64, u32, &[u8])] = &[
+            (1, 1, 0, 10, &[1]),
+            (1, 2, 10, 10, &[2]),
+            (2, 1, 0, 20, &[3, 3]),
+            (2, 2, 20, 20, &[4, 4]),
+        ];
+        for &(track_id, sample_id, start_time, duration, bytes) in expected.iter() {
+            let sample = mp4.read_sample(track_id, sample_id).unwrap().unwrap();
+            assert_eq!(sample.start_time, start_time);
+            assert_eq!(sample.duration, duration);
+            assert_eq!(sample.bytes.as_ref(), bytes);
+        }
+    }
+
+    /// An AAC trak of 4 samples of 4 bytes lasting 1024 each, written in a
+    /// movie of timescale 600.
+    #[test]
+    fn test_fragmented_available_sample_count() {
+        let mut track = Mp4Track::new(&audio_trak(1), 1000);
+        track.set_data_size(25).unwrap();
+        let traf = TrafBox {
+            truns: vec![trun(TrunBox::FLAG_SAMPLE_SIZE, None, vec![10; 3], 3)],
+            ..TrafBox::default()
+        };
+        assert_eq!(track.add_traf(traf.clone(), 0).unwrap(), 30);
+        assert_eq!(track.sample_count(), 3);
+        assert_eq!(track.available_sample_count(), 2);
+
+        // The samples following a cut one aren't available either.
+        assert_eq!(track.add_traf(traf, 0).unwrap(), 30);
+        assert_eq!(track.sample_count(), 6);
+        assert_eq!(track.available_sample_count(), 2);
+    }
+
+    fn written_audio_trak() -> TrakBox {
+        let config = TrackConfig::from(AacConfig::default());
+        let mut writer = Mp4TrackWriter::new(1, &config).unwrap();
+        let mut buf = Cursor::new(Vec::new());
+        for i in 0..4 {
+            let sample = Mp4Sample {
+                start_time: i * 1024,
+                duration: 1024,
+                rendering_offset: 0,
+                is_sync: true,
+                flags: None,
+                sample_description_index: 1,
+                bytes: Bytes::from_static(&[0; 4]),
+            };
+            writer.write_sample(&mut buf, &sample, 600).unwrap();
+        }
+        writer.write_end(&mut buf, 600).unwrap()
+    }
+
+    #[test]
+    fn test_edit_list() {
+        let mut trak = written_audio_trak();
+
+        let track = Mp4Track::new(&trak, 600);
+        assert!(track.edits().is_empty());
+        assert_eq!(track.sample_presentation_time(1).unwrap(), Some(0));
+        assert_eq!(track.media_time(100), Some(100));
+        assert_eq!(track.presentation_delay(), 0);
+        assert_eq!(track.trimmed_start(), 0);
+        assert_eq!(track.trimmed_end().unwrap(), 4096);
+
+        // An empty edit of 500 track units, then the media from 1024 on for
+        // 2000 track units, with segment durations in the movie timescale.
+        trak.edts = Some(EdtsBox {
+            elst: Some(ElstBox {
+                version: 0,
+                flags: 0,
+                entries: vec![
+                    ElstEntry {
+                        segment_duration: 300,
+                        media_time: -1,
+                        media_rate: 1,
+                        media_rate_fraction: 0,
+                    },
+                    ElstEntry {
+                        segment_duration: 1200,
+                        media_time: 1024,
+                        media_rate: 1,
+                        media_rate_fraction: 0,
+                    },
+                ],
+            }),
+            unknown_boxes: Vec::new(),
+        });
+        let track = Mp4Track::new(&trak, 600);
+        assert_eq!(
+            track.edits(),
+            vec![
+                TrackEdit {
+                    presentation_time: 0,
+                    duration: Some(500),
+                    media_time: None,
+                    media_rate: 1,
+                },
+                TrackEdit {
+                    presentation_time: 500,
+                    duration: Some(2000),
+                    media_time: Some(1024),
+                    media_rate: 1,
+                },
+            ]
+        );
+
+        let times: Vec<_> = (1..=4)
+            .map(|id| track.sample_presentation_time(id).unwrap())
+            .collect();
+        assert_eq!(times, [None, Some(500), Some(1524), None]);
+
+        assert_eq!(track.media_time(0), None);
+        assert_eq!(track.media_time(500), Some(1024));
+        assert_eq!(track.media_time(2499), Some(3023));
+        assert_eq!(track.media_time(2500), None);
+
+        assert_eq!(track.presentation_delay(), 500);
+        assert_eq!(track.trimmed_start(), 1024);
+        assert_eq!(track.trimmed_end().unwrap(), 3024);
+
+        // The end of the first sample is presented when the edit starts
+        // within it.
+        let elst = trak.edts.as_mut().unwrap().elst.as_mut().unwrap();
+        elst.entries[1].media_time = 1000;
+        let track = Mp4Track::new(&trak, 600);
+        let times: Vec<_> = (1..=4)
+            .map(|id| track.sample_presentation_time(id).unwrap())
+            .collect();
+        assert_eq!(times, [Some(500), Some(524), Some(1548), None]);
+    }
+
+    #[test]
+    fn test_sample_descriptions() {
+        let mut trak = written_audio_trak();
+
+        // Two chunks of two samples, the second one with a stereo entry.
+        let stbl = &mut trak.mdia.minf.stbl;
+        let mut stereo = stbl.stsd.mp4a().unwrap().clone();
+        stereo.channelcount = 2;
+        stbl.stsd.entries.push(SampleEntry::Mp4a(stereo.clone()));
+        stbl.stsc.entries = vec![
+            StscEntry {
+                first_chunk: 1,
+                samples_per_chunk: 2,
+                sample_description_index: 1,
+                first_sample: 1,
+            },
+            StscEntry {
+                first_chunk: 2,
+                samples_per_chunk: 2,
+                sample_description_index: 2,
+                first_sample: 3,
+            },
+        ];
+        stbl.stco = Some(StcoBox {
+            entries: vec![0, 8],
+            ..StcoBox::default()
+        });
+        stbl.co64 = None;
+
+        let mut buf = Vec::new();
+        stbl.stsd.write_box(&mut buf).unwrap();
+        let mut reader = Cursor::new(&buf);
+        let header = BoxHeader::read(&mut reader).unwrap();
+        let stsd = StsdBox::read_box(&mut reader, header.size).unwrap();
+        assert_eq!(stsd, stbl.stsd);
+
+        let mut track = Mp4Track::new(&trak, 600);
+        for _ in 0..2 {
+            let indexes: Vec<_> = (1..=4)
+                .map(|id| track.sample_description_index(id).unwrap())
+                .collect();
+            assert_eq!(indexes, [1, 1, 2, 2]);
+            assert_eq!(
+                track.sample_entry(3).unwrap(),
+                Some(&SampleEntry::Mp4a(stereo.clone()))
+            );
+            let sample = track.sample_with_bytes(4, Bytes::new()).unwrap();
+            assert_eq!(sample.sample_description_index, 2);
+            track.build_sample_index().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sample_index_large_chunk() {
+        // A chunk of three 3 GiB samples, whose offsets in the chunk don't all
+        // fit in 32 bits.
+        let mut trak = audio_trak(1);
+        let stbl = &mut trak.mdia.minf.stbl;
+        stbl.stsz.sample_size = 0;
+        stbl.stsz.sample_count = 3;
+        stbl.stsz.sample_sizes = vec![3 << 30; 3];
+        stbl.stts.entries = vec![SttsEntry {
+            sample_count: 3,
+            sample_delta: 1024,
+        }];
+        stbl.stsc.entries = vec![StscEntry {
+            first_chunk: 1,
+            samples_per_chunk: 3,
+            sample_description_index: 1,
+            first_sample: 1,
+        }];
+        stbl.stco = Some(StcoBox {
+            entries: vec![100],
+            ..StcoBox::default()
+        });
+        stbl.co64 = None;
+
+        let mut track = Mp4Track::new(&trak, 1000);
+        let mut locations = Vec::new();
+        for _ in 0..2 {
+            let samples: Vec<_> = (1..=3)
+                .map(|id| {
+                    let location = track.sample_location(id).unwrap().unwrap();
+                    let sample = track.sample_with_bytes(id, Bytes::new()).unwrap();
+                    (location, sample)
+                })
+                .collect();
+            locations.push(samples);
+            track.build_sample_index().unwrap();
+        }
+        assert_eq!(locations[0], locations[1]);
+        assert_eq!(locations[1][2].0, (100 + (6 << 30), 3 << 30));
+    }
+}
@@ -0,0 +1,199 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::*;
+
+// Largest read done ahead of the sample being read.
+const READ_AHEAD_SIZE: u64 = 1 << 20;
+
+/// Order of the samples of [`Mp4Reader::interleaved_samples`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleOrder {
+    FileOffset,
+    DecodeTime,
+}
+
+/// Iterator over the samples of a track, see [`Mp4Reader::samples`].
+#[derive(Debug)]
+pub struct Samples<'a, R> {
+    mp4: &'a mut Mp4Reader<R>,
+    track_id: u32,
+    next_sample_id: u32,
+    buf: ReadAhead,
+}
+
+impl<'a, R: Read + Seek> Samples<'a, R> {
+    pub(crate) fn new(mp4: &'a mut Mp4Reader<R>, track_id: u32) -> Result<Self> {
+        build_sample_index(mp4, track_id)?;
+        Ok(Samples {
+            mp4,
+            track_id,
+            next_sample_id: 1,
+            buf: ReadAhead::default(),
+        })
+    }
+}
+
+impl<R: Read + Seek> Iterator for Samples<'_, R> {
+    type Item = Result<Mp4Sample>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let track = &self.mp4.tracks[&self.track_id];
+        if self.next_sample_id > track.sample_count() {
+            return None;
+        }
+        let sample_id = self.next_sample_id;
+        self.next_sample_id += 1;
+        Some(self.buf.read_sample(&mut self.mp4.reader, track, sample_id))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let sample_count = self.mp4.tracks[&self.track_id].sample_count();
+        let remaining = (sample_count + 1).saturating_sub(self.next_sample_id) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Iterator over the samples of all tracks with their track id, see
+/// [`Mp4Reader::interleaved_samples`].
+#[derive(Debug)]
+pub struct InterleavedSamples<'a, R> {
+    mp4: &'a mut Mp4Reader<R>,
+    order: SampleOrder,
+    // Track id and next sample id of each track.
+    next_sample_ids: Vec<(u32, u32)>,
+    buf: ReadAhead,
+}
+
+impl<'a, R: Read + Seek> InterleavedSamples<'a, R> {
+    pub(crate) fn new(mp4: &'a mut Mp4Reader<R>, order: SampleOrder) -> Result<Self> {
+        let mut track_ids: Vec<u32> = mp4.tracks.keys().copied().collect();
+        track_ids.sort_unstable();
+        for &track_id in track_ids.iter() {
+            build_sample_index(mp4, track_id)?;
+        }
+        Ok(InterleavedSamples {
+            mp4,
+            order,
+            next_sample_ids: track_ids.into_iter().map(|id| (id, 1)).collect(),
+            buf: ReadAhead::default(),
+        })
+    }
+
+    /// Track and sample id of the next sample, and the index of the track in
+    /// `next_sample_ids`.
+    fn next_sample(&self) -> Result<Option<(usize, u32, u32)>> {
+        let mut next: Option<(usize, u32, u32)> = None;
+        for (i, &(track_id, sample_id)) in self.next_sample_ids.iter().enumerate() {
+            let track = &self.mp4.tracks[&track_id];
+            if sample_id > track.sample_count() {
+                continue;
+            }
+            let is_before = match next {
+                None => true,
+                Some((_, next_track_id, next_sample_id)) => {
+                    let next_track = &self.mp4.tracks[&next_track_id];
+                    match self.order {
+                        SampleOrder::FileOffset => {
+                            track.sample_offset(sample_id)?
+                                < next_track.sample_offset(next_sample_id)?
+                        }
+                        SampleOrder::DecodeTime => {
+                            // Compare the times in seconds without rounding.
+                            let time = track.sample_time(sample_id)?.0 as u128;
+                            let next_time = next_track.sample_time(next_sample_id)?.0 as u128;
+                            time * (next_track.timescale() as u128)
+                                < next_time * (track.timescale() as u128)
+                        }
+                    }
+                }
+            };
+            if is_before {
+                next = Some((i, track_id, sample_id));
+            }
+        }
+        Ok(next)
+    }
+}
+
+impl<R: Read + Seek> Iterator for InterleavedSamples<'_, R> {
+    type Item = Result<(u32, Mp4Sample)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (i, track_id, sample_id) = match self.next_sample() {
+            Ok(next) => next?,
+            Err(err) => return Some(Err(err)),
+        };
+        self.next_sample_ids[i].1 += 1;
+
+        let track = &self.mp4.tracks[&track_id];
+        let sample = self.buf.read_sample(&mut self.mp4.reader, track, sample_id);
+        Some(sample.map(|sample| (track_id, sample)))
+    }
+}
+
+fn build_sample_index<R>(mp4: &mut Mp4Reader<R>, track_id: u32) -> Result<()> {
+    match mp4.tracks.get_mut(&track_id) {
+        Some(track) if track.has_sample_index() => Ok(()),
+        Some(track) => track.build_sample_index(),
+        None => Err(Error::TrakNotFound(track_id)),
+    }
+}
+
+/// Data read at once for the contiguous samples of a chunk, so that reading
+/// them one by one doesn't seek for each of them.
+#[derive(Debug, Default)]
+struct ReadAhead {
+    offset: u64,
+    data: Bytes,
+}
+
+impl ReadAhead {
+    fn read_sample<R: Read + Seek>(
+        &mut self,
+        reader: &mut R,
+        track: &Mp4Track,
+        sample_id: u32,
+    ) -> Result<Mp4Sample> {
+        let (offset, size) = match track.checked_sample_location(sample_id)? {
+            Some(location) => location,
+            None => {
+                return Err(Error::EntryInStblNotFound(
+                    track.track_id(),
+                    BoxType::StszBox,
+                    sample_id,
+                ))
+            }
+        };
+        let end = offset + size as u64;
+        if offset < self.offset || end > self.offset + self.data.len() as u64 {
+            // Read ahead the following samples that directly follow this one,
+            // up to the end of the file.
+            let data_size = track.data_size().unwrap_or(u64::MAX);
+            let mut read_end = end;
+            let mut next_id = sample_id + 1;
+            while next_id <= track.sample_count() {
+                match track.sample_location(next_id)? {
+                    Some((next_offset, next_size))
+                        if next_offset == read_end
+                            && read_end + next_size as u64 - offset <= READ_AHEAD_SIZE
+                            && read_end + next_size as u64 <= data_size =>
+                    {
+                        read_end += next_size as u64;
+                        next_id += 1;
+                    }
+                    _ => break,
+                }
+            }
+
+            let mut data = vec![0u8; (read_end - offset) as usize];
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(&mut data)?;
+            self.offset = offset;
+            self.data = Bytes::from(data);
+        }
+
+        let start = (offset - self.offset) as usize;
+        let bytes = self.data.slice(start..start + size as usize);
+        track.sample_with_bytes(sample_id, bytes)
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read};
+
+use crate::reader::{fragment_samples, tracks_from_moov, HeaderBoxes};
+use crate::*;
+
+const MDAT_WITHOUT_MOOF: &str = "mdat without moof skipped";
+
+/// A movie fragment: a moof box together with the samples of its mdat.
+#[derive(Debug)]
+pub struct Mp4Fragment {
+    pub moof: MoofBox,
+    pub emsgs: Vec<EmsgBox>,
+
+    /// `(track_id, sample)` pairs in the order of the trafs of the moof.
+    pub samples: Vec<(u32, Mp4Sample)>,
+}
+
+/// Forward-only reader of fragmented MP4 from a non-seekable source.
+///
+/// The init segment (ftyp and moov) is read by `read_header`, after which
+/// every call to `next_fragment` returns the next moof with the samples of
+/// the mdat following it. The boxes read into memory, mdat included, are
+/// limited to `Mp4Limits::max_box_size`.
+#[derive(Debug)]
+pub struct Mp4StreamReader<R> {
+    reader: R,
+    pub ftyp: FtypBox,
+    pub moov: MoovBox,
+
+    tracks: HashMap<u32, Mp4Track>,
+    limits: Mp4Limits,
+    context: ParseContext,
+
+    // Absolute offsets of the stream position and of the last read box.
+    offset: u64,
+    box_offset: u64,
+}
+
+impl<R: Read> Mp4StreamReader<R> {
+    pub fn read_header(reader: R) -> Result<Self> {
+        Self::read_header_with_config(reader, &Mp4ReaderConfig::default())
+    }
+
+    /// Read the init segment with the options of an `Mp4Reader`, see
+    /// [`Mp4Reader::read_header_with_config`].
+    pub fn read_header_with_config(reader: R, config: &Mp4ReaderConfig) -> Result<Self> {
+        let mut stream = Mp4StreamReader {
+            reader,
+            ftyp: FtypBox::default(),
+            moov: MoovBox::default(),
+            tracks: HashMap::new(),
+            limits: config.limits,
+            context: ParseContext::new(config),
+            offset: 0,
+            box_offset: 0,
+        };
+
+        let mut ftyp = None;
+        let mut moov = None;
+        while moov.is_none() {
+            let BoxHeader { name, size } = match stream.read_header_box()? {
+                Some(header) => header,
+                None => break,
+            };
+            match name {
+                BoxType::FtypBox => {
+                    ftyp = stream.read_box::<FtypBox>(name, size)?.or(ftyp);
+                }
+                BoxType::MoovBox => {
+                    moov = stream.read_box::<MoovBox>(name, size)?;
+                }
+                _ => {
+                    stream.skip_box(name, size, None)?;
+                }
+            }
+        }
+
+        stream.ftyp = ftyp.ok_or(Error::BoxNotFound(BoxType::FtypBox))?;
+        stream.moov = moov.ok_or(Error::BoxNotFound(BoxType::MoovBox))?;
+        stream.tracks = tracks_from_moov(&stream.moov)?;
+        for track in stream.tracks.values_mut() {
+            track.set_limits(&config.limits);
+        }
+        Ok(stream)
+    }
+
+    /// Read the next fragment, or `None` at the end of the stream.
+    pub fn next_fragment(&mut self) -> Result<Option<Mp4Fragment>> {
+        let mut emsgs = Vec::new();
+        let mut moof = None;
+        while let Some(BoxHeader { name, size }) = self.read_header_box()? {
+            match name {
+                BoxType::MoofBox => {
+                    if moof.is_some() {
+                        return Err(Error::BoxNotFound(BoxType::MdatBox));
+                    }
+                    let moof_offset = self.box_offset;
+                    moof = self
+                        .read_box::<MoofBox>(name, size)?
+                        .map(|moof| (moof, moof_offset));
+                }
+                BoxType::MdatBox => {
+                    if let Some((moof, moof_offset)) = moof.take() {
+                        self.context
+                            .count_top_level_box(name, self.box_offset, None);
+                        let mdat_offset = self.offset;
+                        let mdat = Bytes::from(self.read_payload(size, Vec::new())?);
+                        return self
+                            .fragment(moof, moof_offset, emsgs, mdat_offset, mdat)
+                            .map(Some);
+                    }
+                    self.skip_box(name, size, Some(MDAT_WITHOUT_MOOF))?;
+                }
+                BoxType::EmsgBox => {
+                    emsgs.extend(self.read_box::<EmsgBox>(name, size)?);
+                }
+                _ => {
+                    self.skip_box(name, size, None)?;
+                }
+            }
+        }
+
+        if moof.is_some() {
+            return Err(Error::BoxNotFound(BoxType::MdatBox));
+        }
+        Ok(None)
+    }
+
+    /// Tracks of the movie, holding the trafs of the last read fragment.
+    pub fn tracks(&self) -> &HashMap<u32, Mp4Track> {
+        &self.tracks
+    }
+
+    /// Problems found in lenient mode so far, see [`Mp4Reader::warnings`].
+    pub fn warnings(&self) -> &[Mp4Warning] {
+        self.context.warnings()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn fragment(
+        &mut self,
+        moof: MoofBox,
+        moof_offset: u64,
+        emsgs: Vec<EmsgBox>,
+        mdat_offset: u64,
+        mdat: Bytes,
+    ) -> Result<Mp4Fragment> {
+        let mut samples = Vec::new();
+        for mut fragment_sample in fragment_samples(&mut self.tracks, &moof, moof_offset)? {
+            let size = fragment_sample.size;
+            let start = fragment_sample
+                .offset
+                .checked_sub(mdat_offset)
+                .filter(|start| start + size <= mdat.len() as u64)
+                .ok_or(Error::InvalidData("sample data outside of mdat"))?;
+            fragment_sample.sample.bytes = mdat.slice(start as usize..(start + size) as usize);
+            samples.push((fragment_sample.track_id, fragment_sample.sample));
+        }
+
+        Ok(Mp4Fragment {
+            moof,
+            emsgs,
+            samples,
+        })
+    }
+
+    /// Read the next box header, or `None` at the end of the stream. A size
+    /// of 0 is kept for a box extending to the end of the stream.
+    fn read_header_box(&mut self) -> Result<Option<BoxHeader>> {
+        let mut buf = [0u8; 16];
+        loop {
+            match self.reader.read(&mut buf[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.box_offset = self.offset;
+        self.reader.read_exact(&mut buf[1..8])?;
+        let mut len = 8;
+        if buf[0..4] == [0, 0, 0, 1] {
+            self.reader.read_exact(&mut buf[8..16])?;
+            len = 16;
+        }
+        self.offset += len as u64;
+
+        let header = BoxHeader::read(&mut &buf[..len])?;
+        if header.size != 0 && header.size < HEADER_SIZE {
+            return Err(Error::InvalidData("box size too small"));
+        }
+        Ok(Some(header))
+    }
+
+    /// Append the payload of a box to `buf`, which grows with the data
+    /// actually read rather than with the declared box size. Boxes over the
+    /// size limit fail before they are read.
+    fn read_payload(&mut self, size: u64, mut buf: Vec<u8>) -> Result<Vec<u8>> {
+        let max_box_size = self.limits.max_box_size;
+        if size == 0 {
+            // Read up to a byte past the limit, to tell whether it's over it.
+            let max_len = max_box_size.saturating_sub(HEADER_SIZE);
+            let take = max_len.saturating_add(1);
+            let read = (&mut self.reader).take(take).read_to_end(&mut buf)? as u64;
+            self.offset += read;
+            if read > max_len {
+                let size = read.saturating_add(HEADER_SIZE);
+                return Err(Error::LimitExceeded("box size", size, max_box_size));
+            }
+            return Ok(buf);
+        }
+        if size > max_box_size {
+            return Err(Error::LimitExceeded("box size", size, max_box_size));
+        }
+        let len = size - HEADER_SIZE;
+        let read = (&mut self.reader).take(len).read_to_end(&mut buf)? as u64;
+        if read < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.offset += len;
+        Ok(buf)
+    }
+
+    fn skip_payload(&mut self, size: u64) -> Result<()> {
+        if size == 0 {
+            self.offset += io::copy(&mut self.reader, &mut io::sink())?;
+            return Ok(());
+        }
+        let len = size - HEADER_SIZE;
+        let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.offset += len;
+        Ok(())
+    }
+
+    /// Skip a box which isn't read, with `warning` or else with a warning if
+    /// its type isn't known.
+    fn skip_box(&mut self, name: BoxType, size: u64, warning: Option<&str>) -> Result<()> {
+        let is_known = HeaderBoxes::is_parsed(name) || HeaderBoxes::is_skipped(name);
+        let warning = warning.or((!is_known).then_some(UNKNOWN_BOX_SKIPPED));
+        self.context
+            .count_top_level_box(name, self.box_offset, warning);
+        self.skip_payload(size)
+    }
+
+    /// Read a box payload and parse it with the seekable box parsers, or in
+    /// lenient mode skip it with a warning if it fails to parse, returning
+    /// `None`.
+    fn read_box<B: ParseBox>(&mut self, name: BoxType, size: u64) -> Result<Option<B>> {
+        let header_size = self.offset - self.box_offset;
+        // Room for the header, so that the box starts at position 0.
+        let payload = self.read_payload(size, vec![0u8; HEADER_SIZE as usize]);
+        let over_limit = matches!(payload, Err(Error::LimitExceeded(..)));
+        let (buf, payload) = match payload {
+            Ok(buf) => (buf, Ok(())),
+            Err(err) => (vec![0u8; HEADER_SIZE as usize], Err(err)),
+        };
+        let box_size = match payload {
+            Ok(()) => buf.len() as u64,
+            Err(_) => size.max(HEADER_SIZE),
+        };
+
+        let mut cursor = Cursor::new(buf);
+        cursor.set_position(HEADER_SIZE);
+        let mut context = std::mem::take(&mut self.context);
+        context.set_base_offset(self.box_offset + header_size - HEADER_SIZE);
+        context.set_header_size(header_size);
+        let mut reader = BoxReader::new(cursor, context);
+        let mut parsed = None;
+        let result = read_child(&mut reader, name, box_size, |reader| {
+            payload?;
+            parsed = Some(B::parse_box(reader, box_size)?);
+            Ok(())
+        });
+
+        // Only the warnings are kept, not the locations of the boxes.
+        self.context = reader.into_parts().1;
+        self.context.take_locations();
+        result?;
+
+        // The rest of a box skipped for its size is left to skip.
+        if over_limit {
+            self.skip_payload(size)?;
+        }
+        Ok(parsed)
+    }
+}
+
+impl<R: Read> Iterator for Mp4StreamReader<R> {
+    type Item = Result<Mp4Fragment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_fragment().transpose()
+    }
+}
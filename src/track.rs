@@ -6,10 +6,28 @@ use std::time::Duration;
 
 use crate::mp4box::traf::TrafBox;
 use crate::mp4box::trak::TrakBox;
+use crate::mp4box::trun::TrunBox;
 use crate::mp4box::{
-    avc1::Avc1Box, co64::Co64Box, ctts::CttsBox, ctts::CttsEntry, hev1::Hev1Box, mp4a::Mp4aBox,
-    smhd::SmhdBox, stco::StcoBox, stsc::StscEntry, stss::StssBox, stts::SttsEntry, tx3g::Tx3gBox,
-    vmhd::VmhdBox, vp09::Vp09Box,
+    av01::Av01Box,
+    avc1::Avc1Box,
+    co64::Co64Box,
+    ctts::CttsBox,
+    ctts::CttsEntry,
+    edts::EdtsBox,
+    elst::{ElstBox, ElstEntry},
+    flac::FlacBox,
+    hev1::{Hev1Box, Hvc1Box, HvcCBox},
+    hev1::{PPS_NAL_UNIT_TYPE, SPS_NAL_UNIT_TYPE, VPS_NAL_UNIT_TYPE},
+    mp4a::Mp4aBox,
+    opus::OpusBox,
+    smhd::SmhdBox,
+    stco::StcoBox,
+    stsc::StscEntry,
+    stss::StssBox,
+    stts::SttsEntry,
+    tx3g::Tx3gBox,
+    vmhd::VmhdBox,
+    vp09::Vp09Box,
 };
 use crate::*;
 
@@ -27,8 +45,11 @@ impl From<MediaConfig> for TrackConfig {
             MediaConfig::AvcConfig(avc_conf) => Self::from(avc_conf),
             MediaConfig::HevcConfig(hevc_conf) => Self::from(hevc_conf),
             MediaConfig::AacConfig(aac_conf) => Self::from(aac_conf),
+            MediaConfig::OpusConfig(opus_conf) => Self::from(opus_conf),
+            MediaConfig::FlacConfig(flac_conf) => Self::from(flac_conf),
             MediaConfig::TtxtConfig(ttxt_conf) => Self::from(ttxt_conf),
             MediaConfig::Vp9Config(vp9_config) => Self::from(vp9_config),
+            MediaConfig::Av1Config(av1_config) => Self::from(av1_config),
         }
     }
 }
@@ -66,6 +87,33 @@ impl From<AacConfig> for TrackConfig {
     }
 }
 
+impl From<OpusConfig> for TrackConfig {
+    fn from(opus_conf: OpusConfig) -> Self {
+        Self {
+            track_type: TrackType::Audio,
+            timescale: 48000,              // Opus is always decoded at 48 kHz
+            language: String::from("und"), // XXX
+            media_conf: MediaConfig::OpusConfig(opus_conf),
+        }
+    }
+}
+
+impl From<FlacConfig> for TrackConfig {
+    fn from(flac_conf: FlacConfig) -> Self {
+        // One tick per sample, as recommended.
+        let timescale = match flac_conf.stream_info.sample_rate {
+            0 => 1000,
+            sample_rate => sample_rate,
+        };
+        Self {
+            track_type: TrackType::Audio,
+            timescale,
+            language: String::from("und"), // XXX
+            media_conf: MediaConfig::FlacConfig(flac_conf),
+        }
+    }
+}
+
 impl From<TtxtConfig> for TrackConfig {
     fn from(txtt_conf: TtxtConfig) -> Self {
         Self {
@@ -88,23 +136,194 @@ impl From<Vp9Config> for TrackConfig {
     }
 }
 
+impl From<Av1Config> for TrackConfig {
+    fn from(av1_conf: Av1Config) -> Self {
+        Self {
+            track_type: TrackType::Video,
+            timescale: 1000,               // XXX
+            language: String::from("und"), // XXX
+            media_conf: MediaConfig::Av1Config(av1_conf),
+        }
+    }
+}
+
+/// An edit of the edit list of a track, with times in the track timescale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackEdit {
+    /// Start of the edit on the presentation timeline.
+    pub presentation_time: u64,
+
+    /// `None` for a last edit extending to the end of the media.
+    pub duration: Option<u64>,
+
+    /// Media time presented at the start of the edit, `None` for an empty edit.
+    pub media_time: Option<i64>,
+
+    pub media_rate: u16,
+}
+
 #[derive(Debug)]
 pub struct Mp4Track {
     pub trak: TrakBox,
     pub trafs: Vec<TrafBox>,
 
+    // Edit list in the track timescale, see `edits`.
+    edits: Vec<TrackEdit>,
+
+    // Resolved base data offset and base decode time of each traf, see `add_traf`.
+    base_data_offsets: Vec<u64>,
+    base_decode_times: Vec<u64>,
+    // Decode time at the end of the last added traf.
+    next_decode_time: u64,
+    // Number of samples of the added trafs.
+    traf_sample_count: u32,
+
+    sample_index: Option<SampleIndex>,
+
+    // Size of the file the samples are read from, and the number of samples
+    // fully in it, see `set_data_size`.
+    data_size: Option<u64>,
+    available_sample_count: Option<u32>,
+    limits: Mp4Limits,
+
     // Fragmented Tracks Defaults.
     pub default_sample_duration: u32,
+    pub default_sample_size: u32,
+    pub default_sample_flags: u32,
+    pub default_sample_description_index: u32,
 }
 
 impl Mp4Track {
-    pub(crate) fn from(trak: &TrakBox) -> Self {
+    pub(crate) fn new(trak: &TrakBox, movie_timescale: u32) -> Self {
         let trak = trak.clone();
         Self {
+            edits: track_edits(&trak, movie_timescale),
             trak,
             trafs: Vec::new(),
+            base_data_offsets: Vec::new(),
+            base_decode_times: Vec::new(),
+            next_decode_time: 0,
+            traf_sample_count: 0,
+            sample_index: None,
+            data_size: None,
+            available_sample_count: None,
+            limits: Mp4Limits::default(),
             default_sample_duration: 0,
+            default_sample_size: 0,
+            default_sample_flags: 0,
+            default_sample_description_index: 1,
+        }
+    }
+
+    /// Append a track fragment whose base data offset has already been resolved
+    /// by the caller (ISO/IEC 14496-12 8.8.7.1), returning the absolute offset
+    /// of the end of the sample data described by the fragment.
+    ///
+    /// The decode time of the fragment is taken from its tfdt, or continues
+    /// from the end of the previous fragment of this track if there is none.
+    pub(crate) fn add_traf(&mut self, traf: TrafBox, base_data_offset: u64) -> Result<u64> {
+        // Fragments are cheap to declare samples in, bound what is built for
+        // them as for the sample tables.
+        let mut sample_count = self.traf_sample_count as u64;
+        for trun in traf.truns.iter() {
+            sample_count += trun.sample_count as u64;
+        }
+        let max_sample_count = self.limits.max_table_entries as u64;
+        if sample_count > max_sample_count {
+            return Err(Error::LimitExceeded(
+                "sample count",
+                sample_count,
+                max_sample_count,
+            ));
+        }
+
+        // The samples of a recording are stored in order, so those of a
+        // truncated file are available up to the first one cut.
+        let data_size = self.data_size.unwrap_or(u64::MAX);
+        let mut available_count = self.available_sample_count();
+        let mut is_cut = available_count < self.traf_sample_count;
+        let mut data_end = base_data_offset;
+        for trun in traf.truns.iter() {
+            let trun_offset = self.trun_data_offset(base_data_offset, data_end, trun)?;
+            let mut trun_size = 0u64;
+            for sample_idx in 0..trun.sample_count as usize {
+                trun_size += self.trun_sample_size(&traf, trun, sample_idx)? as u64;
+                is_cut = is_cut || trun_offset.saturating_add(trun_size) > data_size;
+                if !is_cut {
+                    available_count += 1;
+                }
+            }
+            data_end = trun_offset
+                .checked_add(trun_size)
+                .ok_or(Error::InvalidData("trun data offset out of range"))?;
+        }
+
+        let base_decode_time = match traf.tfdt {
+            Some(ref tfdt) => tfdt.base_media_decode_time,
+            None => self.next_decode_time,
+        };
+        self.next_decode_time = base_decode_time.saturating_add(self.traf_duration(&traf)?);
+
+        self.sample_index = None;
+        self.trafs.push(traf);
+        self.base_data_offsets.push(base_data_offset);
+        self.base_decode_times.push(base_decode_time);
+        self.traf_sample_count = sample_count as u32;
+        if self.data_size.is_some() {
+            self.available_sample_count = Some(available_count);
+        }
+        Ok(data_end)
+    }
+
+    /// Drop the track fragments added so far, keeping the decode time at
+    /// which the next fragment continues. Sample ids restart at 1.
+    pub(crate) fn clear_trafs(&mut self) {
+        self.sample_index = None;
+        self.trafs.clear();
+        self.base_data_offsets.clear();
+        self.base_decode_times.clear();
+        self.traf_sample_count = 0;
+        self.available_sample_count = None;
+    }
+
+    /// Check the samples against the size of the file they are read from, so
+    /// that those cut off by the end of a truncated file are found.
+    pub(crate) fn set_data_size(&mut self, size: u64) -> Result<()> {
+        self.data_size = Some(size);
+        self.update_available_sample_count()
+    }
+
+    pub(crate) fn data_size(&self) -> Option<u64> {
+        self.data_size
+    }
+
+    /// Limit the samples added by fragments and those read.
+    pub(crate) fn set_limits(&mut self, limits: &Mp4Limits) {
+        self.limits = *limits;
+    }
+
+    fn update_available_sample_count(&mut self) -> Result<()> {
+        let size = match self.data_size {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+
+        // The samples of a recording are stored in order, so those of a
+        // truncated file are available up to the cut, which is searched for.
+        let (mut low, mut high) = (0, self.sample_count());
+        while low < high {
+            let count = low + (high - low) / 2 + 1;
+            match self.sample_location(count)? {
+                Some((offset, sample_size))
+                    if offset.saturating_add(sample_size as u64) <= size =>
+                {
+                    low = count
+                }
+                _ => high = count - 1,
+            }
         }
+        self.available_sample_count = Some(low);
+        Ok(())
     }
 
     pub fn track_id(&self) -> u32 {
@@ -116,39 +335,30 @@ impl Mp4Track {
     }
 
     pub fn media_type(&self) -> Result<MediaType> {
-        if self.trak.mdia.minf.stbl.stsd.avc1.is_some() {
-            Ok(MediaType::H264)
-        } else if self.trak.mdia.minf.stbl.stsd.hev1.is_some() {
-            Ok(MediaType::H265)
-        } else if self.trak.mdia.minf.stbl.stsd.vp09.is_some() {
-            Ok(MediaType::VP9)
-        } else if self.trak.mdia.minf.stbl.stsd.mp4a.is_some() {
-            Ok(MediaType::AAC)
-        } else if self.trak.mdia.minf.stbl.stsd.tx3g.is_some() {
-            Ok(MediaType::TTXT)
-        } else {
-            Err(Error::InvalidData("unsupported media type"))
+        match self.trak.mdia.minf.stbl.stsd.entries.first() {
+            Some(SampleEntry::Avc1(_)) => Ok(MediaType::H264),
+            Some(SampleEntry::Hev1(_)) | Some(SampleEntry::Hvc1(_)) => Ok(MediaType::H265),
+            Some(SampleEntry::Vp09(_)) => Ok(MediaType::VP9),
+            Some(SampleEntry::Av01(_)) => Ok(MediaType::AV1),
+            Some(SampleEntry::Mp4a(_)) => Ok(MediaType::AAC),
+            Some(SampleEntry::Opus(_)) => Ok(MediaType::OPUS),
+            Some(SampleEntry::Flac(_)) => Ok(MediaType::FLAC),
+            Some(SampleEntry::Tx3g(_)) => Ok(MediaType::TTXT),
+            _ => Err(Error::InvalidData("unsupported media type")),
         }
     }
 
     pub fn box_type(&self) -> Result<FourCC> {
-        if self.trak.mdia.minf.stbl.stsd.avc1.is_some() {
-            Ok(FourCC::from(BoxType::Avc1Box))
-        } else if self.trak.mdia.minf.stbl.stsd.hev1.is_some() {
-            Ok(FourCC::from(BoxType::Hev1Box))
-        } else if self.trak.mdia.minf.stbl.stsd.vp09.is_some() {
-            Ok(FourCC::from(BoxType::Vp09Box))
-        } else if self.trak.mdia.minf.stbl.stsd.mp4a.is_some() {
-            Ok(FourCC::from(BoxType::Mp4aBox))
-        } else if self.trak.mdia.minf.stbl.stsd.tx3g.is_some() {
-            Ok(FourCC::from(BoxType::Tx3gBox))
-        } else {
-            Err(Error::InvalidData("unsupported sample entry box"))
+        match self.trak.mdia.minf.stbl.stsd.entries.first() {
+            Some(SampleEntry::Unknown(..)) | None => {
+                Err(Error::InvalidData("unsupported sample entry box"))
+            }
+            Some(entry) => Ok(FourCC::from(entry.box_type())),
         }
     }
 
     pub fn width(&self) -> u16 {
-        if let Some(ref avc1) = self.trak.mdia.minf.stbl.stsd.avc1 {
+        if let Some(avc1) = self.trak.mdia.minf.stbl.stsd.avc1() {
             avc1.width
         } else {
             self.trak.tkhd.width.value()
@@ -156,7 +366,7 @@ impl Mp4Track {
     }
 
     pub fn height(&self) -> u16 {
-        if let Some(ref avc1) = self.trak.mdia.minf.stbl.stsd.avc1 {
+        if let Some(avc1) = self.trak.mdia.minf.stbl.stsd.avc1() {
             avc1.height
         } else {
             self.trak.tkhd.height.value()
@@ -165,15 +375,14 @@ impl Mp4Track {
 
     pub fn frame_rate(&self) -> f64 {
         let dur_msec = self.duration().as_millis() as u64;
-        if dur_msec > 0 {
-            ((self.sample_count() as u64 * 1000) / dur_msec) as f64
-        } else {
-            0.0
+        match (self.sample_count() as u64 * 1000).checked_div(dur_msec) {
+            Some(frame_rate) => frame_rate as f64,
+            None => 0.0,
         }
     }
 
     pub fn sample_freq_index(&self) -> Result<SampleFreqIndex> {
-        if let Some(ref mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a {
+        if let Some(mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a() {
             if let Some(ref esds) = mp4a.esds {
                 SampleFreqIndex::try_from(esds.es_desc.dec_config.dec_specific.freq_index)
             } else {
@@ -185,7 +394,7 @@ impl Mp4Track {
     }
 
     pub fn channel_config(&self) -> Result<ChannelConfig> {
-        if let Some(ref mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a {
+        if let Some(mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a() {
             if let Some(ref esds) = mp4a.esds {
                 ChannelConfig::try_from(esds.es_desc.dec_config.dec_specific.chan_conf)
             } else {
@@ -205,13 +414,15 @@ impl Mp4Track {
     }
 
     pub fn duration(&self) -> Duration {
-        Duration::from_micros(
-            self.trak.mdia.mdhd.duration * 1_000_000 / self.trak.mdia.mdhd.timescale as u64,
-        )
+        let duration = self.trak.mdia.mdhd.duration as u128 * 1_000_000;
+        let duration = duration
+            .checked_div(self.trak.mdia.mdhd.timescale as u128)
+            .unwrap_or(0);
+        Duration::from_micros(u64::try_from(duration).unwrap_or(u64::MAX))
     }
 
     pub fn bitrate(&self) -> u32 {
-        if let Some(ref mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a {
+        if let Some(mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a() {
             if let Some(ref esds) = mp4a.esds {
                 esds.es_desc.dec_config.avg_bitrate
             } else {
@@ -220,31 +431,35 @@ impl Mp4Track {
             // mp4a.esds.es_desc.dec_config.avg_bitrate
         } else {
             let dur_sec = self.duration().as_secs();
-            if dur_sec > 0 {
-                let bitrate = self.total_sample_size() * 8 / dur_sec;
-                bitrate as u32
-            } else {
-                0
+            match self
+                .total_sample_size()
+                .saturating_mul(8)
+                .checked_div(dur_sec)
+            {
+                Some(bitrate) => bitrate as u32,
+                None => 0,
             }
         }
     }
 
+    /// Number of samples fully in the file, which is less than
+    /// `sample_count` for a truncated file. Reading the following samples
+    /// fails with `Error::TruncatedSample`.
+    pub fn available_sample_count(&self) -> u32 {
+        self.available_sample_count
+            .unwrap_or_else(|| self.sample_count())
+    }
+
     pub fn sample_count(&self) -> u32 {
         if !self.trafs.is_empty() {
-            let mut sample_count = 0u32;
-            for traf in self.trafs.iter() {
-                if let Some(ref trun) = traf.trun {
-                    sample_count += trun.sample_count;
-                }
-            }
-            sample_count
+            self.traf_sample_count
         } else {
             self.trak.mdia.minf.stbl.stsz.sample_count
         }
     }
 
     pub fn video_profile(&self) -> Result<AvcProfile> {
-        if let Some(ref avc1) = self.trak.mdia.minf.stbl.stsd.avc1 {
+        if let Some(avc1) = self.trak.mdia.minf.stbl.stsd.avc1() {
             AvcProfile::try_from((
                 avc1.avcc.avc_profile_indication,
                 avc1.avcc.profile_compatibility,
@@ -254,9 +469,10 @@ impl Mp4Track {
         }
     }
 
+    /// First SPS of an H.264 or H.265 track.
     pub fn sequence_parameter_set(&self) -> Result<&[u8]> {
-        if let Some(ref avc1) = self.trak.mdia.minf.stbl.stsd.avc1 {
-            match avc1.avcc.sequence_parameter_sets.get(0) {
+        if let Some(avc1) = self.trak.mdia.minf.stbl.stsd.avc1() {
+            match avc1.avcc.sequence_parameter_sets.first() {
                 Some(nal) => Ok(nal.bytes.as_ref()),
                 None => Err(Error::EntryInStblNotFound(
                     self.track_id(),
@@ -264,14 +480,17 @@ impl Mp4Track {
                     0,
                 )),
             }
+        } else if self.hvcc().is_some() {
+            self.hevc_parameter_set(SPS_NAL_UNIT_TYPE)
         } else {
             Err(Error::BoxInStblNotFound(self.track_id(), BoxType::Avc1Box))
         }
     }
 
+    /// First PPS of an H.264 or H.265 track.
     pub fn picture_parameter_set(&self) -> Result<&[u8]> {
-        if let Some(ref avc1) = self.trak.mdia.minf.stbl.stsd.avc1 {
-            match avc1.avcc.picture_parameter_sets.get(0) {
+        if let Some(avc1) = self.trak.mdia.minf.stbl.stsd.avc1() {
+            match avc1.avcc.picture_parameter_sets.first() {
                 Some(nal) => Ok(nal.bytes.as_ref()),
                 None => Err(Error::EntryInStblNotFound(
                     self.track_id(),
@@ -279,13 +498,104 @@ impl Mp4Track {
                     0,
                 )),
             }
+        } else if self.hvcc().is_some() {
+            self.hevc_parameter_set(PPS_NAL_UNIT_TYPE)
         } else {
             Err(Error::BoxInStblNotFound(self.track_id(), BoxType::Avc1Box))
         }
     }
 
+    /// First VPS of an H.265 track.
+    pub fn video_parameter_set(&self) -> Result<&[u8]> {
+        self.hevc_parameter_set(VPS_NAL_UNIT_TYPE)
+    }
+
+    fn hevc_parameter_set(&self, nal_unit_type: u8) -> Result<&[u8]> {
+        if let Some(hvcc) = self.hvcc() {
+            hvcc.nal_unit(nal_unit_type)
+                .ok_or(Error::EntryInStblNotFound(
+                    self.track_id(),
+                    BoxType::HvcCBox,
+                    0,
+                ))
+        } else {
+            Err(Error::BoxInStblNotFound(self.track_id(), BoxType::Hev1Box))
+        }
+    }
+
+    /// The hvcC box of the first hev1 or hvc1 sample entry.
+    fn hvcc(&self) -> Option<&HvcCBox> {
+        let stsd = &self.trak.mdia.minf.stbl.stsd;
+        stsd.entries.iter().find_map(|entry| match entry {
+            SampleEntry::Hev1(hev1) => Some(&hev1.hvcc),
+            SampleEntry::Hvc1(hvc1) => Some(&hvc1.hvcc),
+            _ => None,
+        })
+    }
+
+    /// Configuration of an AV1 track, as given by its av1C box.
+    pub fn av1_config(&self) -> Result<Av1Config> {
+        if let Some(av01) = self.trak.mdia.minf.stbl.stsd.av01() {
+            let av1c = &av01.av1c;
+            Ok(Av1Config {
+                width: av01.width,
+                height: av01.height,
+                seq_profile: av1c.seq_profile,
+                seq_level_idx_0: av1c.seq_level_idx_0,
+                seq_tier_0: av1c.seq_tier_0,
+                bit_depth: av1c.bit_depth(),
+                monochrome: av1c.monochrome,
+                chroma_subsampling_x: av1c.chroma_subsampling_x,
+                chroma_subsampling_y: av1c.chroma_subsampling_y,
+                chroma_sample_position: av1c.chroma_sample_position,
+                config_obus: av1c.config_obus.clone(),
+            })
+        } else {
+            Err(Error::BoxInStblNotFound(self.track_id(), BoxType::Av01Box))
+        }
+    }
+
+    /// Configuration of an Opus track, as given by its dOps box.
+    pub fn opus_config(&self) -> Result<OpusConfig> {
+        if let Some(opus) = self.trak.mdia.minf.stbl.stsd.opus() {
+            let dops = &opus.dops;
+            Ok(OpusConfig {
+                channel_count: dops.output_channel_count,
+                pre_skip: dops.pre_skip,
+                input_sample_rate: dops.input_sample_rate,
+                output_gain: dops.output_gain,
+                channel_mapping_family: dops.channel_mapping_family,
+                channel_mapping: dops.channel_mapping.clone(),
+            })
+        } else {
+            Err(Error::BoxInStblNotFound(self.track_id(), BoxType::OpusBox))
+        }
+    }
+
+    /// Pre-skip of an Opus track in the track timescale. The edit list of a
+    /// conforming track trims it, so that it is also its `trimmed_start`.
+    pub fn pre_skip(&self) -> Result<u64> {
+        if let Some(opus) = self.trak.mdia.minf.stbl.stsd.opus() {
+            Ok(pre_skip_time(opus.dops.pre_skip, self.timescale()))
+        } else {
+            Err(Error::BoxInStblNotFound(self.track_id(), BoxType::OpusBox))
+        }
+    }
+
+    /// Configuration of a FLAC track, as given by its dfLa box.
+    pub fn flac_config(&self) -> Result<FlacConfig> {
+        if let Some(flac) = self.trak.mdia.minf.stbl.stsd.flac() {
+            Ok(FlacConfig {
+                stream_info: flac.dfla.stream_info.clone(),
+                metadata_blocks: flac.dfla.metadata_blocks.clone(),
+            })
+        } else {
+            Err(Error::BoxInStblNotFound(self.track_id(), BoxType::FlacBox))
+        }
+    }
+
     pub fn audio_profile(&self) -> Result<AudioObjectType> {
-        if let Some(ref mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a {
+        if let Some(mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a() {
             if let Some(ref esds) = mp4a.esds {
                 AudioObjectType::try_from(esds.es_desc.dec_config.dec_specific.profile)
             } else {
@@ -317,7 +627,7 @@ impl Mp4Track {
             return Err(Error::InvalidData("must have either stco or co64 boxes"));
         }
         if let Some(ref stco) = self.trak.mdia.minf.stbl.stco {
-            if let Some(offset) = stco.entries.get(chunk_id as usize - 1) {
+            if let Some(offset) = stco.entries.get((chunk_id as usize).wrapping_sub(1)) {
                 return Ok(*offset as u64);
             } else {
                 return Err(Error::EntryInStblNotFound(
@@ -327,7 +637,7 @@ impl Mp4Track {
                 ));
             }
         } else if let Some(ref co64) = self.trak.mdia.minf.stbl.co64 {
-            if let Some(offset) = co64.entries.get(chunk_id as usize - 1) {
+            if let Some(offset) = co64.entries.get((chunk_id as usize).wrapping_sub(1)) {
                 return Ok(*offset);
             } else {
                 return Err(Error::EntryInStblNotFound(
@@ -340,14 +650,26 @@ impl Mp4Track {
         Err(Error::Box2NotFound(BoxType::StcoBox, BoxType::Co64Box))
     }
 
+    /// Error for a sample of stsz which the sample table `name` runs out of
+    /// entries before.
+    fn sample_count_mismatch(&self, name: BoxType, sample_id: u32) -> Error {
+        Error::Malformed(format!(
+            "trak[{}].stbl.{} has no entry for sample {} of the {} in stsz",
+            self.track_id(),
+            name,
+            sample_id,
+            self.sample_count()
+        ))
+    }
+
     fn ctts_index(&self, sample_id: u32) -> Result<(usize, u32)> {
         let ctts = self.trak.mdia.minf.stbl.ctts.as_ref().unwrap();
-        let mut sample_count = 1;
+        let mut sample_count = 1u64;
         for (i, entry) in ctts.entries.iter().enumerate() {
-            if sample_id < sample_count + entry.sample_count {
-                return Ok((i, sample_count));
+            if (sample_id as u64) < sample_count + entry.sample_count as u64 {
+                return Ok((i, sample_count as u32));
             }
-            sample_count += entry.sample_count;
+            sample_count += entry.sample_count as u64;
         }
 
         Err(Error::EntryInStblNotFound(
@@ -357,15 +679,15 @@ impl Mp4Track {
         ))
     }
 
-    /// return `(traf_idx, sample_idx_in_trun)`
-    fn find_traf_idx_and_sample_idx(&self, sample_id: u32) -> Option<(usize, usize)> {
-        let global_idx = sample_id - 1;
+    /// return `(traf_idx, trun_idx, sample_idx_in_trun)`
+    fn find_traf_idx_and_sample_idx(&self, sample_id: u32) -> Option<(usize, usize, usize)> {
+        let global_idx = sample_id.checked_sub(1)?;
         let mut offset = 0;
-        for traf_idx in 0..self.trafs.len() {
-            if let Some(trun) = &self.trafs[traf_idx].trun {
+        for (traf_idx, traf) in self.trafs.iter().enumerate() {
+            for (trun_idx, trun) in traf.truns.iter().enumerate() {
                 let sample_count = trun.sample_count;
                 if sample_count > (global_idx - offset) {
-                    return Some((traf_idx, (global_idx - offset) as _));
+                    return Some((traf_idx, trun_idx, (global_idx - offset) as _));
                 }
                 offset += sample_count;
             }
@@ -373,24 +695,104 @@ impl Mp4Track {
         None
     }
 
-    fn sample_size(&self, sample_id: u32) -> Result<u32> {
-        if !self.trafs.is_empty() {
-            if let Some((traf_idx, sample_idx)) = self.find_traf_idx_and_sample_idx(sample_id) {
-                if let Some(size) = self.trafs[traf_idx]
-                    .trun
-                    .as_ref()
-                    .unwrap()
-                    .sample_sizes
-                    .get(sample_idx)
-                {
-                    Ok(*size)
-                } else {
-                    Err(Error::EntryInTrunNotFound(
-                        self.track_id(),
-                        BoxType::TrunBox,
-                        sample_id,
-                    ))
+    /// Size of a sample in a trun, falling back to the tfhd and then the trex
+    /// defaults when the trun doesn't carry per-sample sizes.
+    fn trun_sample_size(&self, traf: &TrafBox, trun: &TrunBox, sample_idx: usize) -> Result<u32> {
+        if TrunBox::FLAG_SAMPLE_SIZE & trun.flags > 0 {
+            if let Some(size) = trun.sample_sizes.get(sample_idx) {
+                Ok(*size)
+            } else {
+                Err(Error::EntryInTrunNotFound(
+                    self.track_id(),
+                    BoxType::TrunBox,
+                    sample_idx as u32,
+                ))
+            }
+        } else if let Some(size) = traf.tfhd.default_sample_size {
+            Ok(size)
+        } else {
+            Ok(self.default_sample_size)
+        }
+    }
+
+    /// Duration of a sample in a trun, falling back to the tfhd and then the
+    /// trex defaults when the trun doesn't carry per-sample durations.
+    fn trun_sample_duration(
+        &self,
+        traf: &TrafBox,
+        trun: &TrunBox,
+        sample_idx: usize,
+    ) -> Result<u32> {
+        if TrunBox::FLAG_SAMPLE_DURATION & trun.flags > 0 {
+            if let Some(duration) = trun.sample_durations.get(sample_idx) {
+                Ok(*duration)
+            } else {
+                Err(Error::EntryInTrunNotFound(
+                    self.track_id(),
+                    BoxType::TrunBox,
+                    sample_idx as u32,
+                ))
+            }
+        } else if let Some(duration) = traf.tfhd.default_sample_duration {
+            Ok(duration)
+        } else {
+            Ok(self.default_sample_duration)
+        }
+    }
+
+    /// Composition time offset of a sample in a trun. Version 0 truns store
+    /// unsigned offsets, which are clamped to `i32::MAX`, version 1 truns
+    /// signed ones.
+    fn trun_sample_cts(&self, trun: &TrunBox, sample_idx: usize) -> i32 {
+        if TrunBox::FLAG_SAMPLE_CTS & trun.flags > 0 {
+            if let Some(&cts) = trun.sample_cts.get(sample_idx) {
+                if trun.version == 0 {
+                    return cts.min(i32::MAX as u32) as i32;
                 }
+                return cts as i32;
+            }
+        }
+        0
+    }
+
+    fn traf_duration(&self, traf: &TrafBox) -> Result<u64> {
+        let mut duration = 0u64;
+        for trun in traf.truns.iter() {
+            for sample_idx in 0..trun.sample_count as usize {
+                let sample_duration = self.trun_sample_duration(traf, trun, sample_idx)?;
+                duration = duration.saturating_add(sample_duration as u64);
+            }
+        }
+        Ok(duration)
+    }
+
+    /// Absolute offset of the first sample of a trun. Without an explicit
+    /// data_offset the trun's data directly follows that of the previous trun.
+    fn trun_data_offset(
+        &self,
+        base_data_offset: u64,
+        prev_end: u64,
+        trun: &TrunBox,
+    ) -> Result<u64> {
+        if let Some(data_offset) = trun.data_offset {
+            base_data_offset
+                .checked_add_signed(data_offset as i64)
+                .ok_or(Error::InvalidData("trun data offset out of range"))
+        } else {
+            Ok(prev_end)
+        }
+    }
+
+    pub(crate) fn sample_size(&self, sample_id: u32) -> Result<u32> {
+        if let Some(sample) = self.indexed_sample(sample_id) {
+            return Ok(sample.size);
+        }
+        if !self.trafs.is_empty() {
+            if let Some((traf_idx, trun_idx, sample_idx)) =
+                self.find_traf_idx_and_sample_idx(sample_id)
+            {
+                let traf = &self.trafs[traf_idx];
+                self.trun_sample_size(traf, &traf.truns[trun_idx], sample_idx)
             } else {
                 Err(Error::BoxInTrafNotFound(self.track_id(), BoxType::TrafBox))
             }
@@ -399,8 +801,15 @@ impl Mp4Track {
             if stsz.sample_size > 0 {
                 return Ok(stsz.sample_size);
             }
-            if let Some(size) = stsz.sample_sizes.get(sample_id as usize - 1) {
+            if let Some(size) = stsz.sample_sizes.get((sample_id as usize).wrapping_sub(1)) {
                 Ok(*size)
+            } else if sample_id > 0 && sample_id <= stsz.sample_count {
+                Err(Error::Malformed(format!(
+                    "trak[{}].stbl.stsz has {} sample sizes for {} samples",
+                    self.track_id(),
+                    stsz.sample_sizes.len(),
+                    stsz.sample_count
+                )))
             } else {
                 Err(Error::EntryInStblNotFound(
                     self.track_id(),
@@ -416,18 +825,40 @@ impl Mp4Track {
         if stsz.sample_size > 0 {
             stsz.sample_size as u64 * self.sample_count() as u64
         } else {
-            let mut total_size = 0;
+            let mut total_size = 0u64;
             for size in stsz.sample_sizes.iter() {
-                total_size += *size as u64;
+                total_size = total_size.saturating_add(*size as u64);
             }
             total_size
         }
     }
 
-    fn sample_offset(&self, sample_id: u32) -> Result<u64> {
+    pub(crate) fn sample_offset(&self, sample_id: u32) -> Result<u64> {
+        if let Some(sample) = self.indexed_sample(sample_id) {
+            return Ok(sample.offset);
+        }
         if !self.trafs.is_empty() {
-            if let Some((traf_idx, _sample_idx)) = self.find_traf_idx_and_sample_idx(sample_id) {
-                Ok(self.trafs[traf_idx].tfhd.base_data_offset.unwrap_or(0))
+            if let Some((traf_idx, trun_idx, sample_idx)) =
+                self.find_traf_idx_and_sample_idx(sample_id)
+            {
+                let traf = &self.trafs[traf_idx];
+                let base_data_offset = self.base_data_offsets[traf_idx];
+
+                let mut data_end = base_data_offset;
+                for trun in traf.truns.iter().take(trun_idx) {
+                    data_end = self.trun_data_offset(base_data_offset, data_end, trun)?;
+                    for i in 0..trun.sample_count as usize {
+                        data_end = add_offset(data_end, self.trun_sample_size(traf, trun, i)?)?;
+                    }
+                }
+
+                let trun = &traf.truns[trun_idx];
+                let mut sample_offset = self.trun_data_offset(base_data_offset, data_end, trun)?;
+                for i in 0..sample_idx {
+                    sample_offset =
+                        add_offset(sample_offset, self.trun_sample_size(traf, trun, i)?)?;
+                }
+                Ok(sample_offset)
             } else {
                 Err(Error::BoxInTrafNotFound(self.track_id(), BoxType::TrafBox))
             }
@@ -441,42 +872,71 @@ impl Mp4Track {
             let first_sample = stsc_entry.first_sample;
             let samples_per_chunk = stsc_entry.samples_per_chunk;
 
-            let chunk_id = first_chunk + (sample_id - first_sample) / samples_per_chunk;
+            if samples_per_chunk == 0 {
+                return Err(Error::InvalidData("stsc entry without samples"));
+            }
+            let chunk_id =
+                first_chunk.saturating_add((sample_id - first_sample) / samples_per_chunk);
 
-            let chunk_offset = self.chunk_offset(chunk_id)?;
+            let mut sample_offset = self.chunk_offset(chunk_id)?;
 
             let first_sample_in_chunk = sample_id - (sample_id - first_sample) % samples_per_chunk;
 
-            let mut sample_offset = 0;
             for i in first_sample_in_chunk..sample_id {
-                sample_offset += self.sample_size(i)?;
+                sample_offset = add_offset(sample_offset, self.sample_size(i)?)?;
             }
 
-            Ok(chunk_offset + sample_offset as u64)
+            Ok(sample_offset)
         }
     }
 
-    fn sample_time(&self, sample_id: u32) -> Result<(u64, u32)> {
+    pub(crate) fn sample_time(&self, sample_id: u32) -> Result<(u64, u32)> {
+        if let Some(sample) = self.indexed_sample(sample_id) {
+            return Ok((sample.time, sample.duration));
+        }
         let stts = &self.trak.mdia.minf.stbl.stts;
 
-        let mut sample_count = 1;
-        let mut elapsed = 0;
+        let mut sample_count = 1u64;
+        let mut elapsed = 0u64;
 
         if !self.trafs.is_empty() {
-            let start_time = ((sample_id - 1) * self.default_sample_duration) as u64;
-            Ok((start_time, self.default_sample_duration))
+            if let Some((traf_idx, trun_idx, sample_idx)) =
+                self.find_traf_idx_and_sample_idx(sample_id)
+            {
+                let traf = &self.trafs[traf_idx];
+                let mut start_time = self.base_decode_times[traf_idx];
+                for trun in traf.truns.iter().take(trun_idx) {
+                    for i in 0..trun.sample_count as usize {
+                        let duration = self.trun_sample_duration(traf, trun, i)?;
+                        start_time = start_time.saturating_add(duration as u64);
+                    }
+                }
+
+                let trun = &traf.truns[trun_idx];
+                for i in 0..sample_idx {
+                    let duration = self.trun_sample_duration(traf, trun, i)?;
+                    start_time = start_time.saturating_add(duration as u64);
+                }
+                let duration = self.trun_sample_duration(traf, trun, sample_idx)?;
+                Ok((start_time, duration))
+            } else {
+                Err(Error::BoxInTrafNotFound(self.track_id(), BoxType::TrafBox))
+            }
         } else {
             for entry in stts.entries.iter() {
-                if sample_id < sample_count + entry.sample_count {
-                    let start_time =
-                        (sample_id - sample_count) as u64 * entry.sample_delta as u64 + elapsed;
-                    return Ok((start_time, entry.sample_delta));
+                if (sample_id as u64) < sample_count + entry.sample_count as u64 {
+                    let start_time = (sample_id as u64 - sample_count) * entry.sample_delta as u64;
+                    return Ok((start_time.saturating_add(elapsed), entry.sample_delta));
                 }
 
-                sample_count += entry.sample_count;
-                elapsed += entry.sample_count as u64 * entry.sample_delta as u64;
+                sample_count += entry.sample_count as u64;
+                let entry_duration = entry.sample_count as u64 * entry.sample_delta as u64;
+                elapsed = elapsed.saturating_add(entry_duration);
             }
 
+            if sample_id > 0 && sample_id <= self.sample_count() {
+                return Err(self.sample_count_mismatch(BoxType::SttsBox, sample_id));
+            }
             Err(Error::EntryInStblNotFound(
                 self.track_id(),
                 BoxType::SttsBox,
@@ -486,6 +946,18 @@ impl Mp4Track {
     }
 
     fn sample_rendering_offset(&self, sample_id: u32) -> i32 {
+        if let Some(sample) = self.indexed_sample(sample_id) {
+            return sample.rendering_offset;
+        }
+        if !self.trafs.is_empty() {
+            if let Some((traf_idx, trun_idx, sample_idx)) =
+                self.find_traf_idx_and_sample_idx(sample_id)
+            {
+                return self.trun_sample_cts(&self.trafs[traf_idx].truns[trun_idx], sample_idx);
+            }
+            return 0;
+        }
+
         if let Some(ref ctts) = self.trak.mdia.minf.stbl.ctts {
             if let Ok((ctts_index, _)) = self.ctts_index(sample_id) {
                 let ctts_entry = ctts.entries.get(ctts_index).unwrap();
@@ -495,10 +967,202 @@ impl Mp4Track {
         0
     }
 
+    /// Flags of a fragmented sample: the trun's first_sample_flags for its
+    /// first sample, then per-sample trun flags, then the tfhd and trex defaults.
+    fn sample_flags(&self, sample_id: u32) -> Option<SampleFlags> {
+        let (traf_idx, trun_idx, sample_idx) = match self.indexed_sample(sample_id) {
+            Some(sample) => match sample.source {
+                RunSource::Trun(traf_idx, trun_idx, sample_idx) => (traf_idx, trun_idx, sample_idx),
+                RunSource::Stsc(_) => return None,
+            },
+            None => self.find_traf_idx_and_sample_idx(sample_id)?,
+        };
+        let traf = &self.trafs[traf_idx];
+        let flags = self.trun_sample_flags(traf, &traf.truns[trun_idx], sample_idx)?;
+        Some(SampleFlags::from(flags))
+    }
+
+    fn trun_sample_flags(&self, traf: &TrafBox, trun: &TrunBox, sample_idx: usize) -> Option<u32> {
+        match trun.first_sample_flags {
+            Some(flags) if sample_idx == 0 => Some(flags),
+            _ if TrunBox::FLAG_SAMPLE_FLAGS & trun.flags > 0 => {
+                trun.sample_flags.get(sample_idx).copied()
+            }
+            _ => Some(
+                traf.tfhd
+                    .default_sample_flags
+                    .unwrap_or(self.default_sample_flags),
+            ),
+        }
+    }
+
+    /// Sample description index of a sample, the 1-based index in the stsd
+    /// box of the sample entry describing it, which may change along the track.
+    pub fn sample_description_index(&self, sample_id: u32) -> Result<u32> {
+        if let Some(sample) = self.indexed_sample(sample_id) {
+            return Ok(match sample.source {
+                RunSource::Stsc(stsc_index) => {
+                    self.trak.mdia.minf.stbl.stsc.entries[stsc_index].sample_description_index
+                }
+                RunSource::Trun(traf_idx, _, _) => {
+                    self.traf_sample_description_index(&self.trafs[traf_idx])
+                }
+            });
+        }
+        if !self.trafs.is_empty() {
+            return match self.find_traf_idx_and_sample_idx(sample_id) {
+                Some((traf_idx, _, _)) => {
+                    Ok(self.traf_sample_description_index(&self.trafs[traf_idx]))
+                }
+                None => Err(Error::BoxInTrafNotFound(self.track_id(), BoxType::TrafBox)),
+            };
+        }
+        if sample_id > self.sample_count() {
+            return Err(Error::EntryInStblNotFound(
+                self.track_id(),
+                BoxType::StscBox,
+                sample_id,
+            ));
+        }
+        let stsc_index = self.stsc_index(sample_id)?;
+        Ok(self.trak.mdia.minf.stbl.stsc.entries[stsc_index].sample_description_index)
+    }
+
+    /// Sample entry describing a sample, or `None` if its sample description
+    /// index is out of the entries of the stsd box.
+    pub fn sample_entry(&self, sample_id: u32) -> Result<Option<&SampleEntry>> {
+        let index = self.sample_description_index(sample_id)?;
+        Ok(self.trak.mdia.minf.stbl.stsd.entry(index))
+    }
+
+    fn traf_sample_description_index(&self, traf: &TrafBox) -> u32 {
+        traf.tfhd
+            .sample_description_index
+            .unwrap_or(self.default_sample_description_index)
+    }
+
+    /// Precompute the offset, size and timing of every sample, so that
+    /// looking them up no longer scans the sample tables or the fragments.
+    /// Their flags and sample description index are found from the chunk or
+    /// trun they belong to.
+    ///
+    /// The index is dropped when fragments are added to the track.
+    pub fn build_sample_index(&mut self) -> Result<()> {
+        let index = if self.trafs.is_empty() {
+            self.stbl_sample_index()?
+        } else {
+            self.traf_sample_index()?
+        };
+        self.sample_index = Some(index);
+        Ok(())
+    }
+
+    pub fn has_sample_index(&self) -> bool {
+        self.sample_index.is_some()
+    }
+
+    fn indexed_sample(&self, sample_id: u32) -> Option<IndexedSample> {
+        self.sample_index.as_ref()?.get(sample_id)
+    }
+
+    fn stbl_sample_index(&self) -> Result<SampleIndex> {
+        let stbl = &self.trak.mdia.minf.stbl;
+        let sample_count = self.sample_count();
+        let mut index = SampleIndex::with_capacity(sample_count as usize);
+
+        let mut stsc_index = 0;
+        let mut stts_entries = stbl.stts.entries.iter().flat_map(|entry| {
+            std::iter::repeat(entry.sample_delta).take(entry.sample_count as usize)
+        });
+        let mut ctts_entries = stbl.ctts.iter().flat_map(|ctts| {
+            ctts.entries.iter().flat_map(|entry| {
+                std::iter::repeat(entry.sample_offset).take(entry.sample_count as usize)
+            })
+        });
+        let mut chunk_id = 0;
+        let mut offset = 0;
+        let mut time = 0u64;
+        for sample_id in 1..=sample_count {
+            let size = self.sample_size(sample_id)?;
+
+            // Samples are contiguous within their chunk.
+            while stsc_index + 1 < stbl.stsc.entries.len()
+                && sample_id >= stbl.stsc.entries[stsc_index + 1].first_sample
+            {
+                stsc_index += 1;
+            }
+            let stsc_entry = match stbl.stsc.entries.get(stsc_index) {
+                Some(entry) if sample_id >= entry.first_sample => entry,
+                Some(_) => return Err(Error::InvalidData("sample not found")),
+                None => return Err(Error::InvalidData("no stsc entries")),
+            };
+            let sample_chunk_id = (sample_id - stsc_entry.first_sample)
+                .checked_div(stsc_entry.samples_per_chunk)
+                .ok_or(Error::InvalidData("stsc entry without samples"))?
+                .saturating_add(stsc_entry.first_chunk);
+            let is_new_chunk = sample_chunk_id != chunk_id;
+            if is_new_chunk {
+                chunk_id = sample_chunk_id;
+                offset = self.chunk_offset(chunk_id)?;
+            }
+
+            let duration = stts_entries
+                .next()
+                .ok_or_else(|| self.sample_count_mismatch(BoxType::SttsBox, sample_id))?;
+            index.push(
+                is_new_chunk,
+                IndexedSample {
+                    offset,
+                    size,
+                    time,
+                    duration,
+                    rendering_offset: ctts_entries.next().unwrap_or(0),
+                    source: RunSource::Stsc(stsc_index),
+                },
+            );
+            offset = add_offset(offset, size)?;
+            time = time.saturating_add(duration as u64);
+        }
+        Ok(index)
+    }
+
+    fn traf_sample_index(&self) -> Result<SampleIndex> {
+        let mut index = SampleIndex::with_capacity(self.sample_count() as usize);
+        for (traf_idx, traf) in self.trafs.iter().enumerate() {
+            let base_data_offset = self.base_data_offsets[traf_idx];
+            let mut time = self.base_decode_times[traf_idx];
+            let mut data_end = base_data_offset;
+            for (trun_idx, trun) in traf.truns.iter().enumerate() {
+                let mut offset = self.trun_data_offset(base_data_offset, data_end, trun)?;
+                for sample_idx in 0..trun.sample_count as usize {
+                    let size = self.trun_sample_size(traf, trun, sample_idx)?;
+                    let duration = self.trun_sample_duration(traf, trun, sample_idx)?;
+                    index.push(
+                        sample_idx == 0,
+                        IndexedSample {
+                            offset,
+                            size,
+                            time,
+                            duration,
+                            rendering_offset: self.trun_sample_cts(trun, sample_idx),
+                            source: RunSource::Trun(traf_idx, trun_idx, sample_idx),
+                        },
+                    );
+                    offset = add_offset(offset, size)?;
+                    time = time.saturating_add(duration as u64);
+                }
+                data_end = offset;
+            }
+        }
+        Ok(index)
+    }
+
     fn is_sync_sample(&self, sample_id: u32) -> bool {
         if !self.trafs.is_empty() {
-            let sample_sizes_count = self.sample_count() / self.trafs.len() as u32;
-            return sample_id == 1 || sample_id % sample_sizes_count == 0;
+            return match self.sample_flags(sample_id) {
+                Some(flags) => flags.is_sync(),
+                None => false,
+            };
         }
 
         if let Some(ref stss) = self.trak.mdia.minf.stbl.stss {
@@ -508,36 +1172,604 @@ impl Mp4Track {
         }
     }
 
-    pub(crate) fn read_sample<R: Read + Seek>(
+    /// Find the sample at a time in the track timescale, the last sample
+    /// starting at or before it, then move to a sync sample depending on `mode`.
+    ///
+    /// Times before the first sample give the first sample. Returns `None` for
+    /// times at or after the end of the track, or if there is no sync sample in
+    /// the requested direction.
+    pub fn sample_at_time(
         &self,
-        reader: &mut R,
-        sample_id: u32,
-    ) -> Result<Option<Mp4Sample>> {
+        time: u64,
+        timeline: Timeline,
+        mode: SeekMode,
+    ) -> Result<Option<u32>> {
+        let time = i64::try_from(time).map_err(|_| Error::InvalidData("seek time out of range"))?;
+        let sample_id = match timeline {
+            Timeline::Decode => self.sample_at_decode_time(time)?,
+            Timeline::Presentation => self.sample_at_presentation_time(time)?,
+        };
+        match (sample_id, mode) {
+            (None, _) | (Some(_), SeekMode::Exact) => Ok(sample_id),
+            (Some(sample_id), SeekMode::PreviousSync) => Ok(self.previous_sync_sample(sample_id)),
+            (Some(sample_id), SeekMode::NextSync) => Ok(self.next_sync_sample(sample_id)),
+        }
+    }
+
+    /// Same as `sample_at_time`, with a time from the start of the track.
+    pub fn sample_at_duration(
+        &self,
+        time: Duration,
+        timeline: Timeline,
+        mode: SeekMode,
+    ) -> Result<Option<u32>> {
+        let time = time.as_nanos() * self.timescale() as u128 / 1_000_000_000;
+        let time = u64::try_from(time).map_err(|_| Error::InvalidData("seek time out of range"))?;
+        self.sample_at_time(time, timeline, mode)
+    }
+
+    /// Last sample decoded at or before a time, by binary search as decode
+    /// times increase with sample ids.
+    fn last_sample_decoded_at(&self, time: i64) -> Result<Option<u32>> {
+        let (mut low, mut high) = (1, self.sample_count() + 1);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if self.sample_time(mid)?.0 as i64 <= time {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low.checked_sub(1).filter(|&sample_id| sample_id > 0))
+    }
+
+    fn sample_at_decode_time(&self, time: i64) -> Result<Option<u32>> {
+        let sample_count = self.sample_count();
+        match self.last_sample_decoded_at(time)? {
+            Some(sample_id) if sample_id == sample_count => {
+                let (start_time, duration) = self.sample_time(sample_id)?;
+                if time >= (start_time as i64).saturating_add(duration as i64) {
+                    Ok(None)
+                } else {
+                    Ok(Some(sample_id))
+                }
+            }
+            Some(sample_id) => Ok(Some(sample_id)),
+            None if sample_count > 0 => Ok(Some(1)),
+            None => Ok(None),
+        }
+    }
+
+    /// Presentation times aren't ordered, but a sample presented at a time is
+    /// decoded at most the largest composition offset earlier, which bounds
+    /// the samples to look at around the decode time.
+    fn sample_at_presentation_time(&self, time: i64) -> Result<Option<u32>> {
+        let sample_count = self.sample_count();
+        let (min_offset, max_offset) = self.rendering_offset_range();
+
+        // Samples presented at or before the time are decoded at or before
+        // `time - min_offset`, look for the last presented one from there.
+        let last = match self.last_sample_decoded_at(time.saturating_sub(min_offset as i64))? {
+            Some(sample_id) => sample_id,
+            None if sample_count > 0 => return Ok(Some(1)),
+            None => return Ok(None),
+        };
+        let mut found: Option<(i64, u32)> = None;
+        if self.sample_index.is_none() {
+            // Without index each lookup scans the tables, go through them once.
+            self.for_each_sample_time(|sample_id, start_time, _, offset| {
+                let time_presented = (start_time as i64).saturating_add(offset as i64);
+                if time_presented <= time
+                    && found.map_or(true, |(found_time, _)| time_presented >= found_time)
+                {
+                    found = Some((time_presented, sample_id));
+                }
+                sample_id < last
+            })?;
+        } else {
+            for sample_id in (1..=last).rev() {
+                let start_time = self.sample_time(sample_id)?.0 as i64;
+                if let Some((found_time, _)) = found {
+                    if start_time.saturating_add(max_offset as i64) < found_time {
+                        break;
+                    }
+                }
+                let time_presented =
+                    start_time.saturating_add(self.sample_rendering_offset(sample_id) as i64);
+                if time_presented <= time
+                    && found.map_or(true, |(found_time, _)| time_presented > found_time)
+                {
+                    found = Some((time_presented, sample_id));
+                }
+            }
+        }
+        let (found_time, sample_id) = match found {
+            Some(found) => found,
+            None => return Ok(Some(1)),
+        };
+
+        // Past the end of the found sample, check for the end of the track.
+        let duration = self.sample_time(sample_id)?.1 as i64;
+        if time >= found_time.saturating_add(duration)
+            && time >= self.presentation_end(max_offset)?
+        {
+            return Ok(None);
+        }
+        Ok(Some(sample_id))
+    }
+
+    /// End of the last presented sample.
+    fn presentation_end(&self, max_offset: i32) -> Result<i64> {
+        let mut end = i64::MIN;
+        if self.sample_index.is_none() {
+            self.for_each_sample_time(|_, start_time, duration, offset| {
+                let sample_end = (start_time as i64)
+                    .saturating_add(offset as i64)
+                    .saturating_add(duration as i64);
+                end = end.max(sample_end);
+                true
+            })?;
+            return Ok(end);
+        }
+        for sample_id in (1..=self.sample_count()).rev() {
+            let (start_time, duration) = self.sample_time(sample_id)?;
+            // Earlier samples end at the latest when this one starts.
+            if (start_time as i64).saturating_add(max_offset as i64) < end {
+                break;
+            }
+            let sample_end = (start_time as i64)
+                .saturating_add(self.sample_rendering_offset(sample_id) as i64)
+                .saturating_add(duration as i64);
+            end = end.max(sample_end);
+        }
+        Ok(end)
+    }
+
+    /// Call `f` with the id, decode time, duration and composition offset of
+    /// the samples in order, until it returns false, going through the sample
+    /// tables or the fragments once rather than looking up each sample.
+    fn for_each_sample_time<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(u32, u64, u32, i32) -> bool,
+    {
+        let mut sample_id = 1u32;
+        if !self.trafs.is_empty() {
+            for (traf_idx, traf) in self.trafs.iter().enumerate() {
+                let mut time = self.base_decode_times[traf_idx];
+                for trun in traf.truns.iter() {
+                    for sample_idx in 0..trun.sample_count as usize {
+                        let duration = self.trun_sample_duration(traf, trun, sample_idx)?;
+                        let offset = self.trun_sample_cts(trun, sample_idx);
+                        if !f(sample_id, time, duration, offset) {
+                            return Ok(());
+                        }
+                        sample_id = sample_id.saturating_add(1);
+                        time = time.saturating_add(duration as u64);
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let stbl = &self.trak.mdia.minf.stbl;
+        let sample_count = self.sample_count();
+        let mut ctts_entries = stbl.ctts.iter().flat_map(|ctts| {
+            ctts.entries.iter().flat_map(|entry| {
+                std::iter::repeat(entry.sample_offset).take(entry.sample_count as usize)
+            })
+        });
+        let mut time = 0u64;
+        for entry in stbl.stts.entries.iter() {
+            for _ in 0..entry.sample_count {
+                if sample_id > sample_count {
+                    return Ok(());
+                }
+                let offset = ctts_entries.next().unwrap_or(0);
+                if !f(sample_id, time, entry.sample_delta, offset) || sample_id == u32::MAX {
+                    return Ok(());
+                }
+                sample_id += 1;
+                time = time.saturating_add(entry.sample_delta as u64);
+            }
+        }
+        if sample_id <= sample_count {
+            return Err(self.sample_count_mismatch(BoxType::SttsBox, sample_id));
+        }
+        Ok(())
+    }
+
+    /// Smallest and largest composition offsets of the samples, including 0.
+    fn rendering_offset_range(&self) -> (i32, i32) {
+        let mut range = (0, 0);
+        let mut add = |offset: i32| range = (range.0.min(offset), range.1.max(offset));
+        if !self.trafs.is_empty() {
+            for trun in self.trafs.iter().flat_map(|traf| traf.truns.iter()) {
+                for sample_idx in 0..trun.sample_cts.len() {
+                    add(self.trun_sample_cts(trun, sample_idx));
+                }
+            }
+        } else if let Some(ref ctts) = self.trak.mdia.minf.stbl.ctts {
+            for entry in ctts.entries.iter() {
+                add(entry.sample_offset);
+            }
+        }
+        range
+    }
+
+    fn previous_sync_sample(&self, sample_id: u32) -> Option<u32> {
+        if self.trafs.is_empty() {
+            return match self.trak.mdia.minf.stbl.stss {
+                Some(ref stss) => {
+                    let idx = stss.entries.partition_point(|&id| id <= sample_id);
+                    idx.checked_sub(1).map(|idx| stss.entries[idx])
+                }
+                None => Some(sample_id),
+            };
+        }
+        self.traf_sync_samples()
+            .take_while(|&id| id <= sample_id)
+            .last()
+    }
+
+    fn next_sync_sample(&self, sample_id: u32) -> Option<u32> {
+        if self.trafs.is_empty() {
+            return match self.trak.mdia.minf.stbl.stss {
+                Some(ref stss) => {
+                    let idx = stss.entries.partition_point(|&id| id < sample_id);
+                    stss.entries
+                        .get(idx)
+                        .copied()
+                        .filter(|&id| id <= self.sample_count())
+                }
+                None => Some(sample_id),
+            };
+        }
+        self.traf_sync_samples().find(|&id| id >= sample_id)
+    }
+
+    /// Ids of the sync samples of the trafs, in one pass over their truns.
+    fn traf_sync_samples(&self) -> impl Iterator<Item = u32> + '_ {
+        self.trafs
+            .iter()
+            .flat_map(|traf| traf.truns.iter().map(move |trun| (traf, trun)))
+            .flat_map(move |(traf, trun)| {
+                (0..trun.sample_count as usize).map(move |sample_idx| {
+                    self.trun_sample_flags(traf, trun, sample_idx)
+                        .is_some_and(|flags| SampleFlags::from(flags).is_sync())
+                })
+            })
+            .zip(1..)
+            .filter_map(|(is_sync, sample_id)| is_sync.then_some(sample_id))
+    }
+
+    /// Edits of the edit list of the track, empty without edit list, in which
+    /// case the media is presented as is.
+    pub fn edits(&self) -> &[TrackEdit] {
+        &self.edits
+    }
+
+    /// Presentation time of a sample after applying the edit list, in the
+    /// track timescale, or `None` if the edit list doesn't present the sample.
+    ///
+    /// A sample starting before an edit and ending within it is partly
+    /// presented, from the start of the edit. Dwell edits, with a media rate
+    /// of 0, don't present any sample.
+    pub fn sample_presentation_time(&self, sample_id: u32) -> Result<Option<i64>> {
+        let (start_time, duration) = self.sample_time(sample_id)?;
+        let time =
+            (start_time as i64).saturating_add(self.sample_rendering_offset(sample_id) as i64);
+        let time_end = time.saturating_add(duration as i64);
+
+        if self.edits.is_empty() {
+            return Ok(Some(time));
+        }
+        for edit in self.edits.iter().filter(|edit| edit.media_rate != 0) {
+            if let Some(media_time) = edit.media_time {
+                let end = edit
+                    .duration
+                    .map(|duration| media_time.saturating_add(duration as i64));
+                let start = time.max(media_time);
+                if (time >= media_time || time_end > media_time)
+                    && end.map_or(true, |end| start < end)
+                {
+                    let presentation_time = edit.presentation_time as i64;
+                    return Ok(Some(presentation_time.saturating_add(start - media_time)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Media time presented at a presentation time, the inverse of
+    /// `sample_presentation_time`, or `None` within empty edits or after the
+    /// last edit.
+    pub fn media_time(&self, presentation_time: u64) -> Option<i64> {
+        if self.edits.is_empty() {
+            return Some(presentation_time as i64);
+        }
+        let edit = self.edits.iter().find(|edit| {
+            presentation_time >= edit.presentation_time
+                && edit.duration.map_or(true, |duration| {
+                    presentation_time < edit.presentation_time.saturating_add(duration)
+                })
+        })?;
+        let media_time = edit.media_time?;
+        if edit.media_rate == 0 {
+            return Some(media_time);
+        }
+        Some(media_time.saturating_add((presentation_time - edit.presentation_time) as i64))
+    }
+
+    /// Time before the media of the track is presented, from its leading empty
+    /// edits, in the track timescale.
+    pub fn presentation_delay(&self) -> u64 {
+        self.edits
+            .iter()
+            .find(|edit| edit.media_time.is_some())
+            .map_or(0, |edit| edit.presentation_time)
+    }
+
+    /// Media time at which the presentation of the track starts, trimming the
+    /// media before it: the media time of the first non-empty edit, or 0.
+    pub fn trimmed_start(&self) -> i64 {
+        self.edits
+            .iter()
+            .find_map(|edit| edit.media_time)
+            .unwrap_or(0)
+    }
+
+    /// Media time at which the presentation of the track ends, trimming the
+    /// media after it: the end of the last non-empty edit, or the end of the
+    /// media.
+    pub fn trimmed_end(&self) -> Result<i64> {
+        let last_edit = self
+            .edits
+            .iter()
+            .rev()
+            .find(|edit| edit.media_time.is_some());
+        match last_edit {
+            Some(&TrackEdit {
+                media_time: Some(media_time),
+                duration: Some(duration),
+                ..
+            }) => Ok(media_time.saturating_add(duration as i64)),
+            _ if self.sample_count() == 0 => Ok(0),
+            _ => self.presentation_end(self.rendering_offset_range().1),
+        }
+    }
+
+    /// Offset and size of a sample, or `None` if there is no such sample.
+    pub(crate) fn sample_location(&self, sample_id: u32) -> Result<Option<(u64, u32)>> {
         let sample_offset = match self.sample_offset(sample_id) {
             Ok(offset) => offset,
             Err(Error::EntryInStblNotFound(_, _, _)) => return Ok(None),
             Err(err) => return Err(err),
         };
-        let sample_size = self.sample_size(sample_id).unwrap();
+        let sample_size = self.sample_size(sample_id)?;
+        Ok(Some((sample_offset, sample_size)))
+    }
+
+    /// Check the size of a sample to read against the limits.
+    pub(crate) fn check_sample_size(&self, size: u32) -> Result<()> {
+        let max_sample_size = self.limits.max_sample_size;
+        if size > max_sample_size {
+            return Err(Error::LimitExceeded(
+                "sample size",
+                size as u64,
+                max_sample_size as u64,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Offset and size of a sample to read, failing if the sample extends past
+    /// the end of the file or is over the sample size limit.
+    pub(crate) fn checked_sample_location(&self, sample_id: u32) -> Result<Option<(u64, u32)>> {
+        let location = self.sample_location(sample_id)?;
+        if let Some((offset, size)) = location {
+            self.check_sample_size(size)?;
+            if let Some(data_size) = self.data_size {
+                if offset.saturating_add(size as u64) > data_size {
+                    return Err(Error::TruncatedSample(self.track_id(), sample_id));
+                }
+            }
+        }
+        Ok(location)
+    }
+
+    pub(crate) fn read_sample<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        sample_id: u32,
+    ) -> Result<Option<Mp4Sample>> {
+        let (sample_offset, sample_size) = match self.checked_sample_location(sample_id)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
 
         let mut buffer = vec![0x0u8; sample_size as usize];
         reader.seek(SeekFrom::Start(sample_offset))?;
         reader.read_exact(&mut buffer)?;
 
-        let (start_time, duration) = self.sample_time(sample_id).unwrap(); // XXX
+        self.sample_with_bytes(sample_id, Bytes::from(buffer))
+            .map(Some)
+    }
+
+    /// Build a sample from its already read bytes.
+    pub(crate) fn sample_with_bytes(&self, sample_id: u32, bytes: Bytes) -> Result<Mp4Sample> {
+        let (start_time, duration) = self.sample_time(sample_id)?;
         let rendering_offset = self.sample_rendering_offset(sample_id);
         let is_sync = self.is_sync_sample(sample_id);
+        let flags = self.sample_flags(sample_id);
+        let sample_description_index = self.sample_description_index(sample_id)?;
 
-        Ok(Some(Mp4Sample {
+        Ok(Mp4Sample {
             start_time,
             duration,
             rendering_offset,
             is_sync,
-            bytes: Bytes::from(buffer),
-        }))
+            flags,
+            sample_description_index,
+            bytes,
+        })
     }
 }
 
+/// Opus pre-skip, in samples at 48 kHz, in a timescale, rounded up so that
+/// no skipped sample is presented.
+fn pre_skip_time(pre_skip: u16, timescale: u32) -> u64 {
+    (pre_skip as u64 * timescale as u64 + 47999) / 48000
+}
+
+/// Edits of the edit list of a track, with the segment durations converted
+/// from the movie timescale to the track timescale.
+fn track_edits(trak: &TrakBox, movie_timescale: u32) -> Vec<TrackEdit> {
+    let elst = match trak.edts.as_ref().and_then(|edts| edts.elst.as_ref()) {
+        Some(elst) => elst,
+        None => return Vec::new(),
+    };
+
+    let mut presentation_time = 0u64;
+    let mut edits = Vec::with_capacity(elst.entries.len());
+    for (i, entry) in elst.entries.iter().enumerate() {
+        // In fragmented files the duration of the last edit may be 0,
+        // for an edit which includes all the following media.
+        let duration = if entry.segment_duration == 0 && i + 1 == elst.entries.len() {
+            None
+        } else {
+            let duration = entry.segment_duration as u128 * trak.mdia.mdhd.timescale as u128;
+            Some(
+                duration
+                    .checked_div(movie_timescale as u128)
+                    .unwrap_or(duration) as u64,
+            )
+        };
+        edits.push(TrackEdit {
+            presentation_time,
+            duration,
+            media_time: Some(entry.media_time).filter(|&time| time >= 0),
+            media_rate: entry.media_rate,
+        });
+        presentation_time = presentation_time.saturating_add(duration.unwrap_or(0));
+    }
+    edits
+}
+
+/// Offset following a sample of `size` bytes at `offset`.
+fn add_offset(offset: u64, size: u32) -> Result<u64> {
+    offset
+        .checked_add(size as u64)
+        .ok_or(Error::InvalidData("sample offset out of range"))
+}
+
+/// Precomputed sample lookups of a track. The samples are grouped in runs
+/// of samples contiguous in the file, chunks or truns, and store their offset
+/// and decode time relative to the start of their run.
+#[derive(Debug, Clone, Default)]
+struct SampleIndex {
+    runs: Vec<IndexRun>,
+    // Indexed by sample id - 1.
+    entries: Vec<IndexEntry>,
+}
+
+impl SampleIndex {
+    fn with_capacity(sample_count: usize) -> Self {
+        SampleIndex {
+            runs: Vec::new(),
+            entries: Vec::with_capacity(sample_count),
+        }
+    }
+
+    /// Add the next sample, starting a new run at it if `new_run` is set or if
+    /// it's too far from the start of the current run.
+    fn push(&mut self, new_run: bool, sample: IndexedSample) {
+        let sample_id = self.entries.len() as u32 + 1;
+        let deltas = self.runs.last().filter(|_| !new_run).and_then(|run| {
+            let offset = u32::try_from(sample.offset.checked_sub(run.offset)?).ok()?;
+            let time = u32::try_from(sample.time.checked_sub(run.time)?).ok()?;
+            Some((offset, time))
+        });
+        let (offset, time) = deltas.unwrap_or_else(|| {
+            self.runs.push(IndexRun {
+                first_sample_id: sample_id,
+                offset: sample.offset,
+                time: sample.time,
+                source: sample.source,
+            });
+            (0, 0)
+        });
+        self.entries.push(IndexEntry {
+            offset,
+            time,
+            size: sample.size,
+            duration: sample.duration,
+            rendering_offset: sample.rendering_offset,
+        });
+    }
+
+    fn get(&self, sample_id: u32) -> Option<IndexedSample> {
+        let entry = self.entries.get(sample_id.checked_sub(1)? as usize)?;
+        let run_idx = self
+            .runs
+            .partition_point(|run| run.first_sample_id <= sample_id);
+        let run = &self.runs[run_idx - 1];
+        let source = match run.source {
+            RunSource::Trun(traf_idx, trun_idx, sample_idx) => {
+                let run_sample_idx = (sample_id - run.first_sample_id) as usize;
+                RunSource::Trun(traf_idx, trun_idx, sample_idx + run_sample_idx)
+            }
+            source => source,
+        };
+        Some(IndexedSample {
+            offset: run.offset + entry.offset as u64,
+            size: entry.size,
+            time: run.time + entry.time as u64,
+            duration: entry.duration,
+            rendering_offset: entry.rendering_offset,
+            source,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IndexRun {
+    first_sample_id: u32,
+    offset: u64,
+    time: u64,
+    // Source of the first sample of the run.
+    source: RunSource,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry {
+    offset: u32,
+    time: u32,
+    size: u32,
+    duration: u32,
+    rendering_offset: i32,
+}
+
+/// Where the flags and the sample description index of a sample are found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunSource {
+    /// Index of the stsc entry of the chunk of the sample.
+    Stsc(usize),
+
+    /// Indices of the traf, of the trun and of the sample in the trun.
+    Trun(usize, usize, usize),
+}
+
+/// A sample as it's added to and found in a `SampleIndex`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexedSample {
+    offset: u64,
+    size: u32,
+    time: u64,
+    duration: u32,
+    rendering_offset: i32,
+    source: RunSource,
+}
+
 // TODO creation_time, modification_time
 #[derive(Debug, Default)]
 pub(crate) struct Mp4TrackWriter {
@@ -571,7 +1803,12 @@ impl Mp4TrackWriter {
                 trak.mdia.minf.vmhd = Some(vmhd);
 
                 let avc1 = Avc1Box::new(avc_config);
-                trak.mdia.minf.stbl.stsd.avc1 = Some(avc1);
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Avc1(avc1));
             }
             MediaConfig::HevcConfig(ref hevc_config) => {
                 trak.tkhd.set_width(hevc_config.width);
@@ -580,25 +1817,79 @@ impl Mp4TrackWriter {
                 let vmhd = VmhdBox::default();
                 trak.mdia.minf.vmhd = Some(vmhd);
 
-                let hev1 = Hev1Box::new(hevc_config);
-                trak.mdia.minf.stbl.stsd.hev1 = Some(hev1);
+                let entry = match hevc_config.parameter_sets {
+                    HevcParameterSets::InBand => SampleEntry::Hev1(Hev1Box::new(hevc_config)),
+                    HevcParameterSets::OutOfBand => SampleEntry::Hvc1(Hvc1Box::new(hevc_config)),
+                };
+                trak.mdia.minf.stbl.stsd.entries.push(entry);
             }
             MediaConfig::Vp9Config(ref config) => {
                 trak.tkhd.set_width(config.width);
                 trak.tkhd.set_height(config.height);
 
-                trak.mdia.minf.stbl.stsd.vp09 = Some(Vp09Box::new(config));
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Vp09(Vp09Box::new(config)));
+            }
+            MediaConfig::Av1Config(ref config) => {
+                trak.tkhd.set_width(config.width);
+                trak.tkhd.set_height(config.height);
+
+                let vmhd = VmhdBox::default();
+                trak.mdia.minf.vmhd = Some(vmhd);
+
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Av01(Av01Box::new(config)));
             }
             MediaConfig::AacConfig(ref aac_config) => {
                 let smhd = SmhdBox::default();
                 trak.mdia.minf.smhd = Some(smhd);
 
                 let mp4a = Mp4aBox::new(aac_config);
-                trak.mdia.minf.stbl.stsd.mp4a = Some(mp4a);
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Mp4a(mp4a));
+            }
+            MediaConfig::OpusConfig(ref opus_config) => {
+                let smhd = SmhdBox::default();
+                trak.mdia.minf.smhd = Some(smhd);
+
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Opus(OpusBox::new(opus_config)));
+            }
+            MediaConfig::FlacConfig(ref flac_config) => {
+                let smhd = SmhdBox::default();
+                trak.mdia.minf.smhd = Some(smhd);
+
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Flac(FlacBox::new(flac_config)));
             }
             MediaConfig::TtxtConfig(ref _ttxt_config) => {
                 let tx3g = Tx3gBox::default();
-                trak.mdia.minf.stbl.stsd.tx3g = Some(tx3g);
+                trak.mdia
+                    .minf
+                    .stbl
+                    .stsd
+                    .entries
+                    .push(SampleEntry::Tx3g(tx3g));
             }
         }
         Ok(Mp4TrackWriter {
@@ -737,6 +2028,15 @@ impl Mp4TrackWriter {
         sample: &Mp4Sample,
         movie_timescale: u32,
     ) -> Result<u64> {
+        if self.add_sample(sample) {
+            self.write_chunk(writer)?;
+        }
+        Ok(self.end_sample(sample, movie_timescale))
+    }
+
+    /// Buffer a sample, returning whether the chunk is full and should be
+    /// written before `end_sample`.
+    pub(crate) fn add_sample(&mut self, sample: &Mp4Sample) -> bool {
         self.chunk_buffer.extend_from_slice(&sample.bytes);
         self.chunk_samples += 1;
         self.chunk_duration += sample.duration;
@@ -744,14 +2044,16 @@ impl Mp4TrackWriter {
         self.update_sample_times(sample.duration);
         self.update_rendering_offsets(sample.rendering_offset);
         self.update_sync_samples(sample.is_sync);
-        if self.is_chunk_full() {
-            self.write_chunk(writer)?;
-        }
+        self.is_chunk_full()
+    }
+
+    /// Finish writing a sample, returning the duration of the track.
+    pub(crate) fn end_sample(&mut self, sample: &Mp4Sample, movie_timescale: u32) -> u64 {
         self.update_durations(sample.duration, movie_timescale);
 
         self.sample_id += 1;
 
-        Ok(self.trak.tkhd.duration)
+        self.trak.tkhd.duration
     }
 
     fn chunk_count(&self) -> u32 {
@@ -781,21 +2083,56 @@ impl Mp4TrackWriter {
     }
 
     fn write_chunk<W: Write + Seek>(&mut self, writer: &mut W) -> Result<()> {
-        if self.chunk_buffer.is_empty() {
+        if self.chunk_buffer().is_empty() {
             return Ok(());
         }
         let chunk_offset = writer.stream_position()?;
 
-        writer.write_all(&self.chunk_buffer)?;
+        writer.write_all(self.chunk_buffer())?;
+
+        self.chunk_written(chunk_offset);
+        Ok(())
+    }
+
+    /// Data of the buffered chunk, empty if there is none.
+    pub(crate) fn chunk_buffer(&self) -> &[u8] {
+        &self.chunk_buffer
+    }
 
+    /// Record the buffered chunk as written at `chunk_offset`.
+    pub(crate) fn chunk_written(&mut self, chunk_offset: u64) {
         self.update_sample_to_chunk(self.chunk_count() + 1);
         self.update_chunk_offsets(chunk_offset);
 
         self.chunk_buffer.clear();
         self.chunk_samples = 0;
         self.chunk_duration = 0;
+    }
 
-        Ok(())
+    /// Set an edit list presenting the media from `media_time` on.
+    fn trim_start(&mut self, media_time: u64, movie_timescale: u32) {
+        let mdhd = &self.trak.mdia.mdhd;
+        let duration = mdhd.duration.saturating_sub(media_time) as u128 * movie_timescale as u128;
+        let segment_duration = duration.checked_div(mdhd.timescale as u128).unwrap_or(0) as u64;
+        let version = if segment_duration > u32::MAX as u64 || media_time > i32::MAX as u64 {
+            1
+        } else {
+            0
+        };
+        let elst = ElstBox {
+            version,
+            flags: 0,
+            entries: vec![ElstEntry {
+                segment_duration,
+                media_time: media_time as i64,
+                media_rate: 1,
+                media_rate_fraction: 0,
+            }],
+        };
+        self.trak.edts = Some(EdtsBox {
+            elst: Some(elst),
+            ..EdtsBox::new()
+        });
     }
 
     fn max_sample_size(&self) -> u32 {
@@ -810,11 +2147,19 @@ impl Mp4TrackWriter {
         }
     }
 
-    pub(crate) fn write_end<W: Write + Seek>(&mut self, writer: &mut W) -> Result<TrakBox> {
+    pub(crate) fn write_end<W: Write + Seek>(
+        &mut self,
+        writer: &mut W,
+        movie_timescale: u32,
+    ) -> Result<TrakBox> {
         self.write_chunk(writer)?;
+        Ok(self.end_trak(movie_timescale))
+    }
 
+    /// Build the trak of the track, once its last chunk is written.
+    pub(crate) fn end_trak(&mut self, movie_timescale: u32) -> TrakBox {
         let max_sample_size = self.max_sample_size();
-        if let Some(ref mut mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a {
+        if let Some(mp4a) = self.trak.mdia.minf.stbl.stsd.mp4a_mut() {
             if let Some(ref mut esds) = mp4a.esds {
                 esds.es_desc.dec_config.buffer_size_db = max_sample_size;
             }
@@ -822,11 +2167,17 @@ impl Mp4TrackWriter {
             // mp4a.esds.es_desc.dec_config.max_bitrate
             // mp4a.esds.es_desc.dec_config.avg_bitrate
         }
+        if let Some(opus) = self.trak.mdia.minf.stbl.stsd.opus() {
+            let media_time = pre_skip_time(opus.dops.pre_skip, self.trak.mdia.mdhd.timescale);
+            if media_time > 0 {
+                self.trim_start(media_time, movie_timescale);
+            }
+        }
         if let Ok(stco) = StcoBox::try_from(self.trak.mdia.minf.stbl.co64.as_ref().unwrap()) {
             self.trak.mdia.minf.stbl.stco = Some(stco);
             self.trak.mdia.minf.stbl.co64 = None;
         }
 
-        Ok(self.trak.clone())
+        self.trak.clone()
     }
 }
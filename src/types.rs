@@ -2,6 +2,7 @@ use serde::Serialize;
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
+use std::ops::Range;
 
 use crate::mp4box::*;
 use crate::*;
@@ -220,7 +221,10 @@ impl From<TrackType> for FourCC {
 const MEDIA_TYPE_H264: &str = "h264";
 const MEDIA_TYPE_H265: &str = "h265";
 const MEDIA_TYPE_VP9: &str = "vp9";
+const MEDIA_TYPE_AV1: &str = "av1";
 const MEDIA_TYPE_AAC: &str = "aac";
+const MEDIA_TYPE_OPUS: &str = "opus";
+const MEDIA_TYPE_FLAC: &str = "flac";
 const MEDIA_TYPE_TTXT: &str = "ttxt";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -228,7 +232,10 @@ pub enum MediaType {
     H264,
     H265,
     VP9,
+    AV1,
     AAC,
+    OPUS,
+    FLAC,
     TTXT,
 }
 
@@ -246,7 +253,10 @@ impl TryFrom<&str> for MediaType {
             MEDIA_TYPE_H264 => Ok(MediaType::H264),
             MEDIA_TYPE_H265 => Ok(MediaType::H265),
             MEDIA_TYPE_VP9 => Ok(MediaType::VP9),
+            MEDIA_TYPE_AV1 => Ok(MediaType::AV1),
             MEDIA_TYPE_AAC => Ok(MediaType::AAC),
+            MEDIA_TYPE_OPUS => Ok(MediaType::OPUS),
+            MEDIA_TYPE_FLAC => Ok(MediaType::FLAC),
             MEDIA_TYPE_TTXT => Ok(MediaType::TTXT),
             _ => Err(Error::InvalidData("unsupported media type")),
         }
@@ -259,7 +269,10 @@ impl From<MediaType> for &str {
             MediaType::H264 => MEDIA_TYPE_H264,
             MediaType::H265 => MEDIA_TYPE_H265,
             MediaType::VP9 => MEDIA_TYPE_VP9,
+            MediaType::AV1 => MEDIA_TYPE_AV1,
             MediaType::AAC => MEDIA_TYPE_AAC,
+            MediaType::OPUS => MEDIA_TYPE_OPUS,
+            MediaType::FLAC => MEDIA_TYPE_FLAC,
             MediaType::TTXT => MEDIA_TYPE_TTXT,
         }
     }
@@ -271,7 +284,10 @@ impl From<&MediaType> for &str {
             MediaType::H264 => MEDIA_TYPE_H264,
             MediaType::H265 => MEDIA_TYPE_H265,
             MediaType::VP9 => MEDIA_TYPE_VP9,
+            MediaType::AV1 => MEDIA_TYPE_AV1,
             MediaType::AAC => MEDIA_TYPE_AAC,
+            MediaType::OPUS => MEDIA_TYPE_OPUS,
+            MediaType::FLAC => MEDIA_TYPE_FLAC,
             MediaType::TTXT => MEDIA_TYPE_TTXT,
         }
     }
@@ -576,6 +592,27 @@ pub struct AvcConfig {
 pub struct HevcConfig {
     pub width: u16,
     pub height: u16,
+
+    /// Parameter sets written in the hvcC box, any of them may be left empty
+    /// if they are in the samples instead.
+    pub video_param_set: Vec<u8>,
+    pub seq_param_set: Vec<u8>,
+    pub pic_param_set: Vec<u8>,
+    pub parameter_sets: HevcParameterSets,
+}
+
+/// Where the parameter sets of an HEVC track are, which selects its sample
+/// entry.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum HevcParameterSets {
+    /// In the hvcC box and possibly in the samples too, with a hev1 sample
+    /// entry.
+    #[default]
+    InBand,
+
+    /// Only in the hvcC box, with a hvc1 sample entry as required by some
+    /// players.
+    OutOfBand,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
@@ -584,6 +621,45 @@ pub struct Vp9Config {
     pub height: u16,
 }
 
+/// Fields of the av1C box of an AV1 track, see the AV1 ISOBMFF binding 2.3.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Av1Config {
+    pub width: u16,
+    pub height: u16,
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+
+    /// 8, 10 or 12.
+    pub bit_depth: u8,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8,
+
+    /// Sequence header OBU of the stream, in the low overhead bitstream
+    /// format, optionally followed by metadata OBUs.
+    pub config_obus: Vec<u8>,
+}
+
+impl Default for Av1Config {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            seq_profile: 0,
+            seq_level_idx_0: 31,
+            seq_tier_0: false,
+            bit_depth: 8,
+            monochrome: false,
+            chroma_subsampling_x: true,
+            chroma_subsampling_y: true,
+            chroma_sample_position: 0,
+            config_obus: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AacConfig {
     pub bitrate: u32,
@@ -603,6 +679,105 @@ impl Default for AacConfig {
     }
 }
 
+/// Fields of the dOps box of an Opus track, see Encapsulation of Opus in
+/// ISOBMFF 4.3.2.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct OpusConfig {
+    pub channel_count: u8,
+
+    /// Samples at 48 kHz to discard from the start of the decoded audio. The
+    /// writer trims them with an edit list.
+    pub pre_skip: u16,
+
+    /// Sample rate of the encoder input, for information only.
+    pub input_sample_rate: u32,
+
+    /// Gain in dB as a Q7.8 fixed point number.
+    pub output_gain: i16,
+    pub channel_mapping_family: u8,
+
+    /// Required for the channel mapping families other than 0.
+    pub channel_mapping: Option<OpusChannelMapping>,
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            channel_count: 2,
+            pre_skip: 0,
+            input_sample_rate: 48000,
+            output_gain: 0,
+            channel_mapping_family: 0,
+            channel_mapping: None,
+        }
+    }
+}
+
+/// Mapping of the decoded Opus streams to the output channels.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
+pub struct OpusChannelMapping {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+
+    /// Index of the decoded channel of each output channel, 255 for silence.
+    pub channel_mapping: Vec<u8>,
+}
+
+/// Metadata blocks of the dfLa box of a FLAC track, see Encapsulation of
+/// FLAC in ISOBMFF 3.3.2.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct FlacConfig {
+    pub stream_info: FlacStreamInfo,
+
+    /// Metadata blocks following STREAMINFO, such as VORBIS_COMMENT. PADDING
+    /// blocks shouldn't be included.
+    pub metadata_blocks: Vec<FlacMetadataBlock>,
+}
+
+/// STREAMINFO metadata block of a FLAC stream.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct FlacStreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+
+    /// Frame sizes in bytes on 24 bits, 0 if unknown.
+    pub min_frame_size: u32,
+    pub max_frame_size: u32,
+    pub sample_rate: u32,
+    pub channels: u8,
+    pub bits_per_sample: u8,
+
+    /// Samples per channel on 36 bits, 0 if unknown.
+    pub total_samples: u64,
+
+    /// MD5 of the unencoded audio data, all zeros if unknown.
+    pub md5: [u8; 16],
+}
+
+impl Default for FlacStreamInfo {
+    fn default() -> Self {
+        Self {
+            min_block_size: 4096,
+            max_block_size: 4096,
+            min_frame_size: 0,
+            max_frame_size: 0,
+            sample_rate: 44100,
+            channels: 2,
+            bits_per_sample: 16,
+            total_samples: 0,
+            md5: [0; 16],
+        }
+    }
+}
+
+/// Metadata block of a FLAC stream other than STREAMINFO, as its type and
+/// its data.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize)]
+pub struct FlacMetadataBlock {
+    pub block_type: u8,
+    pub data: Vec<u8>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct TtxtConfig {}
 
@@ -611,16 +786,174 @@ pub enum MediaConfig {
     AvcConfig(AvcConfig),
     HevcConfig(HevcConfig),
     Vp9Config(Vp9Config),
+    Av1Config(Av1Config),
     AacConfig(AacConfig),
+    OpusConfig(OpusConfig),
+    FlacConfig(FlacConfig),
     TtxtConfig(TtxtConfig),
 }
 
+/// Sample flags of a fragmented track sample, see ISO/IEC 14496-12 8.8.3.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct SampleFlags {
+    pub is_leading: u8,
+    pub depends_on: u8,
+    pub is_depended_on: u8,
+    pub has_redundancy: u8,
+    pub padding_value: u8,
+    pub is_non_sync_sample: bool,
+    pub degradation_priority: u16,
+}
+
+impl SampleFlags {
+    pub fn is_sync(&self) -> bool {
+        !self.is_non_sync_sample
+    }
+}
+
+impl From<u32> for SampleFlags {
+    fn from(flags: u32) -> Self {
+        Self {
+            is_leading: ((flags >> 26) & 0x3) as u8,
+            depends_on: ((flags >> 24) & 0x3) as u8,
+            is_depended_on: ((flags >> 22) & 0x3) as u8,
+            has_redundancy: ((flags >> 20) & 0x3) as u8,
+            padding_value: ((flags >> 17) & 0x7) as u8,
+            is_non_sync_sample: (flags >> 16) & 0x1 == 1,
+            degradation_priority: (flags & 0xFFFF) as u16,
+        }
+    }
+}
+
+impl From<SampleFlags> for u32 {
+    fn from(flags: SampleFlags) -> u32 {
+        ((flags.is_leading as u32 & 0x3) << 26)
+            | ((flags.depends_on as u32 & 0x3) << 24)
+            | ((flags.is_depended_on as u32 & 0x3) << 22)
+            | ((flags.has_redundancy as u32 & 0x3) << 20)
+            | ((flags.padding_value as u32 & 0x7) << 17)
+            | ((flags.is_non_sync_sample as u32) << 16)
+            | flags.degradation_priority as u32
+    }
+}
+
+/// Which sample to pick when seeking a track to a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    /// The sample at the time.
+    Exact,
+    /// The last sync sample at or before the sample at the time.
+    PreviousSync,
+    /// The first sync sample at or after the sample at the time.
+    NextSync,
+}
+
+/// Timeline of the times given when seeking a track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timeline {
+    /// Decode times, the `start_time` of the samples.
+    Decode,
+    /// Composition times, the `start_time` plus the `rendering_offset` of the
+    /// samples.
+    Presentation,
+}
+
+/// Path of a box from the top level down, as the type of each box with its
+/// index among the boxes of the same type in its parent, from 1 on. The boxes
+/// skipped in lenient mode aren't counted.
+///
+/// Displayed as `moov/trak[2]/mdia`, the index only given from 2 on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BoxPath(pub Vec<(BoxType, u32)>);
+
+impl BoxPath {
+    /// Type of the box at the end of the path.
+    pub fn last(&self) -> Option<BoxType> {
+        self.0.last().map(|&(name, _)| name)
+    }
+}
+
+impl fmt::Display for BoxPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, index)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "/")?;
+            }
+            write!(f, "{name}")?;
+            if *index > 1 {
+                write!(f, "[{index}]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A problem found while reading a file which didn't stop the reading, see
+/// [`Mp4Reader::warnings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mp4Warning {
+    /// Path of the box with the problem.
+    pub path: BoxPath,
+    /// Offset of the box with the problem in the file.
+    pub offset: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Mp4Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {}: {}",
+            self.path, self.offset, self.message
+        )
+    }
+}
+
+/// Where a box is in a file, see [`Mp4Reader::box_locations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoxLocation {
+    pub path: BoxPath,
+    /// Offset of the start of the box header in the file.
+    pub offset: u64,
+    /// Size of the box header, 16 with a 64-bit largesize.
+    pub header_size: u64,
+    /// Size of the box with its header.
+    pub size: u64,
+}
+
+impl BoxLocation {
+    /// Type of the box.
+    pub fn name(&self) -> Option<BoxType> {
+        self.path.last()
+    }
+
+    /// Offset of the payload of the box, following its header.
+    pub fn payload_offset(&self) -> u64 {
+        self.offset + self.header_size
+    }
+
+    /// Offsets of the box in the file, header included.
+    pub fn range(&self) -> Range<u64> {
+        self.offset..self.offset.saturating_add(self.size)
+    }
+
+    /// Offsets of the payload of the box in the file.
+    pub fn payload_range(&self) -> Range<u64> {
+        self.payload_offset()..self.offset.saturating_add(self.size)
+    }
+}
+
 #[derive(Debug)]
 pub struct Mp4Sample {
     pub start_time: u64,
     pub duration: u32,
     pub rendering_offset: i32,
     pub is_sync: bool,
+    pub flags: Option<SampleFlags>,
+
+    /// Index of the sample entry describing the sample, see
+    /// [`Mp4Track::sample_entry`].
+    pub sample_description_index: u32,
     pub bytes: Bytes,
 }
 
@@ -630,6 +963,8 @@ impl PartialEq for Mp4Sample {
             && self.duration == other.duration
             && self.rendering_offset == other.rendering_offset
             && self.is_sync == other.is_sync
+            && self.flags == other.flags
+            && self.sample_description_index == other.sample_description_index
             && self.bytes.len() == other.bytes.len() // XXX for easy check
     }
 }
@@ -657,20 +992,15 @@ pub fn creation_time(creation_time: u64) -> u64 {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize)]
 pub enum DataType {
+    #[default]
     Binary = 0x000000,
     Text = 0x000001,
     Image = 0x00000D,
     TempoCpil = 0x000015,
 }
 
-impl std::default::Default for DataType {
-    fn default() -> Self {
-        DataType::Binary
-    }
-}
-
 impl TryFrom<u32> for DataType {
     type Error = Error;
     fn try_from(value: u32) -> Result<DataType> {
@@ -694,17 +1024,17 @@ pub enum MetadataKey {
 
 pub trait Metadata<'a> {
     /// The video's title
-    fn title(&self) -> Option<Cow<str>>;
+    fn title(&self) -> Option<Cow<'_, str>>;
     /// The video's release year
     fn year(&self) -> Option<u32>;
     /// The video's poster (cover art)
     fn poster(&self) -> Option<&[u8]>;
     /// The video's summary
-    fn summary(&self) -> Option<Cow<str>>;
+    fn summary(&self) -> Option<Cow<'_, str>>;
 }
 
 impl<'a, T: Metadata<'a>> Metadata<'a> for &'a T {
-    fn title(&self) -> Option<Cow<str>> {
+    fn title(&self) -> Option<Cow<'_, str>> {
         (**self).title()
     }
 
@@ -716,13 +1046,13 @@ impl<'a, T: Metadata<'a>> Metadata<'a> for &'a T {
         (**self).poster()
     }
 
-    fn summary(&self) -> Option<Cow<str>> {
+    fn summary(&self) -> Option<Cow<'_, str>> {
         (**self).summary()
     }
 }
 
 impl<'a, T: Metadata<'a>> Metadata<'a> for Option<T> {
-    fn title(&self) -> Option<Cow<str>> {
+    fn title(&self) -> Option<Cow<'_, str>> {
         self.as_ref().and_then(|t| t.title())
     }
 
@@ -734,7 +1064,7 @@ impl<'a, T: Metadata<'a>> Metadata<'a> for Option<T> {
         self.as_ref().and_then(|t| t.poster())
     }
 
-    fn summary(&self) -> Option<Cow<str>> {
+    fn summary(&self) -> Option<Cow<'_, str>> {
         self.as_ref().and_then(|t| t.summary())
     }
 }
@@ -1,6 +1,9 @@
 use byteorder::{BigEndian, WriteBytesExt};
 use std::io::{Seek, SeekFrom, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
+use crate::mp4box::trak::TrakBox;
 use crate::mp4box::*;
 use crate::track::Mp4TrackWriter;
 use crate::*;
@@ -57,32 +60,15 @@ impl<W> Mp4Writer<W> {
     pub fn into_writer(self) -> W {
         self.writer
     }
-}
-
-impl<W: Write + Seek> Mp4Writer<W> {
-    pub fn write_start(mut writer: W, config: &Mp4Config) -> Result<Self> {
-        let ftyp = FtypBox {
-            major_brand: config.major_brand,
-            minor_version: config.minor_version,
-            compatible_brands: config.compatible_brands.clone(),
-        };
-        ftyp.write_box(&mut writer)?;
 
-        // TODO largesize
-        let mdat_pos = writer.stream_position()?;
-        BoxHeader::new(BoxType::MdatBox, HEADER_SIZE).write(&mut writer)?;
-        BoxHeader::new(BoxType::WideBox, HEADER_SIZE).write(&mut writer)?;
-
-        let tracks = Vec::new();
-        let timescale = config.timescale;
-        let duration = 0;
-        Ok(Self {
+    fn new(writer: W, config: &Mp4Config, mdat_pos: u64) -> Self {
+        Self {
             writer,
-            tracks,
+            tracks: Vec::new(),
             mdat_pos,
-            timescale,
-            duration,
-        })
+            timescale: config.timescale,
+            duration: 0,
+        }
     }
 
     pub fn add_track(&mut self, config: &TrackConfig) -> Result<()> {
@@ -98,16 +84,57 @@ impl<W: Write + Seek> Mp4Writer<W> {
         }
     }
 
-    pub fn write_sample(&mut self, track_id: u32, sample: &Mp4Sample) -> Result<()> {
-        if track_id == 0 {
-            return Err(Error::TrakNotFound(track_id));
+    fn moov(&self, traks: Vec<TrakBox>) -> MoovBox {
+        let mut moov = MoovBox {
+            traks,
+            ..MoovBox::default()
+        };
+        moov.mvhd.timescale = self.timescale;
+        moov.mvhd.duration = self.duration;
+        if moov.mvhd.duration > (u32::MAX as u64) {
+            moov.mvhd.version = 1
         }
+        moov
+    }
+}
 
-        let track_dur = if let Some(ref mut track) = self.tracks.get_mut(track_id as usize - 1) {
-            track.write_sample(&mut self.writer, sample, self.timescale)?
-        } else {
-            return Err(Error::TrakNotFound(track_id));
-        };
+fn track_mut(tracks: &mut [Mp4TrackWriter], track_id: u32) -> Result<&mut Mp4TrackWriter> {
+    if track_id == 0 {
+        return Err(Error::TrakNotFound(track_id));
+    }
+    tracks
+        .get_mut(track_id as usize - 1)
+        .ok_or(Error::TrakNotFound(track_id))
+}
+
+fn ftyp(config: &Mp4Config) -> FtypBox {
+    FtypBox {
+        major_brand: config.major_brand,
+        minor_version: config.minor_version,
+        compatible_brands: config.compatible_brands.clone(),
+    }
+}
+
+fn write_mdat_start<W: Write>(writer: &mut W) -> Result<()> {
+    // TODO largesize
+    BoxHeader::new(BoxType::MdatBox, HEADER_SIZE).write(writer)?;
+    BoxHeader::new(BoxType::WideBox, HEADER_SIZE).write(writer)?;
+    Ok(())
+}
+
+impl<W: Write + Seek> Mp4Writer<W> {
+    pub fn write_start(mut writer: W, config: &Mp4Config) -> Result<Self> {
+        ftyp(config).write_box(&mut writer)?;
+
+        let mdat_pos = writer.stream_position()?;
+        write_mdat_start(&mut writer)?;
+
+        Ok(Self::new(writer, config, mdat_pos))
+    }
+
+    pub fn write_sample(&mut self, track_id: u32, sample: &Mp4Sample) -> Result<()> {
+        let track = track_mut(&mut self.tracks, track_id)?;
+        let track_dur = track.write_sample(&mut self.writer, sample, self.timescale)?;
 
         self.update_durations(track_dur);
 
@@ -117,7 +144,7 @@ impl<W: Write + Seek> Mp4Writer<W> {
     fn update_mdat_size(&mut self) -> Result<()> {
         let mdat_end = self.writer.stream_position()?;
         let mdat_size = mdat_end - self.mdat_pos;
-        if mdat_size > std::u32::MAX as u64 {
+        if mdat_size > u32::MAX as u64 {
             self.writer.seek(SeekFrom::Start(self.mdat_pos))?;
             self.writer.write_u32::<BigEndian>(1)?;
             self.writer.seek(SeekFrom::Start(self.mdat_pos + 8))?;
@@ -131,19 +158,90 @@ impl<W: Write + Seek> Mp4Writer<W> {
     }
 
     pub fn write_end(&mut self) -> Result<()> {
-        let mut moov = MoovBox::default();
-
+        let mut traks = Vec::new();
         for track in self.tracks.iter_mut() {
-            moov.traks.push(track.write_end(&mut self.writer)?);
+            traks.push(track.write_end(&mut self.writer, self.timescale)?);
         }
         self.update_mdat_size()?;
 
-        moov.mvhd.timescale = self.timescale;
-        moov.mvhd.duration = self.duration;
-        if moov.mvhd.duration > (u32::MAX as u64) {
-            moov.mvhd.version = 1
+        self.moov(traks).write_box(&mut self.writer)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite + AsyncSeek + Unpin> Mp4Writer<W> {
+    /// Async counterpart of [`Mp4Writer::write_start`].
+    pub async fn write_start_async(mut writer: W, config: &Mp4Config) -> Result<Self> {
+        let mut buf = Vec::new();
+        ftyp(config).write_box(&mut buf)?;
+        writer.write_all(&buf).await?;
+
+        let mdat_pos = writer.stream_position().await?;
+        buf.clear();
+        write_mdat_start(&mut buf)?;
+        writer.write_all(&buf).await?;
+
+        Ok(Self::new(writer, config, mdat_pos))
+    }
+
+    /// Async counterpart of [`Mp4Writer::write_sample`].
+    pub async fn write_sample_async(&mut self, track_id: u32, sample: &Mp4Sample) -> Result<()> {
+        let track = track_mut(&mut self.tracks, track_id)?;
+        if track.add_sample(sample) {
+            write_chunk_async(&mut self.writer, track).await?;
+        }
+        let track_dur = track.end_sample(sample, self.timescale);
+
+        self.update_durations(track_dur);
+
+        Ok(())
+    }
+
+    async fn update_mdat_size_async(&mut self) -> Result<()> {
+        let mdat_end = self.writer.stream_position().await?;
+        let mdat_size = mdat_end - self.mdat_pos;
+        if mdat_size > u32::MAX as u64 {
+            self.writer.seek(SeekFrom::Start(self.mdat_pos)).await?;
+            self.writer.write_u32(1).await?;
+            self.writer.seek(SeekFrom::Start(self.mdat_pos + 8)).await?;
+            self.writer.write_u64(mdat_size).await?;
+        } else {
+            self.writer.seek(SeekFrom::Start(self.mdat_pos)).await?;
+            self.writer.write_u32(mdat_size as u32).await?;
+        }
+        self.writer.seek(SeekFrom::Start(mdat_end)).await?;
+        Ok(())
+    }
+
+    /// Async counterpart of [`Mp4Writer::write_end`], which also flushes the
+    /// writer.
+    pub async fn write_end_async(&mut self) -> Result<()> {
+        let mut traks = Vec::new();
+        for track in self.tracks.iter_mut() {
+            write_chunk_async(&mut self.writer, track).await?;
+            traks.push(track.end_trak(self.timescale));
         }
-        moov.write_box(&mut self.writer)?;
+        self.update_mdat_size_async().await?;
+
+        let mut buf = Vec::new();
+        self.moov(traks).write_box(&mut buf)?;
+        self.writer.write_all(&buf).await?;
+        self.writer.flush().await?;
         Ok(())
     }
 }
+
+#[cfg(feature = "tokio")]
+async fn write_chunk_async<W: AsyncWrite + AsyncSeek + Unpin>(
+    writer: &mut W,
+    track: &mut Mp4TrackWriter,
+) -> Result<()> {
+    if track.chunk_buffer().is_empty() {
+        return Ok(());
+    }
+    let chunk_offset = writer.stream_position().await?;
+    writer.write_all(track.chunk_buffer()).await?;
+    track.chunk_written(chunk_offset);
+    Ok(())
+}
@@ -0,0 +1,140 @@
+#![cfg(feature = "tokio")]
+
+use mp4::{AacConfig, Bytes, Error, Mp4Config, Mp4Reader, Mp4Sample, Mp4Writer, TrackConfig};
+use std::io::Cursor;
+
+#[tokio::test]
+async fn test_async_read_fragmented() {
+    let data = std::fs::read("tests/samples/fragmented.mp4").unwrap();
+    let size = data.len() as u64;
+    let mut sync_mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    let mut mp4 = Mp4Reader::read_header_async(Cursor::new(data), size)
+        .await
+        .unwrap();
+
+    assert_eq!(mp4.ftyp, sync_mp4.ftyp);
+    assert_eq!(mp4.moov, sync_mp4.moov);
+    assert_eq!(mp4.moofs, sync_mp4.moofs);
+    assert_eq!(mp4.size(), sync_mp4.size());
+    for track_id in [1, 2] {
+        let sample_count = mp4.sample_count(track_id).unwrap();
+        assert_eq!(sample_count, sync_mp4.sample_count(track_id).unwrap());
+        for sample_id in 1..=sample_count {
+            let sample = mp4.read_sample_async(track_id, sample_id).await.unwrap();
+            let expected = sync_mp4.read_sample(track_id, sample_id).unwrap();
+            assert_eq!(sample, expected);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_async_box_locations() {
+    let mut data = std::fs::read("tests/samples/fragmented.mp4").unwrap();
+    // An unknown box with a largesize at the end.
+    data.extend_from_slice(&[0, 0, 0, 1, b'a', b'b', b'c', b'd']);
+    data.extend_from_slice(&20u64.to_be_bytes());
+    data.extend_from_slice(&[0; 4]);
+
+    let size = data.len() as u64;
+    let sync_mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    let mp4 = Mp4Reader::read_header_async(Cursor::new(data), size)
+        .await
+        .unwrap();
+    assert_eq!(mp4.box_locations(), sync_mp4.box_locations());
+    assert_eq!(mp4.warnings(), sync_mp4.warnings());
+    assert_eq!(mp4.box_locations().last().unwrap().header_size, 16);
+}
+
+#[tokio::test]
+async fn test_async_read_oversized_largesize() {
+    let sample = std::fs::read("tests/samples/fragmented.mp4").unwrap();
+    for (name, largesize) in [(b"mdat", u64::MAX), (b"moof", u64::MAX), (b"moof", 1 << 40)] {
+        let mut data = sample.clone();
+        data.extend_from_slice(&[0, 0, 0, 1]);
+        data.extend_from_slice(name);
+        data.extend_from_slice(&largesize.to_be_bytes());
+
+        let size = data.len() as u64;
+        let sync_err = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap_err();
+        let err = Mp4Reader::read_header_async(Cursor::new(data), size)
+            .await
+            .unwrap_err();
+        if largesize == u64::MAX {
+            assert!(matches!(err, Error::InvalidData("box size too large")));
+        } else {
+            assert!(matches!(err.root(), Error::LimitExceeded("box size", _, _)));
+        }
+        assert_eq!(err.to_string(), sync_err.to_string());
+    }
+}
+
+#[tokio::test]
+async fn test_async_read_size_zero_mdat() {
+    let mut data = std::fs::read("tests/samples/fragmented.mp4").unwrap();
+    let last_mdat = data.windows(4).rposition(|name| name == b"mdat").unwrap() - 4;
+    data[last_mdat..last_mdat + 4].copy_from_slice(&[0; 4]);
+
+    let size = data.len() as u64;
+    let mut sync_mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    let mut mp4 = Mp4Reader::read_header_async(Cursor::new(data), size)
+        .await
+        .unwrap();
+    assert_eq!(mp4.moofs.len(), 3);
+    let sample_count = sync_mp4.sample_count(1).unwrap();
+    let sample = mp4.read_sample_async(1, sample_count).await.unwrap();
+    assert_eq!(sample, sync_mp4.read_sample(1, sample_count).unwrap());
+}
+
+#[tokio::test]
+async fn test_async_write() {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap()],
+        timescale: 1000,
+    };
+    let samples: Vec<Mp4Sample> = (0..100u8)
+        .map(|i| Mp4Sample {
+            start_time: i as u64 * 1024,
+            duration: 1024,
+            rendering_offset: 0,
+            is_sync: true,
+            flags: None,
+            sample_description_index: 1,
+            bytes: Bytes::from(vec![i; 10 + i as usize]),
+        })
+        .collect();
+
+    let mut writer = Mp4Writer::write_start_async(Cursor::new(Vec::new()), &config)
+        .await
+        .unwrap();
+    writer
+        .add_track(&TrackConfig::from(AacConfig::default()))
+        .unwrap();
+    for sample in samples.iter() {
+        writer.write_sample_async(1, sample).await.unwrap();
+    }
+    writer.write_end_async().await.unwrap();
+    let data = writer.into_writer().into_inner();
+
+    // Written the same way as with the sync writer.
+    let mut sync_writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    sync_writer
+        .add_track(&TrackConfig::from(AacConfig::default()))
+        .unwrap();
+    for sample in samples.iter() {
+        sync_writer.write_sample(1, sample).unwrap();
+    }
+    sync_writer.write_end().unwrap();
+    assert_eq!(data, sync_writer.into_writer().into_inner());
+
+    let size = data.len() as u64;
+    let mut mp4 = Mp4Reader::read_header_async(Cursor::new(data), size)
+        .await
+        .unwrap();
+    assert_eq!(mp4.sample_count(1).unwrap(), samples.len() as u32);
+    for (i, expected) in samples.iter().enumerate() {
+        let sample = mp4.read_sample_async(1, i as u32 + 1).await.unwrap();
+        assert_eq!(sample.as_ref(), Some(expected));
+    }
+}
@@ -1,9 +1,14 @@
 use mp4::{
-    AudioObjectType, AvcProfile, ChannelConfig, MediaType, Metadata, Mp4Reader, SampleFreqIndex,
-    TrackType,
+    AacConfig, AnyBox, AudioObjectType, Av1Config, AvcProfile, BoxHeader, BoxPath, BoxType, Bytes,
+    ChannelConfig, Error, FlacConfig, FlacMetadataBlock, FlacStreamInfo, HevcConfig,
+    HevcParameterSets, MediaType, Metadata, MoovBox, Mp4Box, Mp4Config, Mp4Event, Mp4Limits,
+    Mp4Parser, Mp4Reader, Mp4ReaderConfig, Mp4Sample, Mp4StreamReader, Mp4Warning, Mp4Writer,
+    OpusConfig, ReadBox, SampleFreqIndex, SampleOrder, SeekMode, Timeline, TrackConfig, TrackType,
+    WriteBox, HEADER_SIZE,
 };
+use std::convert::TryInto;
 use std::fs::{self, File};
-use std::io::BufReader;
+use std::io::{BufReader, Cursor};
 use std::time::Duration;
 
 #[test]
@@ -43,6 +48,8 @@ fn test_read_mp4() {
             duration: 512,
             rendering_offset: 0,
             is_sync: true,
+            flags: None,
+            sample_description_index: 1,
             bytes: mp4::Bytes::from(vec![0x0u8; 751]),
         }
     );
@@ -60,6 +67,8 @@ fn test_read_mp4() {
             duration: 1024,
             rendering_offset: 0,
             is_sync: true,
+            flags: None,
+            sample_description_index: 1,
             bytes: mp4::Bytes::from(vec![0x0u8; 179]),
         }
     );
@@ -72,6 +81,8 @@ fn test_read_mp4() {
             duration: 1024,
             rendering_offset: 0,
             is_sync: true,
+            flags: None,
+            sample_description_index: 1,
             bytes: mp4::Bytes::from(vec![0x0u8; 180]),
         }
     );
@@ -84,6 +95,8 @@ fn test_read_mp4() {
             duration: 896,
             rendering_offset: 0,
             is_sync: true,
+            flags: None,
+            sample_description_index: 1,
             bytes: mp4::Bytes::from(vec![0x0u8; 160]),
         }
     );
@@ -137,8 +150,7 @@ fn test_read_extended_audio_object_type() {
             .minf
             .stbl
             .stsd
-            .mp4a
-            .as_ref()
+            .mp4a()
             .unwrap()
             .esds
             .as_ref()
@@ -153,6 +165,178 @@ fn test_read_extended_audio_object_type() {
     assert_eq!(track.bitrate(), 839250);
 }
 
+#[test]
+fn test_write_hevc_parameter_sets() {
+    let vps = vec![0x40, 0x01, 0x0C, 0x01, 0xFF, 0xFF, 0x01, 0x60];
+    let sps = vec![
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x3C, 0xA0, 0x0A, 0x08, 0x0F, 0x17,
+    ];
+    let pps = vec![0x44, 0x01, 0xC1, 0x72, 0xB4, 0x62, 0x40];
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    let hevc_config = HevcConfig {
+        width: 320,
+        height: 240,
+        video_param_set: vps.clone(),
+        seq_param_set: sps.clone(),
+        pic_param_set: pps.clone(),
+        parameter_sets: HevcParameterSets::OutOfBand,
+    };
+    writer.add_track(&TrackConfig::from(hevc_config)).unwrap();
+    writer.write_end().unwrap();
+    let data = writer.into_writer().into_inner();
+
+    let size = data.len() as u64;
+    let mp4 = Mp4Reader::read_header(Cursor::new(data), size).unwrap();
+    let track = &mp4.tracks()[&1];
+    assert_eq!(track.media_type().unwrap(), MediaType::H265);
+    assert_eq!(track.video_parameter_set().unwrap(), vps);
+    assert_eq!(track.sequence_parameter_set().unwrap(), sps);
+    assert_eq!(track.picture_parameter_set().unwrap(), pps);
+
+    assert_eq!(track.box_type().unwrap(), str::parse("hvc1").unwrap());
+    let hvcc = &track.trak.mdia.minf.stbl.stsd.hvc1().unwrap().hvcc;
+    assert_eq!(hvcc.general_profile_idc, 1);
+    assert_eq!(hvcc.general_level_idc, 60);
+    assert_eq!(hvcc.length_size_minus_one, 3);
+}
+
+#[test]
+fn test_write_av1() {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap(), str::parse("av01").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    let av1_config = Av1Config {
+        width: 320,
+        height: 240,
+        seq_level_idx_0: 4,
+        bit_depth: 10,
+        config_obus: vec![
+            0x0A, 0x0B, 0x00, 0x00, 0x00, 0x24, 0xC4, 0xFF, 0xDF, 0x00, 0x68, 0x02,
+        ],
+        ..Default::default()
+    };
+    writer
+        .add_track(&TrackConfig::from(av1_config.clone()))
+        .unwrap();
+    writer.write_end().unwrap();
+    let data = writer.into_writer().into_inner();
+
+    let size = data.len() as u64;
+    let mp4 = Mp4Reader::read_header(Cursor::new(data), size).unwrap();
+    let track = &mp4.tracks()[&1];
+    assert_eq!(track.media_type().unwrap(), MediaType::AV1);
+    assert_eq!(track.box_type().unwrap(), str::parse("av01").unwrap());
+    assert_eq!(track.av1_config().unwrap(), av1_config);
+}
+
+#[test]
+fn test_write_opus_pre_skip() {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap(), str::parse("Opus").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    let opus_config = OpusConfig {
+        pre_skip: 312,
+        ..Default::default()
+    };
+    writer
+        .add_track(&TrackConfig::from(opus_config.clone()))
+        .unwrap();
+    for i in 0..50 {
+        let sample = Mp4Sample {
+            start_time: i * 960,
+            duration: 960,
+            rendering_offset: 0,
+            is_sync: true,
+            flags: None,
+            sample_description_index: 1,
+            bytes: mp4::Bytes::from(vec![0xFCu8; 3]),
+        };
+        writer.write_sample(1, &sample).unwrap();
+    }
+    writer.write_end().unwrap();
+    let data = writer.into_writer().into_inner();
+
+    let size = data.len() as u64;
+    let mp4 = Mp4Reader::read_header(Cursor::new(data), size).unwrap();
+    let track = &mp4.tracks()[&1];
+    assert_eq!(track.media_type().unwrap(), MediaType::OPUS);
+    assert_eq!(track.timescale(), 48000);
+    assert_eq!(track.opus_config().unwrap(), opus_config);
+    assert_eq!(track.pre_skip().unwrap(), 312);
+    assert_eq!(track.trimmed_start(), 312);
+    // The first sample is presented from the end of the pre-skip.
+    assert_eq!(track.sample_presentation_time(1).unwrap(), Some(0));
+    assert_eq!(track.sample_presentation_time(2).unwrap(), Some(960 - 312));
+}
+
+#[test]
+fn test_write_flac() {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    let flac_config = FlacConfig {
+        stream_info: FlacStreamInfo {
+            sample_rate: 48000,
+            bits_per_sample: 24,
+            total_samples: 8192,
+            md5: [0x5A; 16],
+            ..Default::default()
+        },
+        metadata_blocks: vec![FlacMetadataBlock {
+            block_type: 4,
+            data: vec![0; 8],
+        }],
+    };
+    writer
+        .add_track(&TrackConfig::from(flac_config.clone()))
+        .unwrap();
+    for i in 0..2 {
+        let sample = Mp4Sample {
+            start_time: i * 4096,
+            duration: 4096,
+            rendering_offset: 0,
+            is_sync: true,
+            flags: None,
+            sample_description_index: 1,
+            bytes: mp4::Bytes::from(vec![0xFF, 0xF8, 0x00, 0x00]),
+        };
+        writer.write_sample(1, &sample).unwrap();
+    }
+    writer.write_end().unwrap();
+    let data = writer.into_writer().into_inner();
+
+    let size = data.len() as u64;
+    let mut mp4 = Mp4Reader::read_header(Cursor::new(data), size).unwrap();
+    let track = &mp4.tracks()[&1];
+    assert_eq!(track.media_type().unwrap(), MediaType::FLAC);
+    assert_eq!(track.box_type().unwrap(), str::parse("fLaC").unwrap());
+    assert_eq!(track.timescale(), 48000);
+    assert_eq!(track.flac_config().unwrap(), flac_config);
+
+    let sample = mp4.read_sample(1, 2).unwrap().unwrap();
+    assert_eq!(sample.start_time, 4096);
+    assert_eq!(sample.bytes.as_ref(), [0xFF, 0xF8, 0x00, 0x00]);
+}
+
 fn get_reader(path: &str) -> Mp4Reader<BufReader<File>> {
     let f = File::open(path).unwrap();
     let f_size = f.metadata().unwrap().len();
@@ -176,3 +360,899 @@ fn test_read_metadata() {
     assert_eq!(poster.len(), want_poster.len());
     assert_eq!(poster, want_poster.as_slice());
 }
+
+#[test]
+fn test_unknown_boxes_kept() {
+    let mp4 = get_reader("tests/samples/big_buck_bunny_metadata.m4v");
+    let sgpd = BoxType::from(u32::from_be_bytes(*b"sgpd"));
+    let stbl = &mp4.moov.traks[1].mdia.minf.stbl;
+    assert!(stbl
+        .unknown_boxes
+        .iter()
+        .any(|unknown| unknown.name == sgpd));
+    assert!(mp4
+        .warnings()
+        .iter()
+        .all(|warning| warning.path.last() != Some(sgpd)));
+
+    // Written back as they were read.
+    let mut buf = Vec::new();
+    mp4.moov.write_box(&mut buf).unwrap();
+    assert_eq!(buf.len() as u64, mp4.moov.box_size());
+    let mut reader = Cursor::new(&buf);
+    let header = BoxHeader::read(&mut reader).unwrap();
+    let moov = MoovBox::read_box(&mut reader, header.size).unwrap();
+    assert_eq!(moov, mp4.moov);
+}
+
+#[test]
+fn test_read_fragmented() {
+    let mut mp4 = get_reader("tests/samples/fragmented.mp4");
+    assert!(mp4.is_fragmented());
+    assert_eq!(mp4.moofs.len(), 3);
+    assert_eq!(mp4.sample_count(1).unwrap(), 12);
+    assert_eq!(mp4.sample_count(2).unwrap(), 9);
+
+    // Second video sample of the last fragment.
+    let sample = mp4.read_sample(1, 10).unwrap().unwrap();
+    assert_eq!(sample.start_time, 2 * 2048 + 512);
+    assert_eq!(sample.duration, 512);
+    assert_eq!(sample.rendering_offset, -512);
+    assert!(!sample.is_sync);
+    assert_eq!(sample.bytes, vec![0x19; 29]);
+
+    // First audio sample of the second fragment.
+    let sample = mp4.read_sample(2, 4).unwrap().unwrap();
+    assert_eq!(sample.start_time, 3072);
+    assert_eq!(sample.duration, 1024);
+    assert!(sample.is_sync);
+    assert_eq!(sample.bytes, vec![0x83; 8]);
+}
+
+#[test]
+fn test_box_locations() {
+    let mut data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let file_size = data.len() as u64;
+    // A free box with a largesize at the end.
+    data.extend_from_slice(&[0, 0, 0, 1, b'f', b'r', b'e', b'e']);
+    data.extend_from_slice(&20u64.to_be_bytes());
+    data.extend_from_slice(&[0; 4]);
+
+    let size = data.len() as u64;
+    let mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    let locations = mp4.box_locations();
+    for location in locations {
+        let offset = location.offset as usize;
+        let name = BoxType::from(u32::from_be_bytes(
+            data[offset + 4..offset + 8].try_into().unwrap(),
+        ));
+        assert_eq!(location.name(), Some(name));
+        assert!(location.range().end <= size);
+
+        // Children are within the last box before them one level up.
+        let depth = location.path.0.len();
+        if depth > 1 {
+            let parent = locations
+                .iter()
+                .rev()
+                .find(|parent| parent.path.0.len() == depth - 1 && parent.offset < location.offset)
+                .unwrap();
+            assert_eq!(parent.path.0[..], location.path.0[..depth - 1]);
+            assert!(parent.payload_offset() <= location.offset);
+            assert!(location.range().end <= parent.range().end);
+        }
+    }
+
+    let top_level: Vec<_> = locations.iter().filter(|l| l.path.0.len() == 1).collect();
+    assert_eq!(top_level[0].offset, 0);
+    for pair in top_level.windows(2) {
+        assert_eq!(pair[0].range().end, pair[1].offset);
+    }
+    let free = top_level.last().unwrap();
+    assert_eq!(free.path.to_string(), "free");
+    assert_eq!(
+        (free.offset, free.header_size, free.size),
+        (file_size, 16, 20)
+    );
+    assert_eq!(free.payload_range(), file_size + 16..size);
+
+    let moov = mp4.moov_location().unwrap();
+    assert_eq!(moov.header_size, 8);
+    assert_eq!(moov.size, mp4.moov.box_size());
+    for path in [
+        "moov/trak/mdia/minf/stbl/stsd/avc1/avcC",
+        "moov/trak[2]/mdia/minf/stbl/stsd/mp4a/esds",
+    ] {
+        assert!(locations.iter().any(|l| l.path.to_string() == path));
+    }
+
+    let moofs: Vec<_> = mp4.moof_locations().collect();
+    let mdats: Vec<_> = mp4.mdat_locations().collect();
+    assert_eq!(moofs.len(), 3);
+    assert_eq!(mdats.len(), 3);
+    for ((moof_box, moof), mdat) in mp4.moofs.iter().zip(moofs).zip(mdats) {
+        assert_eq!(moof.size, moof_box.box_size());
+        assert_eq!(moof.range().end, mdat.offset);
+    }
+}
+
+#[test]
+fn test_walk_boxes() {
+    for path in [
+        "tests/samples/fragmented.mp4",
+        "tests/samples/big_buck_bunny_metadata.m4v",
+    ] {
+        let mp4 = get_reader(path);
+        let nodes: Vec<_> = mp4.walk_boxes().collect();
+
+        // Every box read is walked, in file order.
+        assert_eq!(nodes.len(), mp4.box_locations().len());
+        for (node, location) in nodes.iter().zip(mp4.box_locations()) {
+            assert_eq!(node.location, Some(location));
+            assert_eq!(node.path, location.path);
+            assert_eq!(node.depth(), location.path.0.len() - 1);
+        }
+    }
+
+    // Boxes written back as they were read have the same size.
+    let mp4 = get_reader("tests/samples/fragmented.mp4");
+    for node in mp4.walk_boxes() {
+        if !matches!(node.mp4box, AnyBox::Skipped(..)) {
+            assert_eq!(node.mp4box.box_size(), node.size());
+        }
+    }
+    let names: Vec<_> = mp4
+        .walk_boxes()
+        .filter(|node| node.depth() == 0)
+        .map(|node| node.mp4box.box_type().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        ["ftyp", "moov", "moof", "mdat", "moof", "mdat", "moof", "mdat"]
+    );
+}
+
+#[test]
+fn test_stream_fragmented() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let mut mp4 = get_reader("tests/samples/fragmented.mp4");
+
+    // A plain `&[u8]` is not seekable.
+    let mut stream = Mp4StreamReader::read_header(data.as_slice()).unwrap();
+    assert_eq!(stream.ftyp, mp4.ftyp);
+    assert_eq!(stream.moov, mp4.moov);
+    assert_eq!(stream.tracks().len(), 2);
+
+    let mut next_sample_ids = [1, 1];
+    let mut fragment_count = 0;
+    while let Some(fragment) = stream.next_fragment().unwrap() {
+        assert_eq!(fragment.moof, mp4.moofs[fragment_count]);
+        assert_eq!(fragment.samples.len(), 7);
+        for (track_id, sample) in fragment.samples {
+            let sample_id = &mut next_sample_ids[track_id as usize - 1];
+            let expected = mp4.read_sample(track_id, *sample_id).unwrap().unwrap();
+            assert_eq!(sample, expected);
+            *sample_id += 1;
+        }
+        fragment_count += 1;
+    }
+    assert_eq!(fragment_count, 3);
+    assert_eq!(next_sample_ids, [13, 10]);
+}
+
+#[test]
+fn test_stream_truncated_fragment() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let stream = Mp4StreamReader::read_header(&data[..data.len() - 1]).unwrap();
+    let fragments: Vec<_> = stream.collect();
+    assert_eq!(fragments.len(), 3);
+    assert!(fragments[..2].iter().all(|fragment| fragment.is_ok()));
+    assert!(fragments[2].is_err());
+}
+
+#[test]
+fn test_stream_skipped_boxes() {
+    let mut data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let unknown_offset = data.len() as u64;
+    data.extend_from_slice(&[0, 0, 0, 8, b'a', b'b', b'c', b'd']);
+    data.extend_from_slice(&[0, 0, 0, 8, b'f', b'r', b'e', b'e']);
+    data.extend_from_slice(&[0, 0, 0, 8, b'm', b'd', b'a', b't']);
+
+    let mut stream = Mp4StreamReader::read_header(data.as_slice()).unwrap();
+    while stream.next_fragment().unwrap().is_some() {}
+    let warnings: Vec<_> = stream
+        .warnings()
+        .iter()
+        .map(|warning| (warning.path.to_string(), warning.offset))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            ("abcd".to_string(), unknown_offset),
+            ("mdat[4]".to_string(), unknown_offset + 16),
+        ]
+    );
+    assert_eq!(stream.warnings()[1].message, "mdat without moof skipped");
+}
+
+#[test]
+fn test_stream_hostile_headers() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let ftyp_size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+    let moov_size = u32::from_be_bytes(data[ftyp_size..ftyp_size + 4].try_into().unwrap());
+    let config = Mp4ReaderConfig {
+        limits: Mp4Limits {
+            max_box_size: moov_size as u64,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // A moov whose largesize is over the limit fails before it is read.
+    let mut hostile = data[..ftyp_size].to_vec();
+    hostile.extend_from_slice(&[0, 0, 0, 1, b'm', b'o', b'o', b'v']);
+    hostile.extend_from_slice(&(1u64 << 40).to_be_bytes());
+    let err = Mp4StreamReader::read_header_with_config(hostile.as_slice(), &config).unwrap_err();
+    assert!(matches!(err.root(), Error::LimitExceeded("box size", ..)));
+
+    // A box of size 0 is read up to the limit only.
+    let mut hostile = data.clone();
+    hostile.extend_from_slice(&[0, 0, 0, 0, b'e', b'm', b's', b'g']);
+    hostile.resize(hostile.len() + moov_size as usize, 0);
+    let mut stream = Mp4StreamReader::read_header_with_config(hostile.as_slice(), &config).unwrap();
+    for _ in 0..3 {
+        stream.next_fragment().unwrap().unwrap();
+    }
+    let err = stream.next_fragment().unwrap_err();
+    assert!(
+        matches!(err.root(), Error::LimitExceeded("box size", _, max) if *max == moov_size as u64)
+    );
+
+    // Or is skipped in lenient mode.
+    let config = Mp4ReaderConfig {
+        lenient: true,
+        ..config
+    };
+    let mut stream = Mp4StreamReader::read_header_with_config(hostile.as_slice(), &config).unwrap();
+    for _ in 0..3 {
+        stream.next_fragment().unwrap().unwrap();
+    }
+    assert!(stream.next_fragment().unwrap().is_none());
+    let warning = &stream.warnings()[0];
+    assert_eq!(warning.path.to_string(), "emsg");
+    assert_eq!(warning.offset, data.len() as u64);
+}
+
+#[test]
+fn test_push_parser_chunks() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let mut mp4 = get_reader("tests/samples/fragmented.mp4");
+
+    for chunk_size in [1, 7, 100, data.len()] {
+        let mut parser = Mp4Parser::new();
+        let mut events = Vec::new();
+        for chunk in data.chunks(chunk_size) {
+            events.extend(parser.push(chunk).unwrap());
+        }
+        parser.finish().unwrap();
+        assert_eq!(parser.offset(), data.len() as u64);
+
+        let mut boxes = Vec::new();
+        let mut moofs = Vec::new();
+        let mut mdats = Vec::new();
+        let mut next_sample_ids = [1, 1];
+        for event in events {
+            match event {
+                Mp4Event::BoxHeader { name, offset, size } => {
+                    assert!(offset + size <= data.len() as u64);
+                    boxes.push(name);
+                }
+                Mp4Event::Ftyp(ftyp) => assert_eq!(ftyp, mp4.ftyp),
+                Mp4Event::Moov(moov) => assert_eq!(*moov, mp4.moov),
+                Mp4Event::Moof(moof) => moofs.push(moof),
+                Mp4Event::Mdat { offset, size } => mdats.push((offset, size)),
+                Mp4Event::Sample { track_id, sample } => {
+                    let sample_id = &mut next_sample_ids[track_id as usize - 1];
+                    let expected = mp4.read_sample(track_id, *sample_id).unwrap().unwrap();
+                    assert_eq!(sample, expected);
+                    *sample_id += 1;
+                }
+                Mp4Event::Emsg(_) => panic!("unexpected emsg"),
+            }
+        }
+        assert_eq!(boxes.len(), 8);
+        assert_eq!(moofs, mp4.moofs);
+        assert_eq!(mdats.len(), 3);
+        assert_eq!(next_sample_ids, [13, 10]);
+    }
+}
+
+#[test]
+fn test_push_parser_incomplete() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let mut parser = Mp4Parser::new();
+    parser.push(&data[..data.len() - 1]).unwrap();
+    assert!(parser.finish().is_err());
+}
+
+#[test]
+fn test_push_parser_hostile_headers() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+
+    // A largesize whose end overflows.
+    let mut parser = Mp4Parser::new();
+    parser.push(&data).unwrap();
+    let mut header = vec![0, 0, 0, 1, b'f', b'r', b'e', b'e'];
+    header.extend_from_slice(&u64::MAX.to_be_bytes());
+    let err = parser.push(&header).unwrap_err();
+    assert!(matches!(
+        err.root(),
+        Error::InvalidData("box size too large")
+    ));
+
+    // A moov over the size limit fails once its header is pushed, before it is
+    // buffered.
+    let ftyp_size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+    let moov_size = u32::from_be_bytes(data[ftyp_size..ftyp_size + 4].try_into().unwrap());
+    let config = Mp4ReaderConfig {
+        limits: Mp4Limits {
+            max_box_size: moov_size as u64 - 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut parser = Mp4Parser::with_config(&config);
+    let err = parser.push(&data[..ftyp_size + 8]).unwrap_err();
+    assert!(
+        matches!(err.root(), Error::LimitExceeded("box size", size, _) if *size == moov_size as u64)
+    );
+
+    // Or is skipped in lenient mode.
+    let config = Mp4ReaderConfig {
+        lenient: true,
+        ..config
+    };
+    let mut parser = Mp4Parser::with_config(&config);
+    let events = parser.push(&data).unwrap();
+    parser.finish().unwrap();
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Mp4Event::Moov(_))));
+    let warning = &parser.warnings()[0];
+    assert_eq!(warning.path.to_string(), "moov");
+    assert_eq!(warning.offset, ftyp_size as u64);
+}
+
+#[test]
+fn test_push_parser_size_zero_box() {
+    let mut data = write_tracks(1);
+    let size = data.len() as u64;
+    let mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+
+    // A moov of size 0 at the end of the data is parsed by `finish`.
+    let moov = find_box(&data, b"moov", 0);
+    data[moov..moov + 4].copy_from_slice(&[0; 4]);
+    let mut parser = Mp4Parser::new();
+    for chunk in data.chunks(7) {
+        let events = parser.push(chunk).unwrap();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Mp4Event::Moov(_))));
+    }
+    let events = parser.finish().unwrap();
+    assert!(matches!(&events[..], [Mp4Event::Moov(moov)] if **moov == mp4.moov));
+
+    // Over the size limit, it fails rather than being dropped.
+    let config = Mp4ReaderConfig {
+        limits: Mp4Limits {
+            max_box_size: (data.len() - moov - 1) as u64,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut parser = Mp4Parser::with_config(&config);
+    let err = parser.push(&data).unwrap_err();
+    assert!(matches!(err.root(), Error::LimitExceeded("box size", ..)));
+}
+
+#[test]
+fn test_read_size_zero_mdat() {
+    let mut data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let mut mp4 = get_reader("tests/samples/fragmented.mp4");
+
+    // Give the last mdat a size of 0, extending it to the end of the file.
+    let mut offset = 0;
+    let mut last_mdat = None;
+    while offset < data.len() {
+        if &data[offset + 4..offset + 8] == b"mdat" {
+            last_mdat = Some(offset);
+        }
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        offset += size as usize;
+    }
+    let last_mdat = last_mdat.unwrap();
+    data[last_mdat..last_mdat + 4].copy_from_slice(&[0; 4]);
+
+    let size = data.len() as u64;
+    let mut zero_mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    assert_eq!(zero_mp4.moofs, mp4.moofs);
+    for track_id in [1, 2] {
+        let sample_count = mp4.sample_count(track_id).unwrap();
+        assert_eq!(zero_mp4.sample_count(track_id).unwrap(), sample_count);
+        for sample_id in 1..=sample_count {
+            let sample = zero_mp4.read_sample(track_id, sample_id).unwrap();
+            let expected = mp4.read_sample(track_id, sample_id).unwrap();
+            assert_eq!(sample, expected);
+        }
+    }
+
+    let stream = Mp4StreamReader::read_header(data.as_slice()).unwrap();
+    let fragments: Vec<_> = stream.map(|fragment| fragment.unwrap()).collect();
+    assert_eq!(fragments.len(), 3);
+    assert_eq!(fragments[2].samples.len(), 7);
+
+    let mut parser = Mp4Parser::new();
+    let mut sample_count = 0;
+    for chunk in data.chunks(7) {
+        for event in parser.push(chunk).unwrap() {
+            if let Mp4Event::Sample { .. } = event {
+                sample_count += 1;
+            }
+        }
+    }
+    parser.finish().unwrap();
+    assert_eq!(sample_count, 21);
+}
+
+#[test]
+fn test_read_truncated() {
+    let data = fs::read("tests/samples/fragmented.mp4").unwrap();
+    let mut mp4 = get_reader("tests/samples/fragmented.mp4");
+    assert_eq!(mp4.available_sample_count(1).unwrap(), 12);
+
+    // Cut the 3 audio samples and the end of the last video sample.
+    let size = data.len() as u64 - 30;
+    let cursor = Cursor::new(data[..size as usize].to_vec());
+    let mut truncated = Mp4Reader::read_header(cursor, size).unwrap();
+    assert_eq!(truncated.sample_count(1).unwrap(), 12);
+    assert_eq!(truncated.available_sample_count(1).unwrap(), 11);
+    assert_eq!(truncated.sample_count(2).unwrap(), 9);
+    assert_eq!(truncated.available_sample_count(2).unwrap(), 6);
+
+    let sample = truncated.read_sample(1, 11).unwrap();
+    assert_eq!(sample, mp4.read_sample(1, 11).unwrap());
+    assert!(matches!(
+        truncated.read_sample(1, 12),
+        Err(Error::TruncatedSample(1, 12))
+    ));
+
+    let samples: Vec<_> = truncated.samples(1).unwrap().collect();
+    assert_eq!(samples.len(), 12);
+    assert!(samples[..11].iter().all(|sample| sample.is_ok()));
+    assert!(matches!(samples[11], Err(Error::TruncatedSample(1, 12))));
+}
+
+#[test]
+fn test_sample_index() {
+    for path in [
+        "tests/samples/minimal.mp4",
+        "tests/samples/big_buck_bunny_metadata.m4v",
+        "tests/samples/fragmented.mp4",
+    ] {
+        let mut mp4 = get_reader(path);
+        let mut track_ids: Vec<u32> = mp4.tracks().keys().copied().collect();
+        track_ids.sort();
+
+        let mut samples = Vec::new();
+        for &track_id in track_ids.iter() {
+            for sample_id in 1..=mp4.sample_count(track_id).unwrap() {
+                samples.push(mp4.read_sample(track_id, sample_id).unwrap().unwrap());
+            }
+        }
+
+        mp4.build_sample_index().unwrap();
+        assert!(mp4.tracks().values().all(|track| track.has_sample_index()));
+        let mut indexed_samples = Vec::new();
+        for &track_id in track_ids.iter() {
+            let sample_count = mp4.sample_count(track_id).unwrap();
+            for sample_id in 1..=sample_count {
+                indexed_samples.push(mp4.read_sample(track_id, sample_id).unwrap().unwrap());
+            }
+        }
+        assert_eq!(indexed_samples, samples, "{}", path);
+    }
+}
+
+#[test]
+fn test_samples_iter() {
+    for path in [
+        "tests/samples/big_buck_bunny_metadata.m4v",
+        "tests/samples/fragmented.mp4",
+    ] {
+        let mut mp4 = get_reader(path);
+        let mut track_ids: Vec<u32> = mp4.tracks().keys().copied().collect();
+        track_ids.sort();
+
+        let mut all_samples = Vec::new();
+        for &track_id in track_ids.iter() {
+            let mut expected = Vec::new();
+            for sample_id in 1..=mp4.sample_count(track_id).unwrap() {
+                expected.push(mp4.read_sample(track_id, sample_id).unwrap().unwrap());
+            }
+            let samples = mp4.samples(track_id).unwrap();
+            assert_eq!(samples.size_hint().0, expected.len());
+            let samples: Vec<Mp4Sample> = samples.map(|sample| sample.unwrap()).collect();
+            assert_eq!(samples, expected, "{} track {}", path, track_id);
+
+            for sample in samples {
+                all_samples.push((track_id, sample));
+            }
+        }
+        assert!(mp4.samples(3).is_err());
+
+        // File offset order.
+        let interleaved: Vec<(u32, Mp4Sample)> = mp4
+            .interleaved_samples(SampleOrder::FileOffset)
+            .unwrap()
+            .map(|sample| sample.unwrap())
+            .collect();
+        assert_eq!(interleaved.len(), all_samples.len());
+        for (track_id, sample) in interleaved.iter() {
+            assert!(all_samples
+                .iter()
+                .any(|(id, s)| id == track_id && s == sample));
+        }
+
+        // Decode time order.
+        let timescales: Vec<(u32, f64)> = mp4
+            .tracks()
+            .values()
+            .map(|track| (track.track_id(), track.timescale() as f64))
+            .collect();
+        let mut last_time = 0.0;
+        let mut count = 0;
+        for sample in mp4.interleaved_samples(SampleOrder::DecodeTime).unwrap() {
+            let (track_id, sample) = sample.unwrap();
+            let (_, timescale) = timescales.iter().find(|(id, _)| *id == track_id).unwrap();
+            let time = sample.start_time as f64 / timescale;
+            assert!(time >= last_time);
+            last_time = time;
+            count += 1;
+        }
+        assert_eq!(count, all_samples.len());
+    }
+}
+
+#[test]
+fn test_sample_at_time() {
+    let mp4 = get_reader("tests/samples/fragmented.mp4");
+    let track = &mp4.tracks()[&1];
+    let exact = |time, timeline| {
+        track
+            .sample_at_time(time, timeline, SeekMode::Exact)
+            .unwrap()
+    };
+
+    // Decode times are 512 apart, 4 samples per fragment.
+    assert_eq!(exact(0, Timeline::Decode), Some(1));
+    assert_eq!(exact(600, Timeline::Decode), Some(2));
+    assert_eq!(exact(2048, Timeline::Decode), Some(5));
+    assert_eq!(exact(6143, Timeline::Decode), Some(12));
+    assert_eq!(exact(6144, Timeline::Decode), None);
+
+    // Presentation times of a fragment are 1024, 0, 1024 and 2048 after its
+    // decode time, and the last sample ends at 6656.
+    assert_eq!(exact(0, Timeline::Presentation), Some(2));
+    assert_eq!(exact(1100, Timeline::Presentation), Some(3));
+    assert_eq!(exact(2100, Timeline::Presentation), Some(6));
+    assert_eq!(exact(6655, Timeline::Presentation), Some(12));
+    assert_eq!(exact(6656, Timeline::Presentation), None);
+
+    // The first sample of each fragment is the only sync sample.
+    let seek = |time, mode| track.sample_at_time(time, Timeline::Decode, mode).unwrap();
+    assert_eq!(seek(600, SeekMode::PreviousSync), Some(1));
+    assert_eq!(seek(600, SeekMode::NextSync), Some(5));
+    assert_eq!(seek(2048, SeekMode::NextSync), Some(5));
+    assert_eq!(seek(4700, SeekMode::PreviousSync), Some(9));
+    assert_eq!(seek(4700, SeekMode::NextSync), None);
+    assert!(track
+        .sample_at_time(u64::MAX, Timeline::Decode, SeekMode::Exact)
+        .is_err());
+
+    let time = Duration::from_millis(160);
+    let sample_id = track.sample_at_duration(time, Timeline::Decode, SeekMode::Exact);
+    assert_eq!(sample_id.unwrap(), Some(5));
+}
+
+#[test]
+fn test_sample_at_time_matches_samples() {
+    for path in [
+        "tests/samples/big_buck_bunny_metadata.m4v",
+        "tests/samples/fragmented.mp4",
+    ] {
+        let mut mp4 = get_reader(path);
+        let track_ids: Vec<u32> = mp4.tracks().keys().copied().collect();
+        for track_id in track_ids {
+            let samples: Vec<Mp4Sample> = mp4
+                .samples(track_id)
+                .unwrap()
+                .map(|sample| sample.unwrap())
+                .collect();
+            let track = &mp4.tracks()[&track_id];
+
+            let times = |timeline| {
+                samples.iter().map(move |sample| match timeline {
+                    Timeline::Decode => sample.start_time as i64,
+                    Timeline::Presentation => {
+                        sample.start_time as i64 + sample.rendering_offset as i64
+                    }
+                })
+            };
+            for timeline in [Timeline::Decode, Timeline::Presentation] {
+                let end = times(timeline)
+                    .zip(samples.iter())
+                    .map(|(time, sample)| time + sample.duration as i64)
+                    .max()
+                    .unwrap();
+                for time in (0..end as u64 + 100).step_by(97) {
+                    // Last sample starting at or before the time.
+                    let exact = times(timeline)
+                        .enumerate()
+                        .filter(|&(_, start)| start <= time as i64)
+                        .max_by_key(|&(i, start)| (start, i))
+                        .map_or(1, |(i, _)| i as u32 + 1);
+                    let exact = Some(exact).filter(|_| (time as i64) < end);
+                    let is_sync = |id: &u32| samples[*id as usize - 1].is_sync;
+                    let previous_sync = exact.and_then(|id| (1..=id).rev().find(is_sync));
+                    let next_sync = exact.and_then(|id| (id..=samples.len() as u32).find(is_sync));
+
+                    for (mode, expected) in [
+                        (SeekMode::Exact, exact),
+                        (SeekMode::PreviousSync, previous_sync),
+                        (SeekMode::NextSync, next_sync),
+                    ] {
+                        let sample_id = track.sample_at_time(time, timeline, mode).unwrap();
+                        assert_eq!(
+                            sample_id, expected,
+                            "{} track {} {:?} {:?} at {}",
+                            path, track_id, timeline, mode, time
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn write_tracks(track_count: u32) -> Vec<u8> {
+    let config = Mp4Config {
+        major_brand: str::parse("isom").unwrap(),
+        minor_version: 512,
+        compatible_brands: vec![str::parse("isom").unwrap()],
+        timescale: 1000,
+    };
+    let mut writer = Mp4Writer::write_start(Cursor::new(Vec::new()), &config).unwrap();
+    for track_id in 1..=track_count {
+        writer
+            .add_track(&TrackConfig::from(AacConfig::default()))
+            .unwrap();
+        for i in 0..4 {
+            let sample = Mp4Sample {
+                start_time: i * 1024,
+                duration: 1024,
+                rendering_offset: 0,
+                is_sync: true,
+                flags: None,
+                sample_description_index: 1,
+                bytes: Bytes::from_static(&[0; 4]),
+            };
+            writer.write_sample(track_id, &sample).unwrap();
+        }
+    }
+    writer.write_end().unwrap();
+    writer.into_writer().into_inner()
+}
+
+fn find_box(data: &[u8], name: &[u8; 4], n: usize) -> usize {
+    let mut positions = data.windows(4).enumerate().filter(|(_, w)| w == name);
+    positions.nth(n).unwrap().0 - 4
+}
+
+fn read_lenient(data: Vec<u8>) -> mp4::Result<Mp4Reader<Cursor<Vec<u8>>>> {
+    let size = data.len() as u64;
+    let config = Mp4ReaderConfig {
+        lenient: true,
+        ..Default::default()
+    };
+    Mp4Reader::read_header_with_config(Cursor::new(data), size, &config)
+}
+
+#[test]
+fn test_lenient_bad_box_version() {
+    let mut data = write_tracks(2);
+    let tkhd = find_box(&data, b"tkhd", 1);
+    data[tkhd + 8] = 2;
+
+    // The error comes with the path of its box.
+    let size = data.len() as u64;
+    let err = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap_err();
+    assert!(matches!(
+        err.root(),
+        Error::InvalidData("version must be 0 or 1")
+    ));
+    assert_eq!(
+        err.to_string(),
+        format!("moov/trak[2]/tkhd at offset {tkhd}: version must be 0 or 1")
+    );
+
+    // Boxes read on their own give the path from the box.
+    let mut reader = Cursor::new(data.clone());
+    reader.set_position(find_box(&data, b"moov", 0) as u64 + HEADER_SIZE);
+    let err = MoovBox::read_box(&mut reader, size).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("trak[2]/tkhd at offset {tkhd}: version must be 0 or 1")
+    );
+
+    let mp4 = read_lenient(data.clone()).unwrap();
+    assert_eq!(mp4.tracks().keys().collect::<Vec<_>>(), [&1]);
+    let trak = find_box(&data, b"trak", 1) as u64;
+    assert_eq!(
+        mp4.warnings(),
+        [
+            Mp4Warning {
+                path: BoxPath(vec![
+                    (BoxType::MoovBox, 1),
+                    (BoxType::TrakBox, 2),
+                    (BoxType::TkhdBox, 1),
+                ]),
+                offset: tkhd as u64,
+                message: "version must be 0 or 1".to_string(),
+            },
+            Mp4Warning {
+                path: BoxPath(vec![(BoxType::MoovBox, 1), (BoxType::TrakBox, 2)]),
+                offset: trak,
+                message: "tkhd not found".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_lenient_locations() {
+    let mut data = write_tracks(3);
+    let tkhd = find_box(&data, b"tkhd", 1);
+    data[tkhd + 8] = 2;
+
+    let mp4 = read_lenient(data.clone()).unwrap();
+    let mut track_ids: Vec<_> = mp4.tracks().keys().copied().collect();
+    track_ids.sort_unstable();
+    assert_eq!(track_ids, [1, 3]);
+
+    // The skipped trak isn't located, the third one comes second.
+    let locations = mp4.box_locations();
+    assert!(locations
+        .iter()
+        .all(|location| location.offset != tkhd as u64));
+    let nodes: Vec<_> = mp4.walk_boxes().collect();
+    assert_eq!(nodes.len(), locations.len());
+    assert!(nodes.iter().all(|node| node.location.is_some()));
+    let trak = nodes
+        .iter()
+        .find(|node| node.path.to_string() == "moov/trak[2]")
+        .unwrap();
+    assert!(matches!(trak.mp4box, AnyBox::Trak(trak) if trak.tkhd.track_id == 3));
+    assert_eq!(trak.offset(), Some(find_box(&data, b"trak", 2) as u64));
+}
+
+#[test]
+fn test_lenient_sample_counts() {
+    let mut data = write_tracks(2);
+    // Only 3 of the 4 samples of the first track have a duration.
+    let stts = find_box(&data, b"stts", 0);
+    data[stts + 16..stts + 20].copy_from_slice(&3u32.to_be_bytes());
+
+    let size = data.len() as u64;
+    let mut mp4 = Mp4Reader::read_header(Cursor::new(data.clone()), size).unwrap();
+    assert_eq!(mp4.sample_count(1).unwrap(), 4);
+    let err = mp4.read_sample(1, 4).unwrap_err();
+    assert!(matches!(err, Error::Malformed(_)));
+    assert_eq!(
+        err.to_string(),
+        "trak[1].stbl.stts has no entry for sample 4 of the 4 in stsz"
+    );
+    assert!(mp4.warnings().is_empty());
+
+    let mut mp4 = read_lenient(data.clone()).unwrap();
+    assert_eq!(mp4.sample_count(1).unwrap(), 3);
+    assert_eq!(mp4.sample_count(2).unwrap(), 4);
+    assert!(mp4.read_sample(1, 3).unwrap().is_some());
+
+    let stbl = find_box(&data, b"stbl", 0) as u64;
+    let warning = &mp4.warnings()[0];
+    assert_eq!(mp4.warnings().len(), 1);
+    assert_eq!(warning.path.last(), Some(BoxType::StblBox));
+    assert_eq!(warning.offset, stbl);
+    assert_eq!(
+        warning.to_string(),
+        format!(
+            "moov/trak/mdia/minf/stbl at offset {stbl}: 4 samples in stsz, 3 in stts and 4 \
+             in chunks, cut to 3"
+        )
+    );
+}
+
+fn read_with_limits(data: Vec<u8>, limits: Mp4Limits) -> mp4::Result<Mp4Reader<Cursor<Vec<u8>>>> {
+    let size = data.len() as u64;
+    let config = Mp4ReaderConfig {
+        limits,
+        ..Default::default()
+    };
+    Mp4Reader::read_header_with_config(Cursor::new(data), size, &config)
+}
+
+#[test]
+fn test_limits() {
+    let data = write_tracks(2);
+    let moov_size = data.len() as u64 - find_box(&data, b"moov", 0) as u64;
+
+    let limits = Mp4Limits {
+        max_box_size: moov_size - 1,
+        ..Default::default()
+    };
+    let err = read_with_limits(data.clone(), limits).unwrap_err();
+    assert!(matches!(err.root(), Error::LimitExceeded("box size", size, _) if *size == moov_size));
+
+    let limits = Mp4Limits {
+        max_depth: 3,
+        ..Default::default()
+    };
+    let err = read_with_limits(data.clone(), limits).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "moov/trak/mdia/mdhd at offset {}: box depth 4 over the limit of 3, see \
+             Mp4Limits",
+            find_box(&data, b"mdhd", 0)
+        )
+    );
+
+    let limits = Mp4Limits {
+        max_table_entries: 3,
+        ..Default::default()
+    };
+    let err = read_with_limits(data.clone(), limits).unwrap_err();
+    assert!(matches!(
+        err.root(),
+        Error::LimitExceeded("table entry count", 4, 3)
+    ));
+
+    let limits = Mp4Limits {
+        max_sample_size: 3,
+        ..Default::default()
+    };
+    let mut mp4 = read_with_limits(data, limits).unwrap();
+    let err = mp4.read_sample(1, 1).unwrap_err();
+    assert!(matches!(
+        err.root(),
+        Error::LimitExceeded("sample size", 4, 3)
+    ));
+}
+
+#[test]
+fn test_entry_count_past_box() {
+    let mut data = write_tracks(2);
+    let stco = find_box(&data, b"stco", 0);
+    data[stco + 12..stco + 16].copy_from_slice(&1000u32.to_be_bytes());
+
+    let size = data.len() as u64;
+    let err = Mp4Reader::read_header(Cursor::new(data), size).unwrap_err();
+    assert!(matches!(err.root(), Error::Malformed(_)));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "moov/trak/mdia/minf/stbl/stco at offset {stco}: 1000 entries past the end of \
+             the box, room for 4"
+        )
+    );
+}